@@ -0,0 +1,134 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Query write benchmark
+//!
+//! Compares writing every query frame with a single `write` call (which is what connections
+//! do) against writing the metaframe and the dataframe separately. Every `write` call on an
+//! unbuffered socket is a syscall, so the number of calls per query is reported along with
+//! the time taken. The last row runs the queries on a [`Connection`] over an in-memory stream,
+//! so its time also includes reading the responses. Run it with:
+//! ```text
+//! cargo bench --bench write_query
+//! ```
+//!
+
+use skytable::{query, Connection, Query};
+use std::cell::Cell;
+use std::io::{Read, Result as IoResult, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const QUERIES: usize = 200_000;
+const RESPONSE: &[u8] = b"*!0\n";
+
+/// The `write` calls (each of which would be a syscall on a socket) and bytes written to a
+/// [`CountingStream`]
+#[derive(Default)]
+struct Counts {
+    writes: Cell<usize>,
+    written: Cell<usize>,
+}
+
+/// An in-memory stream that counts the writes made on it and responds to every query with
+/// `Okay`
+#[derive(Default)]
+struct CountingStream {
+    counts: Rc<Counts>,
+    pending: usize,
+}
+
+impl Write for CountingStream {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.counts.writes.set(self.counts.writes.get() + 1);
+        self.counts
+            .written
+            .set(self.counts.written.get() + buf.len());
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        // every flush ends a query, which the server responds to
+        self.pending += 1;
+        Ok(())
+    }
+}
+
+impl Read for CountingStream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let mut read = 0;
+        while self.pending != 0 && buf.len() - read >= RESPONSE.len() {
+            buf[read..read + RESPONSE.len()].copy_from_slice(RESPONSE);
+            read += RESPONSE.len();
+            self.pending -= 1;
+        }
+        Ok(read)
+    }
+}
+
+fn report(name: &str, elapsed: Duration, counts: &Counts) {
+    println!(
+        "{:<12} {:>10.1?} {:>8.1} ns/query {:>5.1} writes/query {:>5.1} bytes/query",
+        name,
+        elapsed,
+        elapsed.as_nanos() as f64 / QUERIES as f64,
+        counts.writes.get() as f64 / QUERIES as f64,
+        counts.written.get() as f64 / QUERIES as f64,
+    );
+}
+
+/// Write the whole frame with a single call
+fn write_single(query: &Query, stream: &mut CountingStream) -> IoResult<()> {
+    stream.write_all(&query.as_bytes())?;
+    stream.flush()
+}
+
+/// Write the metaframe (`*<n>\n`) and the dataframe with separate calls
+fn write_split(query: &Query, stream: &mut CountingStream) -> IoResult<()> {
+    let frame = query.as_bytes();
+    let count = query.len().to_string();
+    stream.write_all(b"*")?;
+    stream.write_all(count.as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.write_all(&frame[2 + count.len()..])?;
+    stream.flush()
+}
+
+fn main() {
+    let query = query!("set", "somekey", "somevalue");
+
+    let mut stream = CountingStream::default();
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        write_split(&query, &mut stream).unwrap();
+    }
+    report("split", start.elapsed(), &stream.counts);
+
+    let mut stream = CountingStream::default();
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        write_single(&query, &mut stream).unwrap();
+    }
+    report("single", start.elapsed(), &stream.counts);
+
+    let stream = CountingStream::default();
+    let counts = stream.counts.clone();
+    let mut con = Connection::with_stream(stream);
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        con.run_query_raw(&query).unwrap();
+    }
+    report("connection", start.elapsed(), &counts);
+}
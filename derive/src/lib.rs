@@ -0,0 +1,153 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Skytable derive macros
+//!
+//! This crate provides derive macros for the [Skytable client driver](https://docs.rs/skytable).
+//! You shouldn't depend on this crate directly; instead enable the `derive` feature of the
+//! `skytable` crate.
+//!
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields};
+
+/// Derives `FromSkyhashBytes` for a struct by reading its fields positionally from an
+/// array element. See the documentation for `skytable::FromSkyhash` for more information
+#[proc_macro_derive(FromSkyhash)]
+pub fn derive_from_skyhash(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derives `ToSkyhashMap` for a struct with named fields, mapping every field name to the
+/// bytes of its value. See the documentation for `skytable::ToSkyhashMap` for more information
+#[proc_macro_derive(ToSkyhashMap)]
+pub fn derive_to_skyhash_map(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_to_map(input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let name_str = name.to_string();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match &input.data {
+        Data::Struct(st) => &st.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`FromSkyhash` can only be derived for structs",
+            ))
+        }
+    };
+    let field_count = fields.len();
+    // parse every field from the next element, naming the field in case of errors
+    let parse_field = |field_str: String| {
+        quote! {
+            ::skytable::types::FromSkyhashBytes::from_element(elements.next().unwrap()).map_err(
+                |e| ::skytable::error::Error::ParseError(format!(
+                    "failed to parse field `{}` of `{}`: {}",
+                    #field_str, #name_str, e
+                ))
+            )?
+        }
+    };
+    let construct = match fields {
+        Fields::Named(named) => {
+            let fields = named.named.iter().map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                let parse = parse_field(ident.to_string());
+                quote! { #ident: #parse }
+            });
+            quote! { Self { #(#fields),* } }
+        }
+        Fields::Unnamed(unnamed) => {
+            let fields = (0..unnamed.unnamed.len()).map(|idx| parse_field(idx.to_string()));
+            quote! { Self(#(#fields),*) }
+        }
+        Fields::Unit => quote! { Self },
+    };
+    Ok(quote! {
+        impl #impl_generics ::skytable::types::FromSkyhashBytes for #name #ty_generics #where_clause {
+            fn from_element(element: ::skytable::Element) -> ::skytable::SkyResult<Self> {
+                let elements = match element {
+                    ::skytable::Element::Array(array) => array.into_elements()?,
+                    _ => {
+                        return Err(::skytable::error::Error::ParseError(format!(
+                            "expected an array to parse into `{}`",
+                            #name_str
+                        )))
+                    }
+                };
+                if elements.len() != #field_count {
+                    return Err(::skytable::error::Error::ParseError(format!(
+                        "expected {} elements to parse into `{}` but found {}",
+                        #field_count,
+                        #name_str,
+                        elements.len()
+                    )));
+                }
+                #[allow(unused_mut, unused_variables)]
+                let mut elements = elements.into_iter();
+                Ok(#construct)
+            }
+        }
+    })
+}
+
+fn expand_to_map(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match &input.data {
+        Data::Struct(st) => match &st.fields {
+            Fields::Named(named) => &named.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "`ToSkyhashMap` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`ToSkyhashMap` can only be derived for structs",
+            ))
+        }
+    };
+    let idents: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let names: Vec<_> = idents.iter().map(|ident| ident.to_string()).collect();
+    Ok(quote! {
+        impl #impl_generics ::skytable::types::ToSkyhashMap for #name #ty_generics #where_clause {
+            fn field_names() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+            fn to_skyhash_map(&self) -> ::std::vec::Vec<(&'static str, ::std::vec::Vec<u8>)> {
+                ::std::vec![
+                    #((#names, ::skytable::types::IntoSkyhashBytes::as_bytes(&self.#idents))),*
+                ]
+            }
+        }
+    })
+}
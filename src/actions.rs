@@ -24,6 +24,7 @@
 //!     ```
 //! - For the `async` feature, add this import:
 //!     ```
+//!     # #[cfg(feature = "aio")]
 //!     use skytable::actions::AsyncActions;
 //!     ```
 //! ## Running actions
@@ -37,11 +38,33 @@
 //! assert_eq!(ret, "100");
 //! ```
 //!
+//! ## Compare-and-swap
+//!
+//! Skytable doesn't have a compare-and-swap action, so there is no `cas` method: emulating it
+//! with a [`get`](Actions::get) followed by an [`update`](Actions::update) is **not** atomic since
+//! another client can change the key in between. The conditional actions that _are_ atomic are:
+//! - [`set`](Actions::set), which only sets a key if it doesn't exist (returning `false` if it does)
+//! - [`update`](Actions::update), which only updates a key if it exists
+//! - [`sset`](Actions::sset), [`supdate`](Actions::supdate) and [`sdel`](Actions::sdel), which
+//!   act on all the provided keys or on none of them
+//!
+//! For example, `set` can be used for a simple lock (note that the lock isn't released if the
+//! client holding it goes away):
+//! ```no_run
+//! use skytable::{actions::Actions, Connection};
+//! let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+//! if con.set("lock:orders", "worker-1").unwrap() {
+//!     // we hold the lock
+//!     con.del("lock:orders").unwrap();
+//! }
+//! ```
+//!
 
 use crate::error::{errorstring, SkyhashError};
 use crate::types::Array;
 use crate::types::FromSkyhashBytes;
 use crate::types::SnapshotResult;
+use crate::types::{RawString, ToSkyhashMap};
 use crate::Element;
 use crate::GetIterator;
 use crate::IntoSkyhashAction;
@@ -55,6 +78,12 @@ cfg_async!(
     use crate::AsyncResult;
 );
 
+// the hottest actions, already serialized as arguments
+#[cfg(any(feature = "sync", feature = "aio"))]
+const ACTION_GET: &[u8] = b"3\nget";
+#[cfg(any(feature = "sync", feature = "aio"))]
+const ACTION_SET: &[u8] = b"3\nset";
+
 cfg_async!(
     #[doc(hidden)]
     /// A raw async connection to the database server
@@ -79,7 +108,7 @@ macro_rules! implement_actions {
     (
         $(
             $(#[$attr:meta])+
-            fn $name:ident$(<$($tyargs:ident $(: $ty:ident $(+$tye:lifetime)?)?),*>)?(
+            fn $name:ident$(<$($tyargs:ident $(: $ty:ident $(+$tye:tt)?)?),*>)?(
                 $($argname:ident: $argty:ty),*) -> $ret:ty {
                     $($block:block)?
                     $($($mtch:pat)|+ => $expect:expr),+
@@ -104,6 +133,7 @@ macro_rules! implement_actions {
             $(
                 $(#[$attr])*
                 #[inline]
+                #[allow(clippy::needless_lifetimes)]
                 fn $name<'s, $($($tyargs$(: $ty $(+$tye)*,)* )*)?>(&'s mut self $(, $argname: $argty)*) -> SkyResult<$ret> {
                     gen_match!(self.run($($block)?), $($($mtch)+, $expect),*)
                 }
@@ -116,6 +146,7 @@ macro_rules! implement_actions {
             $(
                 $(#[$attr])*
                 #[inline]
+                #[allow(clippy::needless_lifetimes)]
                 fn $name<'s, $($($tyargs$(: $ty $(+$tye)*,)* )*)?>(&'s mut self $(, $argname: $argty)*) -> AsyncResult<SkyResult<$ret>> {
                     Box::pin(async move {gen_match!(self.run($($block)?).await, $($($mtch)+, $expect),*)})
                 }
@@ -125,6 +156,39 @@ macro_rules! implement_actions {
 }
 
 implement_actions! {
+    /// Authenticate the connection with the provided username and token
+    ///
+    /// This is equivalent to:
+    /// ```text
+    /// AUTH LOGIN <username> <token>
+    /// ```
+    ///
+    /// If the credentials are incorrect, a [`SkyhashError::Code`] error carrying
+    /// [`RespCode::AuthBadCredentials`] is returned. If authn is disabled on the server, the
+    /// error carries the [`ERR_AUTH_DISABLED`](errorstring::ERR_AUTH_DISABLED) error string
+    /// instead
+    ///
+    /// To authenticate right after connecting, use
+    /// [`ConnectionBuilder::set_auth`](crate::ConnectionBuilder::set_auth)
+    fn auth_login(username: impl IntoSkyhashBytes + 's, token: impl IntoSkyhashBytes + 's) -> () {
+        { Query::from("auth").arg("login").arg(username).arg(token) }
+        Element::RespCode(RespCode::Okay) => {}
+    }
+    /// Log out of the connection, so that it has to authenticate again to run queries
+    ///
+    /// This is equivalent to:
+    /// ```text
+    /// AUTH LOGOUT
+    /// ```
+    ///
+    /// If the connection isn't logged in, a [`SkyhashError::Code`] error carrying
+    /// [`RespCode::AuthPermissionError`] is returned. Like [`Self::auth_login`], the
+    /// [`ERR_AUTH_DISABLED`](errorstring::ERR_AUTH_DISABLED) error string is returned if authn
+    /// is disabled
+    fn auth_logout() -> () {
+        { Query::from("auth").arg("logout") }
+        Element::RespCode(RespCode::Okay) => {}
+    }
     /// Get the number of keys present in the database
     fn dbsize() -> u64 {
         { Query::from("dbsize") }
@@ -137,11 +201,13 @@ implement_actions! {
     /// DEL <k1> <k2> <k3> ...
     /// ```
     ///
-    /// This will return the number of keys that were deleted
+    /// This will return the number of keys that were deleted. A single key or multiple keys
+    /// can be passed. If the server returns a response code instead of a count, it is returned
+    /// as a [`SkyhashError::Code`] error
     ///
     fn del(key: impl IntoSkyhashAction + 's) -> u64 {
         { Query::from("del").arg(key) }
-        Element::UnsignedInt(int) => int as u64
+        Element::UnsignedInt(int) => int
     }
     /// Checks if a key (or keys) exist(s)
     ///
@@ -154,7 +220,7 @@ implement_actions! {
     ///
     fn exists(key: impl IntoSkyhashAction + 's) -> u64 {
         { Query::from("exists").arg(key) }
-        Element::UnsignedInt(int) => int as u64
+        Element::UnsignedInt(int) => int
     }
     /// Removes all the keys present in the database
     fn flushdb() -> () {
@@ -168,7 +234,7 @@ implement_actions! {
     /// GET <key>
     /// ```
     fn get<T: FromSkyhashBytes>(key: impl IntoSkyhashBytes + 's) -> T {
-        { Query::from("get").arg(key)}
+        { Query::from_serialized_action(ACTION_GET).arg(key) }
         x @ Element::String(_) | x @ Element::Binstr(_) => T::from_element(x)?
     }
     /// Get the length of a key
@@ -179,7 +245,36 @@ implement_actions! {
     /// ```
     fn keylen(key: impl IntoSkyhashBytes + 's) -> u64 {
         { Query::from("keylen").arg(key)}
-        Element::UnsignedInt(int) => int as u64
+        Element::UnsignedInt(int) => int
+    }
+    /// Get at most `count` elements of a list, starting at the element with index `start`
+    ///
+    /// This is equivalent to:
+    /// ```text
+    /// LGET <key> RANGE <start> <start + count>
+    /// ```
+    ///
+    /// This can be used to page through a large list in bounded chunks. If the range extends
+    /// past the end of the list, fewer than `count` elements are returned; if `start` is past
+    /// the end of the list, an empty vector is returned
+    fn lget_range(key: impl IntoSkyhashBytes + 's, start: u64, count: u64) -> Vec<String> {
+        {
+            Query::from("lget")
+                .arg(key)
+                .arg("range")
+                .arg(start.to_string())
+                .arg(start.saturating_add(count).to_string())
+        }
+        x @ Element::Array(Array::Bin(_))
+        | x @ Element::Array(Array::Str(_))
+        | x @ Element::Array(Array::NonNullBin(_))
+        | x @ Element::Array(Array::NonNullStr(_)) => FromSkyhashBytes::from_element(x)?,
+        Element::RespCode(RespCode::ErrorString(er)) => {
+            match er.as_str() {
+                errorstring::ERR_BAD_LIST_INDEX => Vec::new(),
+                _ => return Err(SkyhashError::Code(RespCode::ErrorString(er)).into())
+            }
+        }
     }
     /// Returns a vector of keys
     ///
@@ -221,6 +316,35 @@ implement_actions! {
            }
        }
     }
+    /// Creates a snapshot with the provided name
+    ///
+    /// This is equivalent to:
+    /// ```text
+    /// MKSNAP <name>
+    /// ```
+    ///
+    /// This will return true if the snapshot was created or false if a snapshot with the same
+    /// name already exists. Unlike the plain `mksnap`, a busy snapshot engine, disabled
+    /// snapshots and invalid names are returned as errors: [`SkyhashError::SnapshotBusy`],
+    /// [`SkyhashError::SnapshotDisabled`] and [`SkyhashError::InvalidSnapshotName`]
+    /// respectively
+    fn mksnap_named(name: impl IntoSkyhashBytes + 's) -> bool {
+        { Query::from("mksnap").arg(name) }
+        Element::RespCode(RespCode::Okay) => true,
+        Element::RespCode(RespCode::ErrorString(er)) => {
+            match er.as_str() {
+                errorstring::ERR_DUPLICATE_SNAPSHOT => false,
+                errorstring::ERR_SNAPSHOT_BUSY => return Err(SkyhashError::SnapshotBusy.into()),
+                errorstring::ERR_SNAPSHOT_DISABLED => {
+                    return Err(SkyhashError::SnapshotDisabled.into())
+                }
+                errorstring::ERR_INVALID_SNAPSHOT_NAME => {
+                    return Err(SkyhashError::InvalidSnapshotName.into())
+                }
+                _ => return Err(SkyhashError::Code(RespCode::ErrorString(er)).into())
+            }
+        }
+    }
 
     /// Sets the value of multiple keys and values and returns the number of keys that were set
     ///
@@ -244,7 +368,7 @@ implement_actions! {
             assert!(keys.incr_len_by() == values.incr_len_by(), "The number of keys and values for mset must be equal");
             Query::from("mset")._push_alt_iter(keys, values)
         }
-        Element::UnsignedInt(int) => int as u64
+        Element::UnsignedInt(int) => int
     }
     /// Updates the value of multiple keys and values and returns the number of keys that were updated
     ///
@@ -268,7 +392,7 @@ implement_actions! {
             assert!(keys.incr_len_by() == values.incr_len_by(), "The number of keys and values for mupdate must be equal");
             Query::from("mset")._push_alt_iter(keys, values)
         }
-        Element::UnsignedInt(int) => int as u64
+        Element::UnsignedInt(int) => int
     }
     /// Consumes a key if it exists
     ///
@@ -315,7 +439,7 @@ implement_actions! {
     /// ```
     ///
     fn set(key: impl IntoSkyhashBytes + 's, value: impl IntoSkyhashBytes + 's) -> bool {
-        { Query::from("set").arg(key).arg(value) }
+        { Query::from_serialized_action(ACTION_SET).arg(key).arg(value) }
         Element::RespCode(RespCode::Okay) => true,
         Element::RespCode(RespCode::OverwriteError) => false
     }
@@ -412,6 +536,159 @@ implement_actions! {
             );
             Query::from("uset")._push_alt_iter(keys, values)
         }
-        Element::UnsignedInt(int) => int as u64
+        Element::UnsignedInt(int) => int
+    }
+    /// Store every field of `value` under its own key, named `<prefix>.<field>`, and return the
+    /// number of keys that were set
+    ///
+    /// Existing keys are overwritten. This is equivalent to:
+    /// ```text
+    /// USET <prefix>.<field1> <value1> <prefix>.<field2> <value2> ...
+    /// ```
+    fn set_struct<T: ToSkyhashMap>(prefix: &'s str, value: &'s T) -> u64 {
+        {
+            let mut q = Query::from("uset");
+            for (field, value) in value.to_skyhash_map() {
+                q.push(format!("{}.{}", prefix, field));
+                q.push(RawString::from(value));
+            }
+            q
+        }
+        Element::UnsignedInt(int) => int
+    }
+    /// Read a value stored with [`set_struct`](Self::set_struct) back, fetching the keys of
+    /// its fields in the order of [`ToSkyhashMap::field_names`]
+    ///
+    /// If any of the keys doesn't exist, an error is returned. This is equivalent to:
+    /// ```text
+    /// MGET <prefix>.<field1> <prefix>.<field2> ...
+    /// ```
+    fn get_struct<T: FromSkyhashBytes + ToSkyhashMap>(prefix: &'s str) -> T {
+        {
+            let mut q = Query::from("mget");
+            for field in T::field_names() {
+                q.push(format!("{}.{}", prefix, field));
+            }
+            q
+        }
+        x @ Element::Array(Array::Bin(_)) | x @ Element::Array(Array::Str(_)) => T::from_element(x)?
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_int_actions_error_mapping() {
+    /// A socket that responds to a single query with the provided element
+    struct MockSocket(Option<Element>);
+    impl SyncSocket for MockSocket {
+        fn run(&mut self, _q: Query) -> SkyQueryResult {
+            Ok(self.0.take().unwrap())
+        }
+    }
+    type IntAction = fn(&mut MockSocket) -> SkyResult<u64>;
+    let actions: [IntAction; 4] = [
+        |con| con.dbsize(),
+        |con| con.del("x"),
+        |con| con.exists("x"),
+        |con| con.keylen("x"),
+    ];
+    for action in actions.iter() {
+        assert_eq!(
+            action(&mut MockSocket(Some(Element::UnsignedInt(3)))),
+            Ok(3)
+        );
+        assert_eq!(
+            action(&mut MockSocket(Some(Element::RespCode(
+                RespCode::ServerError
+            )))),
+            Err(SkyhashError::Code(RespCode::ServerError).into())
+        );
+        assert_eq!(
+            action(&mut MockSocket(Some(Element::String("3".to_owned())))),
+            Err(SkyhashError::UnexpectedDataType.into())
+        );
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "aio"))]
+#[test]
+fn test_serialized_actions() {
+    for (serialized, action) in [(ACTION_GET, "get"), (ACTION_SET, "set")] {
+        assert_eq!(
+            Query::from_serialized_action(serialized)
+                .arg("x")
+                .as_bytes(),
+            Query::from(action).arg("x").as_bytes()
+        );
+    }
+    let mut query = Query::from_serialized_action(ACTION_GET).arg("x");
+    query.uppercase_action();
+    assert_eq!(query.as_bytes(), b"*2\n3\nGET1\nx");
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_struct_actions() {
+    /// A socket that records the query and responds with the provided element
+    struct MockSocket(Option<Query>, Option<Element>);
+    impl SyncSocket for MockSocket {
+        fn run(&mut self, q: Query) -> SkyQueryResult {
+            self.0 = Some(q);
+            Ok(self.1.take().unwrap())
+        }
+    }
+    #[derive(Debug, PartialEq)]
+    struct User {
+        name: String,
+        email: String,
+    }
+    impl ToSkyhashMap for User {
+        fn field_names() -> &'static [&'static str] {
+            &["name", "email"]
+        }
+        fn to_skyhash_map(&self) -> Vec<(&'static str, Vec<u8>)> {
+            vec![
+                ("name", self.name.clone().into_bytes()),
+                ("email", self.email.clone().into_bytes()),
+            ]
+        }
     }
+    impl FromSkyhashBytes for User {
+        fn from_element(element: Element) -> SkyResult<Self> {
+            let mut fields: Vec<String> = FromSkyhashBytes::from_element(element)?;
+            let email = fields.pop().unwrap();
+            let name = fields.pop().unwrap();
+            Ok(Self { name, email })
+        }
+    }
+    let user = User {
+        name: "sayan".to_owned(),
+        email: "sayan@example.com".to_owned(),
+    };
+    let mut con = MockSocket(None, Some(Element::UnsignedInt(2)));
+    assert_eq!(con.set_struct("user:1", &user), Ok(2));
+    assert_eq!(
+        con.0.unwrap(),
+        Query::from(vec![
+            "uset",
+            "user:1.name",
+            "sayan",
+            "user:1.email",
+            "sayan@example.com"
+        ])
+    );
+    let response = Element::Array(Array::Str(vec![
+        Some("sayan".to_owned()),
+        Some("sayan@example.com".to_owned()),
+    ]));
+    let mut con = MockSocket(None, Some(response));
+    assert_eq!(con.get_struct::<User>("user:1"), Ok(user));
+    assert_eq!(
+        con.0.unwrap(),
+        Query::from(vec!["mget", "user:1.name", "user:1.email"])
+    );
+    // a missing field is an error
+    let response = Element::Array(Array::Str(vec![Some("sayan".to_owned()), None]));
+    let mut con = MockSocket(None, Some(response));
+    assert!(con.get_struct::<User>("user:1").is_err());
 }
@@ -21,26 +21,185 @@
 //! - The [`Connection`]: a connection to the database over Skyhash/TCP
 //! - The [`TlsConnection`]: a connection to the database over Skyhash/TLS
 //!
-//! All the [async actions][crate::actions::AsyncActions] can be used on both the connection types
+//! With the `unix` feature, a `UnixConnection` (made with `Connection::new_unix`) connects over
+//! a UNIX domain socket instead.
+//! All the [async actions][crate::actions::AsyncActions] can be used on all the connection types
+//!
+//! ## Cancellation safety
+//!
+//! Methods that write to the connection (like `run_query`, `run_pipeline` and `send_query`) are
+//! not cancellation safe: if their future is dropped before it completes (for example, in a
+//! `tokio::select!` or when using `tokio::time::timeout`), a query might have been written
+//! partially or its response might not have been read. Such a connection is poisoned and every
+//! subsequent call will return an error, instead of returning responses that belong to other
+//! queries. Pools will drop poisoned connections. If the query was written completely, the
+//! connection can be recovered by reading and discarding its response with `discard_pending`.
+//!
+//! Methods that only read from the connection (like `read_response`) are cancellation safe
+//!
+//! ## Concurrency
+//!
+//! Connections aren't internally synchronized: every method that writes to or reads from the
+//! connection takes `&mut self`, so queries on the same connection can never interleave and
+//! trying to run two queries concurrently is a compile error:
+//! ```compile_fail
+//! use skytable::{aio::Connection, query};
+//!
+//! async fn run(con: &mut Connection) {
+//!     let q1 = query!("get", "x");
+//!     let q2 = query!("get", "y");
+//!     // error: `con` can't be borrowed mutably twice
+//!     let _ = tokio::join!(con.run_query_raw(&q1), con.run_query_raw(&q2));
+//! }
+//! ```
+//! To share a connection between tasks, wrap it in an async mutex (like `tokio::sync::Mutex`)
+//! and hold the lock until the response was read, or use a [connection pool](crate::pool)
+//! instead. If a task is cancelled while it holds the lock, the connection is poisoned as
+//! described above, so other tasks will never read a response meant for it
 //!
 
+use crate::actions::{AsyncActions, AsyncSocket};
+use crate::budget::Reservation;
 use crate::deserializer::{ParseError, Parser, RawResponse};
-use crate::error::SkyhashError;
-use crate::types::FromSkyhashBytes;
+use crate::dns::DnsCache;
+use crate::socks::Socks5Proxy;
+use crate::error::{Error, PipelineError, SkyhashError};
+use crate::pool::ErrorPolicy;
+use crate::types::{FromSkyhashBytes, Health, IntoSkyhashBytes, SysMetric, Version};
+use crate::AddressFamily;
+use crate::ArgList;
+use crate::Compression;
+use crate::ConnectionBuilder;
+use crate::ConnectionState;
 use crate::Element;
+use crate::ElementType;
+use crate::MemoryBudget;
+use crate::RespCode;
 use crate::Pipeline;
+use crate::Prefixed;
 use crate::Query;
+use crate::QueryHook;
+use crate::ResponseHeader;
+use crate::ResponseVisitor;
+use crate::RetryPolicy;
 use crate::SkyQueryResult;
 use crate::SkyResult;
 use crate::WriteQueryAsync;
 use bytes::{Buf, BytesMut};
+use core::future::Future;
+use futures_util::stream::{self, Stream};
 use std::io::{Error as IoError, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use tokio::net::TcpStream;
+use socket2::SockRef;
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep_until, timeout_at, Duration, Instant};
 
 /// 4 KB Read Buffer
 const BUF_CAP: usize = 4096;
 
+/// Run the I/O operation `io`, failing with [`SkyhashError::Timeout`] if it doesn't complete
+/// within `timeout` (if provided)
+pub(crate) async fn with_timeout<T, E: Into<Error>>(
+    timeout: Option<Duration>,
+    io: impl Future<Output = Result<T, E>>,
+) -> SkyResult<T> {
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, io).await {
+            Ok(ret) => ret.map_err(Into::into),
+            Err(_) => Err(SkyhashError::Timeout.into()),
+        },
+        None => io.await.map_err(Into::into),
+    }
+}
+
+/// Connect to `host:port`, binding the socket to `local_addr` first (if provided). The host is
+/// resolved through `dns_cache` (if provided)
+async fn connect_tcp(
+    host: &str,
+    port: u16,
+    local_addr: Option<SocketAddr>,
+    dns_cache: Option<&DnsCache>,
+    address_family: AddressFamily,
+) -> std::io::Result<TcpStream> {
+    let addrs: Vec<SocketAddr> = match dns_cache {
+        Some(dns_cache) => dns_cache.resolve_async(host, port).await?,
+        None => tokio::net::lookup_host((host, port)).await?.collect(),
+    };
+    let addrs = address_family.filter(addrs)?;
+    let local_addr = match local_addr {
+        Some(local_addr) => local_addr,
+        None => return TcpStream::connect(&addrs[..]).await,
+    };
+    let mut last_err = None;
+    for addr in addrs {
+        if addr.is_ipv4() != local_addr.is_ipv4() {
+            // can't bind to an address from a different family
+            continue;
+        }
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        if let Err(e) = socket.bind(local_addr) {
+            last_err = Some(e);
+            continue;
+        }
+        match socket.connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        IoError::new(
+            ErrorKind::InvalidInput,
+            "no address for host in the same family as the local address",
+        )
+    }))
+}
+
+/// Connect to `host:port`, through `proxy` (if provided). Connecting to the host (or to the
+/// proxy) fails with [`SkyhashError::Timeout`] if it takes longer than `connect_timeout`
+async fn connect(
+    host: &str,
+    port: u16,
+    local_addr: Option<SocketAddr>,
+    proxy: Option<&Socks5Proxy>,
+    dns_cache: Option<&DnsCache>,
+    address_family: AddressFamily,
+    connect_timeout: Option<Duration>,
+) -> SkyResult<TcpStream> {
+    match proxy {
+        #[cfg(feature = "socks")]
+        Some(proxy) => {
+            let stream = with_timeout(
+                connect_timeout,
+                connect_tcp(
+                    &proxy.host,
+                    proxy.port,
+                    local_addr,
+                    dns_cache,
+                    address_family,
+                ),
+            )
+            .await?;
+            Ok(crate::socks::handshake_async(stream, proxy, host, port).await?)
+        }
+        #[cfg(not(feature = "socks"))]
+        Some(proxy) => match *proxy {},
+        None => {
+            with_timeout(
+                connect_timeout,
+                connect_tcp(host, port, local_addr, dns_cache, address_family),
+            )
+            .await
+        }
+    }
+}
+
 macro_rules! impl_async_methods {
     ($ty:ty, $inner:ty) => {
         impl $ty {
@@ -53,12 +212,168 @@ macro_rules! impl_async_methods {
             /// or invalid and return an appropriate variant of [`Error`](crate::error::Error)
             /// for any I/O errors that may occur
             ///
-            /// ## Panics
-            /// This method will panic if the [`Query`] supplied is empty (i.e has no arguments)
+            /// An empty [`Query`] (with no arguments) isn't sent and returns
+            /// [`Error::ConfigurationError`](crate::error::Error::ConfigurationError)
             pub async fn run_query_raw<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<Element> {
-                match self._run_query(query.as_ref()).await? {
-                    RawResponse::SimpleQuery(sq) => Ok(sq),
-                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                let query = query.as_ref();
+                let action = query.iter_args().next();
+                if action.map_or(false, |action| action.eq_ignore_ascii_case(b"use")) {
+                    // the entity is changed behind the back of `use_entity`
+                    self.entity = None;
+                }
+                let ret = match self._run_query(query).await {
+                    Ok(RawResponse::SimpleQuery(sq)) => Ok(sq),
+                    Ok(RawResponse::PipelinedQuery(_)) => Err(SkyhashError::InvalidResponse.into()),
+                    Err(e) => Err(e),
+                };
+                if let Some(hook) = &self.query_hook {
+                    hook.call(query, &ret);
+                }
+                ret
+            }
+            /// Runs a query like [`Self::run_query_raw`], but also returns the raw bytes of
+            /// the response frame exactly as they were received from the server. This is useful
+            /// to forward responses as-is (for example, in a proxy) without serializing them again
+            pub async fn run_with_raw<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+            ) -> SkyResult<(Element, Vec<u8>)> {
+                let mut raw = Vec::new();
+                let element = self.run_into(query, &mut raw).await?;
+                Ok((element, raw))
+            }
+            /// Runs a query like [`Self::run_query_raw`] and returns a
+            /// [`SkyhashError::ResponseMismatch`] error (holding both responses) if the response
+            /// isn't equal to `expected`. This is mostly useful in tests
+            pub async fn run_expecting<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+                expected: &Element,
+            ) -> SkyResult<()> {
+                let actual = self.run_query_raw(query).await?;
+                crate::check_expected(expected, actual)
+            }
+            /// Runs a query like [`Self::run_with_raw`], but writes the raw bytes of the response
+            /// frame into the provided buffer (which is cleared first) instead of allocating a
+            /// new one. Reusing the same buffer for many queries avoids an allocation per query
+            pub async fn run_into<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+                buf: &mut Vec<u8>,
+            ) -> SkyResult<Element> {
+                buf.clear();
+                let _permit = self.acquire().await;
+                let ret = self._run_into(query.as_ref(), buf).await;
+                self.in_flight = false;
+                self.track(ret)
+            }
+            async fn _run_into(&mut self, query: &Query, buf: &mut Vec<u8>) -> SkyResult<Element> {
+                self.begin()?;
+                self.write_query(query).await?;
+                self.flush_stream().await?;
+                loop {
+                    match self.try_response() {
+                        Ok((RawResponse::SimpleQuery(sq), forward_by)) => {
+                            buf.extend_from_slice(&self.buffer[..forward_by]);
+                            self.buffer.advance(forward_by);
+                            return Ok(sq);
+                        }
+                        Ok((RawResponse::PipelinedQuery(_), forward_by)) => {
+                            self.buffer.advance(forward_by);
+                            return Err(SkyhashError::InvalidResponse.into());
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer().await?;
+                }
+            }
+            /// Runs a query and returns the type symbol and raw payload of each top-level
+            /// element of the response: the elements of the array if the response holds an
+            /// array, or else the one element it holds. The payloads are neither validated as
+            /// UTF-8 nor parsed as numbers, which makes this the cheapest faithful representation
+            /// of a response for code that only forwards responses. Null elements of typed
+            /// arrays are returned as `(b'\0', [])`
+            pub async fn run_query_flat<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+            ) -> SkyResult<Vec<(u8, Vec<u8>)>> {
+                let _permit = self.acquire().await;
+                let ret = self._run_query_flat(query.as_ref()).await;
+                self.in_flight = false;
+                self.track(ret)
+            }
+            async fn _run_query_flat(&mut self, query: &Query) -> SkyResult<Vec<(u8, Vec<u8>)>> {
+                self.begin()?;
+                self.write_query(query).await?;
+                self.flush_stream().await?;
+                loop {
+                    match Parser::parse_flat(&self.buffer) {
+                        Ok((elements, forward_by)) => {
+                            self.buffer.advance(forward_by);
+                            return Ok(elements);
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer().await?;
+                }
+            }
+            /// Runs a query and drives the provided [`ResponseVisitor`] with the response, so
+            /// that the response can be processed without allocating [`Element`]s for it. This
+            /// is the cheapest way to consume large responses
+            ///
+            /// The visitor is only called once the whole response has been received.
+            /// [`SkyhashError::InvalidResponse`] is returned for pipelined responses
+            pub async fn run_visit<Q: AsRef<Query>, V: ResponseVisitor>(
+                &mut self,
+                query: Q,
+                visitor: &mut V,
+            ) -> SkyResult<()> {
+                let _permit = self.acquire().await;
+                let ret = self._run_visit(query.as_ref(), visitor).await;
+                self.in_flight = false;
+                self.track(ret)
+            }
+            async fn _run_visit<V: ResponseVisitor>(
+                &mut self,
+                query: &Query,
+                visitor: &mut V,
+            ) -> SkyResult<()> {
+                self.begin()?;
+                self.write_query(query).await?;
+                self.flush_stream().await?;
+                loop {
+                    match Parser::visit(&self.buffer, visitor) {
+                        Ok(forward_by) => {
+                            self.buffer.advance(forward_by);
+                            return Ok(());
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer().await?;
+                }
+            }
+            /// Runs a query like [`Self::run_query_raw`], but retries it (as per the provided
+            /// [`RetryPolicy`]) as long as the server responds with one of the provided response
+            /// codes. This is useful for conditions that are known to be transient for a workload
+            /// (like a [`RespCode::ServerError`] while a snapshot is being taken)
+            ///
+            /// Once the retries are used up, the last response is returned. Errors (like I/O
+            /// errors) are returned right away and are never retried
+            pub async fn run_retry_on<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+                codes: &[RespCode],
+                policy: RetryPolicy,
+            ) -> SkyResult<Element> {
+                let query = query.as_ref();
+                let mut retry = 0;
+                loop {
+                    let ret = self.run_query_raw(query).await;
+                    if retry == policy.max_retries() || !RetryPolicy::should_retry(&ret, codes) {
+                        return ret;
+                    }
+                    tokio::time::sleep(policy.delay(retry)).await;
+                    retry += 1;
                 }
             }
             #[deprecated(
@@ -71,102 +386,1741 @@ macro_rules! impl_async_methods {
             }
             /// Runs a pipelined query. See the [`Pipeline`](Pipeline) documentation for a guide on
             /// usage
-            pub async fn run_pipeline(&mut self, pipeline: Pipeline) -> SkyResult<Vec<Element>> {
-                match self._run_query(&pipeline).await? {
+            ///
+            /// The queries are written in one frame and their responses are returned in the order
+            /// the queries were added to the pipeline
+            ///
+            /// An empty pipeline isn't sent to the server and returns no responses
+            pub async fn run_pipeline<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+            ) -> SkyResult<Vec<Element>> {
+                let pipeline = pipeline.as_ref();
+                if pipeline.is_empty() {
+                    return Ok(Vec::new());
+                }
+                match self._run_query(pipeline).await? {
                     RawResponse::PipelinedQuery(pq) => Ok(pq),
+                    // the server couldn't parse the pipeline
+                    RawResponse::SimpleQuery(Element::RespCode(RespCode::PacketError)) => {
+                        Err(SkyhashError::ProtocolError.into())
+                    }
                     RawResponse::SimpleQuery(_) => Err(SkyhashError::InvalidResponse.into()),
                 }
             }
+            /// Runs a pipelined query like [`Self::run_pipeline`], but returns a [`Stream`] that
+            /// yields every response as soon as it is read off the connection, in order
+            ///
+            /// The stream borrows the connection, so nothing else can be run on it until the
+            /// stream is dropped. If the stream is dropped before it yields all the responses,
+            /// or if reading a response fails, the connection is left poisoned (see
+            /// [`ConnectionState::Poisoned`]) and must be dropped since the remaining responses
+            /// can't be told apart from the responses to later queries
+            pub async fn run_pipeline_stream<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+            ) -> SkyResult<impl Stream<Item = SkyResult<Element>> + '_> {
+                // the permit is held until the stream is dropped
+                let permit = self.acquire().await;
+                let pipeline = pipeline.as_ref();
+                // an empty pipeline isn't sent, so its stream ends right away
+                let remaining = if pipeline.is_empty() {
+                    0
+                } else {
+                    let ret = self._run_pipeline_head(pipeline).await;
+                    self.in_flight = false;
+                    self.track(ret)?
+                };
+                if remaining != 0 {
+                    // until every response is read
+                    self.state = ConnectionState::Poisoned;
+                    self.recoverable = false;
+                }
+                Ok(stream::unfold((self, remaining, permit), |(con, remaining, permit)| async move {
+                    if remaining == 0 {
+                        return None;
+                    }
+                    let ret = con._read_pipeline_element().await;
+                    match con.track(ret) {
+                        Ok(element) => {
+                            if remaining == 1 {
+                                con.state = ConnectionState::Ready;
+                            }
+                            Some((Ok(element), (con, remaining - 1, permit)))
+                        }
+                        // the stream is out of sync, so stop here
+                        Err(e) => Some((Err(e), (con, 0, permit))),
+                    }
+                }))
+            }
+            /// Runs a pipelined query like [`Self::run_pipeline`], but returns a
+            /// [`SkyhashError::PipelineFailed`] error holding the index and the response code of
+            /// the first query that returned a response code other than [`RespCode::Okay`]
+            ///
+            /// **This isn't atomic**: the queries after the failing query are still run and the
+            /// queries before it aren't rolled back. See the [`Pipeline`] documentation for more
+            /// information
+            pub async fn run_pipeline_checked<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+            ) -> SkyResult<Vec<Element>> {
+                crate::check_pipeline_responses(self.run_pipeline(pipeline).await?)
+            }
+            /// Runs a pipelined query like [`Self::run_pipeline`], but reads the responses one
+            /// by one so that the responses received before an error (for example, if the
+            /// connection is closed midway) aren't lost. These are returned in the
+            /// [`PipelineError`] along with the error, so that only the remaining queries have to
+            /// be run again
+            ///
+            /// **This isn't atomic**: the queries that a response was received for have been run
+            /// by the server, while the remaining queries may or may not have been run
+            ///
+            /// An empty pipeline isn't sent to the server and returns no responses
+            pub async fn run_pipeline_partial<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+            ) -> Result<Vec<Element>, PipelineError> {
+                let pipeline = pipeline.as_ref();
+                if pipeline.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let mut received = Vec::new();
+                let _permit = self.acquire().await;
+                let ret = self._run_pipeline_partial(pipeline, &mut received).await;
+                self.in_flight = false;
+                match self.track(ret) {
+                    Ok(()) => Ok(received),
+                    Err(source) => Err(PipelineError { received, source }),
+                }
+            }
+            async fn _run_pipeline_partial(
+                &mut self,
+                pipeline: &Pipeline,
+                received: &mut Vec<Element>,
+            ) -> SkyResult<()> {
+                let count = self._run_pipeline_head(pipeline).await?;
+                received.reserve(count);
+                while received.len() != count {
+                    received.push(self._read_pipeline_element().await?);
+                }
+                Ok(())
+            }
+            /// Runs a pipeline like [`Self::run_pipeline`], but sends its queries one by one and
+            /// never has more than `window` of them waiting for a response: once `window`
+            /// queries were sent, a response is read before the next query is sent. Sending a
+            /// large pipeline in one go can fill up the socket buffers on both sides (the server
+            /// can't write its responses since they aren't read yet) and deadlock; this keeps
+            /// the number of unread responses bounded. The responses are returned in the order
+            /// of the queries
+            ///
+            /// An empty pipeline isn't sent to the server and returns no responses
+            ///
+            /// ## Panics
+            /// This method will panic if `window` is zero
+            pub async fn run_pipeline_windowed<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+                window: usize,
+            ) -> SkyResult<Vec<Element>> {
+                let pipeline = pipeline.as_ref();
+                assert!(window != 0, "The window must hold at least one query");
+                if pipeline.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let _permit = self.acquire().await;
+                let ret = self._run_pipeline_windowed(pipeline, window).await;
+                self.in_flight = false;
+                self.track(ret)
+            }
+            async fn _run_pipeline_windowed(
+                &mut self,
+                pipeline: &Pipeline,
+                window: usize,
+            ) -> SkyResult<Vec<Element>> {
+                pipeline.check_queries()?;
+                self.begin()?;
+                let mut responses = Vec::with_capacity(pipeline.len());
+                let frames = pipeline.query_frames();
+                for (sent, frame) in frames.iter().enumerate() {
+                    if sent - responses.len() == window {
+                        with_timeout(self.write_timeout, self.stream.flush()).await?;
+                        responses.push(self._read_windowed_response().await?);
+                    }
+                    with_timeout(self.write_timeout, self.stream.write_all(frame)).await?;
+                    self.bytes_written += frame.len() as u64;
+                }
+                // several responses are pending, so the connection can't be recovered if this
+                // is cancelled (unlike after `flush_stream`)
+                with_timeout(self.write_timeout, self.stream.flush()).await?;
+                while responses.len() != frames.len() {
+                    responses.push(self._read_windowed_response().await?);
+                }
+                Ok(responses)
+            }
+            async fn _read_windowed_response(&mut self) -> SkyResult<Element> {
+                match self._read_response().await? {
+                    RawResponse::SimpleQuery(sq) => Ok(sq),
+                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Sends a `HEYA` and returns the time it took the server to respond, which is
+            /// useful to monitor the latency of a connection. An error is returned if the
+            /// server doesn't respond with `HEY!`
+            pub async fn ping_latency(&mut self) -> SkyResult<Duration> {
+                let start = Instant::now();
+                let ret = self.run_query_raw(Query::from("heya")).await?;
+                crate::check_heya(ret)?;
+                Ok(start.elapsed())
+            }
+            /// Returns the metrics reported by the server with `SYS METRIC`, like its health and
+            /// the size of the data it stores. A response code returned for any of the metrics
+            /// (for example, if the user isn't allowed to query them) is returned as
+            /// [`SkyhashError::Code`]
+            pub async fn sys_metric(&mut self) -> SkyResult<SysMetric> {
+                SysMetric::from_responses(self.run_pipeline(SysMetric::pipeline()).await?)
+            }
+            /// Returns the health of the server, as reported by `SYS METRIC health`. Only
+            /// the health is queried, so this is cheaper than [`Self::sys_metric`] for frequent
+            /// health checks. A status unknown to this client is returned as [`Health::Other`]
+            pub async fn health(&mut self) -> SkyResult<Health> {
+                Health::from_response(self.run_query_raw(Health::query()).await?)
+            }
+            /// Runs a query built from the provided arguments, without building a [`Query`]
+            /// first. This is useful for hot paths where the arguments are short-lived
+            pub async fn run_args(&mut self, args: &[&dyn IntoSkyhashBytes]) -> SkyResult<Element> {
+                match self._run_query(&ArgList(args)).await? {
+                    RawResponse::SimpleQuery(sq) => Ok(sq),
+                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Write a [`Query`] to the stream without waiting for the response. The response
+            /// can then be read with [`Self::read_response`]
+            pub async fn send_query<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<()> {
+                let _permit = self.acquire().await;
+                let ret = self._send_query(query.as_ref()).await;
+                self.in_flight = false;
+                self.track(ret)
+            }
+            /// Write a [`Query`] to the buffer of this connection without flushing it, so that
+            /// several queries can be sent to the server at once with [`Self::flush`]. This
+            /// only waits if the buffer is full, in which case the buffered bytes are written
+            /// to the stream first
+            ///
+            /// The server will send one response for every query once it receives them, and
+            /// these must be read (in order) with [`Self::read_response`] before running any
+            /// other query on this connection. Queries that were queued, but not flushed, may
+            /// never reach the server
+            pub async fn queue_query<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<()> {
+                let _permit = self.acquire().await;
+                let ret = self._queue_query(query.as_ref()).await;
+                self.in_flight = false;
+                self.track(ret)
+            }
+            /// Write the queries queued with [`Self::queue_query`] to the stream
+            pub async fn flush(&mut self) -> SkyResult<()> {
+                let ret = match self.begin() {
+                    Ok(()) => self.flush_stream().await,
+                    Err(e) => Err(e),
+                };
+                self.in_flight = false;
+                self.track(ret)
+            }
+            /// Read the response to a query that was previously sent with [`Self::send_query`].
+            /// Responses are returned in the order in which the queries were sent
+            pub async fn read_response(&mut self) -> SkyResult<Element> {
+                let ret = match self.check_state() {
+                    Ok(()) => self._read_response().await,
+                    Err(e) => Err(e),
+                };
+                match self.track(ret)? {
+                    RawResponse::SimpleQuery(sq) => Ok(sq),
+                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Returns a [`Stream`] that reads responses to queries previously sent with
+            /// [`Self::send_query`]. Every item is read only once it's polled and the stream
+            /// ends after an I/O error, a timeout or once the server closed the connection
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use futures_util::StreamExt;
+            /// use skytable::{aio::Connection, query};
+            ///
+            /// async fn run() {
+            ///     let mut con = Connection::new("127.0.0.1", 2003).await.unwrap();
+            ///     for _ in 0..10 {
+            ///         con.send_query(query!("heya")).await.unwrap();
+            ///     }
+            ///     let mut responses = Box::pin(con.responses().take(10));
+            ///     while let Some(response) = responses.next().await {
+            ///         println!("{:?}", response.unwrap());
+            ///     }
+            /// }
+            /// ```
+            pub fn responses(&mut self) -> impl Stream<Item = SkyResult<Element>> + '_ {
+                stream::unfold(Some(self), |con| async move {
+                    let con = con?;
+                    let ret = con.read_response().await;
+                    // the connection is unusable after these errors
+                    let done = matches!(
+                        ret,
+                        Err(Error::IoError(_)
+                            | Error::SkyError(
+                                SkyhashError::ConnectionClosed(_) | SkyhashError::Timeout
+                            ))
+                    );
+                    Some((ret, if done { None } else { Some(con) }))
+                })
+            }
+            /// Read and discard the next response. This can be used to recover a connection
+            /// after a query was abandoned before its response was read (for example, because
+            /// it timed out), instead of reconnecting. Once this returns `Ok(())`, the
+            /// connection can be used (and pooled) again
+            ///
+            /// ## Caveats
+            /// The response is found using its framing information and hence this method will
+            /// block until a complete response frame is received. If no response is pending, this
+            /// will block until the next one arrives. If the frame is corrupt or has a data type
+            /// unknown to this client, its length can't be determined and an error is returned;
+            /// the connection should then be dropped as it can no longer be resynchronized
+            ///
+            /// A poisoned connection can only be recovered if it was poisoned by a read timeout
+            /// or a cancelled query, once the query was written completely. If it was poisoned by
+            /// anything else (like a write timeout, an invalid or too large response, or an I/O
+            /// error), an error is returned without reading anything and the connection stays
+            /// poisoned
+            pub async fn discard_pending(&mut self) -> SkyResult<()> {
+                if self.state() == ConnectionState::Poisoned && !self.recoverable {
+                    return Err(IoError::new(
+                        ErrorKind::NotConnected,
+                        "connection is poisoned by an error it can't be recovered from",
+                    )
+                    .into());
+                }
+                let ret = self._read_response().await.map(|_| ());
+                if ret.is_ok() && self.state() == ConnectionState::Poisoned {
+                    // the stream is back in sync
+                    self.in_flight = false;
+                    self.state = ConnectionState::Ready;
+                }
+                self.track(ret)
+            }
+            /// Peek at the header of the next response, without consuming it. This returns the
+            /// number of top-level elements declared by the response and can be used to decide
+            /// whether a large response should be buffered or not. The response can then be read
+            /// with [`Self::read_response`]
+            pub async fn peek_response_header(&mut self) -> SkyResult<ResponseHeader> {
+                let ret = match self.check_state() {
+                    Ok(()) => self._peek_response_header().await,
+                    Err(e) => Err(e),
+                };
+                self.track(ret)
+            }
+            /// Peek at the type of the element held by the next response, without consuming
+            /// it (or `None` if the next response is pipelined). Only the first bytes of the
+            /// response are needed, so this can be used to decide how to handle a response that
+            /// can have different types. The response can then be read with
+            /// [`Self::read_response`]
+            pub async fn peek_element_type(&mut self) -> SkyResult<Option<ElementType>> {
+                let ret = match self.check_state() {
+                    Ok(()) => self._peek_element_type().await,
+                    Err(e) => Err(e),
+                };
+                self.track(ret)
+            }
             async fn _run_query<Q: WriteQueryAsync<$inner>>(
                 &mut self,
                 query: &Q,
             ) -> SkyResult<RawResponse> {
-                query.write_async(&mut self.stream).await?;
-                self.stream.flush().await?;
+                let _permit = self.acquire().await;
+                let ret = self.__run_query(query).await;
+                self.in_flight = false;
+                self.track(ret)
+            }
+            async fn __run_query<Q: WriteQueryAsync<$inner>>(
+                &mut self,
+                query: &Q,
+            ) -> SkyResult<RawResponse> {
+                self.begin()?;
+                self.write_query(query).await?;
+                self.flush_stream().await?;
+                self._read_response().await
+            }
+            /// Send the pipeline and read the head of its response, returning the number of
+            /// responses that follow
+            async fn _run_pipeline_head(&mut self, pipeline: &Pipeline) -> SkyResult<usize> {
+                self.begin()?;
+                self.write_query(pipeline).await?;
+                self.flush_stream().await?;
                 loop {
-                    if 0usize == self.stream.read_buf(&mut self.buffer).await? {
-                        return Err(IoError::from(ErrorKind::ConnectionReset).into());
+                    match Parser::parse_pipeline_head(&self.buffer) {
+                        Ok(Some((count, forward_by))) => {
+                            self.buffer.advance(forward_by);
+                            return Ok(count);
+                        }
+                        Ok(None) => break,
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer().await?;
+                }
+                // not a pipelined response, but it still has to be consumed
+                match self._read_response().await? {
+                    // the server couldn't parse the pipeline
+                    RawResponse::SimpleQuery(Element::RespCode(RespCode::PacketError)) => {
+                        Err(SkyhashError::ProtocolError.into())
+                    }
+                    _ => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            async fn _read_pipeline_element(&mut self) -> SkyResult<Element> {
+                loop {
+                    match Parser::parse_pipeline_element(&self.buffer) {
+                        Ok((element, forward_by)) => {
+                            self.buffer.advance(forward_by);
+                            return Ok(element);
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer().await?;
+                }
+            }
+            async fn _send_query(&mut self, query: &Query) -> SkyResult<()> {
+                self.begin()?;
+                self.write_query(query).await?;
+                self.flush_stream().await
+            }
+            async fn _queue_query(&mut self, query: &Query) -> SkyResult<()> {
+                if query.is_empty() {
+                    return Err(crate::empty_query());
+                }
+                self.begin()?;
+                let frame = query.get_frame();
+                with_timeout(self.write_timeout, self.stream.write_all(&frame)).await?;
+                self.bytes_written += frame.len() as u64;
+                Ok(())
+            }
+            /// Write `query` to the stream, failing with [`SkyhashError::Timeout`] if it isn't
+            /// written within the write timeout
+            async fn write_query<Q: WriteQueryAsync<$inner>>(
+                &mut self,
+                query: &Q,
+            ) -> SkyResult<()> {
+                let written = with_timeout(self.write_timeout, query.write_async(&mut self.stream));
+                self.bytes_written += written.await? as u64;
+                Ok(())
+            }
+            /// Flush the stream, failing with [`SkyhashError::Timeout`] if it isn't flushed
+            /// within the write timeout. Once flushed, the query was written completely, so the
+            /// connection can be recovered with `discard_pending` if reading its response is
+            /// cancelled or times out
+            async fn flush_stream(&mut self) -> SkyResult<()> {
+                with_timeout(self.write_timeout, self.stream.flush()).await?;
+                self.recoverable = true;
+                Ok(())
+            }
+            /// Wait for a permit to run a query, if the number of queries in flight is limited
+            /// (see [`ConnectionBuilder::set_max_concurrency`](crate::ConnectionBuilder::set_max_concurrency)).
+            /// Waiting is cancellation safe since nothing was sent yet
+            async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+                match &self.limiter {
+                    // the semaphore is never closed
+                    Some(limiter) => Some(limiter.clone().acquire_owned().await.unwrap()),
+                    None => None,
+                }
+            }
+            /// Mark the start of an operation that isn't cancellation safe. If the future of the
+            /// operation is dropped before it completes, the connection will be poisoned
+            fn begin(&mut self) -> SkyResult<()> {
+                self.check_state()?;
+                self.in_flight = true;
+                self.recoverable = false;
+                Ok(())
+            }
+            /// Returns an error if this connection can't be used to run queries. This is also
+            /// the case if an earlier operation was cancelled before it could complete, since the
+            /// responses read from this connection might then belong to other queries
+            fn check_state(&mut self) -> SkyResult<()> {
+                self.state = self.state();
+                self.in_flight = false;
+                match self.state {
+                    ConnectionState::Ready => Ok(()),
+                    ConnectionState::Poisoned => Err(IoError::new(
+                        ErrorKind::NotConnected,
+                        "connection is poisoned by an earlier error or a cancelled query",
+                    )
+                    .into()),
+                    ConnectionState::Closed => Err(IoError::new(
+                        ErrorKind::NotConnected,
+                        "connection was closed by the server",
+                    )
+                    .into()),
+                }
+            }
+            cfg_dbg! {
+                /// Write the raw bytes of a frame to the stream, bypassing [`Query`] serialization
+                /// entirely. This is **not intended for daily use** but is useful for testing the edge
+                /// cases of the Skyhash protocol. The response can be read with [`Self::read_frame`]
+                pub async fn write_frame(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+                    self.stream.write_all(bytes).await?;
+                    self.bytes_written += bytes.len() as u64;
+                    self.stream.flush().await
+                }
+                /// Read the raw bytes of the next response frame from the stream. The parser is
+                /// only used to find where the frame ends; if the response is invalid (and hence
+                /// can't be delimited), all the bytes read so far are returned as-is
+                pub async fn read_frame(&mut self) -> std::io::Result<Vec<u8>> {
+                    loop {
+                        match Parser::parse(&self.buffer) {
+                            Ok((_, frame_len)) => return Ok(self.buffer.split_to(frame_len).to_vec()),
+                            Err(ParseError::NotEnough) => {}
+                            Err(_) => return Ok(self.buffer.split().to_vec()),
+                        }
+                        let read = self.stream.read_buf(&mut self.buffer).await?;
+                        if read == 0 {
+                            return Err(IoError::from(ErrorKind::ConnectionReset));
+                        }
+                        self.bytes_read += read as u64;
+                    }
+                }
+            }
+            /// Returns the state of this connection. Queries can only be run on a connection
+            /// that is [`ConnectionState::Ready`]
+            pub fn state(&self) -> ConnectionState {
+                if self.in_flight && self.state == ConnectionState::Ready {
+                    // an operation was cancelled before it could complete
+                    ConnectionState::Poisoned
+                } else {
+                    self.state
+                }
+            }
+            /// Returns the number of bytes written to this connection (since it was created or
+            /// since the counters were last reset with [`Self::reset_counters`]). For TLS
+            /// connections, this doesn't include the overhead of TLS itself
+            pub fn bytes_written(&self) -> u64 {
+                self.bytes_written
+            }
+            /// Returns the number of bytes read from this connection (since it was created or
+            /// since the counters were last reset with [`Self::reset_counters`]). For TLS
+            /// connections, this doesn't include the overhead of TLS itself
+            pub fn bytes_read(&self) -> u64 {
+                self.bytes_read
+            }
+            /// Reset the counters returned by [`Self::bytes_written`] and [`Self::bytes_read`]
+            pub fn reset_counters(&mut self) {
+                self.bytes_written = 0;
+                self.bytes_read = 0;
+            }
+            /// Returns the name of this connection, if one was set
+            pub fn name(&self) -> Option<&str> {
+                self.name.as_deref()
+            }
+            /// Returns a [`Prefixed`] wrapper around this connection whose
+            /// [`get`](Prefixed::get), [`set`](Prefixed::set) and [`del`](Prefixed::del)
+            /// prepend `prefix` to keys (but not to values). This is useful to keep the keys of
+            /// different tenants (or features) apart in the same table
+            pub fn with_prefix(&mut self, prefix: &str) -> Prefixed<'_, Self> {
+                Prefixed::new(self, prefix)
+            }
+            /// Limit the size of the responses read by this connection to `max` bytes (by
+            /// default, responses can be of any size). Once more than `max` bytes of a response
+            /// were read, the query fails with [`SkyhashError::ResponseTooLarge`] and the
+            /// connection is poisoned, so that a corrupted (or malicious) length can't make this
+            /// connection buffer data endlessly. Responses to queued queries that were read
+            /// together count as one response
+            ///
+            /// To cap the memory used by many connections together, use a [`MemoryBudget`]
+            pub fn set_max_response_size(&mut self, max: Option<usize>) {
+                self.max_response_size = max;
+            }
+            /// Charge the buffer to the memory budget (if any) and check it against the maximum
+            /// response size (if any). If either is exceeded, the buffered part of the response
+            /// is dropped and the connection is poisoned
+            fn charge_buffer(&mut self) -> SkyResult<()> {
+                let mut ret = match self.max_response_size {
+                    Some(max) if self.buffer.len() > max => {
+                        Err(SkyhashError::ResponseTooLarge.into())
+                    }
+                    _ => Ok(()),
+                };
+                if let (Ok(()), Some(reservation)) = (&ret, &mut self.reservation) {
+                    ret = reservation.resize(self.buffer.len());
+                }
+                if ret.is_err() {
+                    self.buffer.clear();
+                    if self.state == ConnectionState::Ready {
+                        self.state = ConnectionState::Poisoned;
+                    }
+                    // the rest of the response can't be told apart from the next one
+                    self.recoverable = false;
+                    self.release_buffer();
+                }
+                ret
+            }
+            /// Give back the part of the memory budget that the buffer doesn't use anymore
+            fn release_buffer(&mut self) {
+                if let Some(reservation) = &mut self.reservation {
+                    // the buffer only shrinks between two reads, so this can't fail
+                    let _ = reservation.resize(self.buffer.len());
+                }
+            }
+            /// Set the timeout for every read from the socket of this connection (by default,
+            /// reads wait until data arrives). Since the timeout applies to every read, a
+            /// response that stalls midway times out too. A query that times out returns
+            /// [`SkyhashError::Timeout`] and poisons the connection, since the rest of the
+            /// response might still arrive
+            pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+                self.read_timeout = timeout;
+            }
+            /// Set the timeout for every write (and flush) to the socket of this connection (by
+            /// default, writes wait until they complete). A query that times out returns
+            /// [`SkyhashError::Timeout`] and poisons the connection, since it might have been
+            /// written partially
+            pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+                self.write_timeout = timeout;
+            }
+            /// Ask the server for the version of the Skyhash protocol it speaks (with
+            /// `SYS INFO PROTOVER`) and return it, if it's compatible with the version spoken by
+            /// this client ([`PROTOCOL_VERSION`](crate::PROTOCOL_VERSION)). Otherwise,
+            /// [`SkyhashError::UnsupportedProtocol`] is returned and no other queries should be
+            /// run on this connection
+            pub async fn check_protocol(&mut self) -> SkyResult<f32> {
+                let query = Query::from("sys").arg("info").arg("protover");
+                let version = crate::check_protover(self.run_query_raw(query).await?)?;
+                self.protocol_version = Some(version);
+                Ok(version)
+            }
+            /// Returns the version of the Skyhash protocol spoken by the server, if it was
+            /// checked with [`Self::check_protocol`]
+            pub fn protocol_version(&self) -> Option<f32> {
+                self.protocol_version
+            }
+            /// Returns the version of the server (with `SYS INFO VERSION`). The version is only
+            /// queried the first time, and is then remembered by this connection
+            pub async fn server_version(&mut self) -> SkyResult<Version> {
+                if let Some(version) = &self.server_version {
+                    return Ok(version.clone());
+                }
+                let version = Version::from_response(self.run_query_raw(Version::query()).await?)?;
+                self.server_version = Some(version.clone());
+                Ok(version)
+            }
+            /// Switch to the provided entity (with `USE`), unless this connection is already
+            /// known to use it, in which case no query is sent. The Skyhash protocol can't target
+            /// an entity per query, so this saves the round trip of redundant `USE`s when
+            /// switching between a few entities (for example, one per tenant)
+            ///
+            /// The current entity is only known after switching with this method (or after
+            /// connecting with a [`ConnectionBuilder`](crate::ConnectionBuilder)). Running a `USE`
+            /// any other way (except in a [`Pipeline`]) forgets the current entity, so the next
+            /// call always switches
+            pub async fn use_entity(&mut self, entity: &str) -> SkyResult<()> {
+                if self.entity.as_deref() != Some(entity) {
+                    crate::ddl::AsyncDdl::switch(self, entity).await?;
+                    self.entity = Some(entity.to_owned());
+                }
+                Ok(())
+            }
+            /// Returns the entity this connection is known to use. See [`Self::use_entity`]
+            pub fn entity(&self) -> Option<&str> {
+                self.entity.as_deref()
+            }
+            /// Record the errors that affect the reusability of this connection
+            fn track<T>(&mut self, ret: SkyResult<T>) -> SkyResult<T> {
+                self.release_buffer();
+                match &ret {
+                    Ok(_) | Err(Error::ParseError(_)) | Err(Error::ConfigurationError(_)) => {}
+                    Err(Error::SkyError(e)) if !matches!(e, SkyhashError::Timeout) => {
+                        self.protocol_error = true
                     }
+                    // I/O (or TLS) errors and timeouts leave the stream in an unknown state. Only
+                    // read timeouts (see `flush_stream`) can be recovered from
+                    Err(e) => {
+                        if self.state == ConnectionState::Ready {
+                            self.state = ConnectionState::Poisoned;
+                            if !matches!(e, Error::SkyError(SkyhashError::Timeout)) {
+                                self.recoverable = false;
+                            }
+                        }
+                    }
+                }
+                match (ret, &self.name) {
+                    (Err(Error::IoError(e)), Some(name)) => Err(IoError::new(
+                        e.kind(),
+                        format!("connection '{}': {}", name, e),
+                    )
+                    .into()),
+                    (ret, _) => ret,
+                }
+            }
+            async fn _read_response(&mut self) -> SkyResult<RawResponse> {
+                loop {
+                    // the buffer might already hold a complete response
                     match self.try_response() {
                         Ok((query, forward_by)) => {
                             self.buffer.advance(forward_by);
                             return Ok(query);
                         }
-                        Err(e) => match e {
-                            ParseError::NotEnough => (),
-                            ParseError::BadPacket => {
-                                self.buffer.clear();
-                                return Err(SkyhashError::InvalidResponse.into());
-                            }
-                            ParseError::DataTypeError => {
-                                return Err(SkyhashError::ParseError.into())
-                            }
-                            ParseError::UnknownDatatype => {
-                                return Err(SkyhashError::UnknownDataType.into())
-                            }
-                        },
+                        Err(e) => self.check_parse_error(e)?,
                     }
+                    self.fill_buffer().await?;
                 }
             }
+            async fn _peek_response_header(&mut self) -> SkyResult<ResponseHeader> {
+                loop {
+                    match Parser::parse_header(&self.buffer) {
+                        Ok(header) => return Ok(header),
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer().await?;
+                }
+            }
+            async fn _peek_element_type(&mut self) -> SkyResult<Option<ElementType>> {
+                loop {
+                    match Parser::parse_element_type(&self.buffer) {
+                        Ok(element_type) => return Ok(element_type),
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer().await?;
+                }
+            }
+            /// Drop the response being read when its end can't be found, along with anything
+            /// else in the buffer. The rest of it may still arrive, so the connection is poisoned
+            /// to keep later queries from reading it as their response
+            fn drop_frame(&mut self) {
+                self.buffer.clear();
+                if self.state == ConnectionState::Ready {
+                    self.state = ConnectionState::Poisoned;
+                }
+                self.recoverable = false;
+            }
+            /// Returns an error if the parse error can't be fixed by reading more data
+            fn check_parse_error(&mut self, e: ParseError) -> SkyResult<()> {
+                match e {
+                    ParseError::NotEnough => Ok(()),
+                    ParseError::BadPacket => {
+                        self.drop_frame();
+                        Err(SkyhashError::InvalidResponse.into())
+                    }
+                    ParseError::DataTypeError => {
+                        self.drop_frame();
+                        Err(SkyhashError::ParseError.into())
+                    }
+                    ParseError::UnknownDatatype(tsymbol) => {
+                        // the length of an unknown element can't be known
+                        self.drop_frame();
+                        Err(SkyhashError::UnknownDataType(tsymbol).into())
+                    }
+                    ParseError::BadDelimiter(byte) => {
+                        // the framing is lost, so whatever is buffered can't be parsed
+                        self.drop_frame();
+                        Err(SkyhashError::BadDelimiter(byte).into())
+                    }
+                }
+            }
+            /// Read more data from the stream into the buffer. If the server closed the
+            /// connection, it is marked as closed and [`SkyhashError::ConnectionClosed`] is
+            /// returned
+            async fn fill_buffer(&mut self) -> SkyResult<()> {
+                let read =
+                    with_timeout(self.read_timeout, self.stream.read_buf(&mut self.buffer)).await?;
+                if read == 0 {
+                    self.state = ConnectionState::Closed;
+                    return Err(SkyhashError::ConnectionClosed(self.name.clone()).into());
+                }
+                self.bytes_read += read as u64;
+                self.charge_buffer()
+            }
             /// This function is a subroutine of `run_query` used to parse the response packet
             fn try_response(&mut self) -> Result<(RawResponse, usize), ParseError> {
-                Parser::parse(&self.buffer)
+                if self.validate_utf8 {
+                    Parser::parse(&self.buffer)
+                } else {
+                    Parser::parse_lossy_utf8(&self.buffer)
+                }
             }
         }
         impl crate::actions::AsyncSocket for $ty {
-            fn run(&mut self, q: Query) -> crate::AsyncResult<SkyQueryResult> {
-                Box::pin(async move { self.run_query_raw(&q).await })
+            fn run(&mut self, mut q: Query) -> crate::AsyncResult<SkyQueryResult> {
+                if self.uppercase_actions {
+                    q.uppercase_action();
+                }
+                Box::pin(async move {
+                    match self.compression {
+                        Some(compression) => self
+                            .run_query_raw(&compression.compress_query(q))
+                            .await
+                            .map(|element| compression.decompress_element(element)),
+                        None => self.run_query_raw(&q).await,
+                    }
+                })
+            }
+        }
+        impl Prefixed<'_, $ty> {
+            /// Get the value of the prefixed key. See [`AsyncActions::get`]
+            pub async fn get<T: FromSkyhashBytes>(
+                &mut self,
+                key: impl IntoSkyhashBytes,
+            ) -> SkyResult<T> {
+                let key = self.key(key);
+                self.con.get(key).await
+            }
+            /// Set the value of the prefixed key. See [`AsyncActions::set`]
+            pub async fn set(
+                &mut self,
+                key: impl IntoSkyhashBytes,
+                value: impl IntoSkyhashBytes,
+            ) -> SkyResult<bool> {
+                let key = self.key(key);
+                self.con.set(key, value).await
+            }
+            /// Delete the prefixed key, returning the number of keys that were deleted. See
+            /// [`AsyncActions::del`]
+            pub async fn del(&mut self, key: impl IntoSkyhashBytes) -> SkyResult<u64> {
+                let key = self.key(key);
+                self.con.del(key).await
+            }
+        }
+    };
+}
+
+/// Implements the methods that the [`ConnectionBuilder`](crate::ConnectionBuilder) and the pools
+/// use to set up connections, for the connection types they make
+macro_rules! impl_async_builder_methods {
+    ($ty:ty) => {
+        impl $ty {
+            pub(crate) fn set_name(&mut self, name: Option<Arc<str>>) {
+                self.name = name;
+            }
+            pub(crate) fn set_query_hook(&mut self, query_hook: Option<QueryHook>) {
+                self.query_hook = query_hook;
+            }
+            pub(crate) fn set_uppercase_actions(&mut self, uppercase_actions: bool) {
+                self.uppercase_actions = uppercase_actions;
+            }
+            /// See [`ConnectionBuilder::set_compression`](crate::ConnectionBuilder::set_compression)
+            pub(crate) fn set_compression(&mut self, compression: Option<Compression>) {
+                self.compression = compression;
+            }
+            /// See [`ConnectionBuilder::set_validate_utf8`](crate::ConnectionBuilder::set_validate_utf8)
+            pub(crate) fn set_validate_utf8(&mut self, validate_utf8: bool) {
+                self.validate_utf8 = validate_utf8;
+            }
+            pub(crate) fn set_memory_budget(&mut self, budget: Option<MemoryBudget>) {
+                self.reservation = budget.map(Reservation::new);
+            }
+            pub(crate) fn set_limiter(&mut self, limiter: Option<Arc<Semaphore>>) {
+                self.limiter = limiter;
+            }
+            /// Returns true if this connection should no longer be used as per the provided
+            /// error policy
+            pub(crate) fn is_broken(&self, policy: ErrorPolicy) -> bool {
+                match policy {
+                    ErrorPolicy::KeepOnProtocolError => self.state() != ConnectionState::Ready,
+                    ErrorPolicy::DropOnAnyError => {
+                        self.state() != ConnectionState::Ready || self.protocol_error
+                    }
+                }
+            }
+            /// Poison this connection (unless it's already closed), so that pools drop it
+            pub(crate) fn poison(&mut self) {
+                if self.state == ConnectionState::Ready {
+                    self.state = ConnectionState::Poisoned;
+                }
+                self.recoverable = false;
             }
         }
     };
 }
 
+/// Run `query` on all the `connections` concurrently and return the results in the same order
+/// as the connections. An error on one connection doesn't stop the query from being run on the
+/// others
+///
+/// ## Example
+/// ```no_run
+/// use skytable::{aio, query};
+///
+/// async fn run() {
+///     let mut shards = vec![
+///         aio::Connection::new("10.0.0.1", 2003).await.unwrap(),
+///         aio::Connection::new("10.0.0.2", 2003).await.unwrap(),
+///     ];
+///     for result in aio::fan_out(&mut shards, &query!("flushdb")).await {
+///         result.unwrap();
+///     }
+/// }
+/// ```
+pub async fn fan_out<C: AsyncSocket>(connections: &mut [C], query: &Query) -> Vec<SkyQueryResult> {
+    futures_util::future::join_all(connections.iter_mut().map(|con| con.run(query.clone()))).await
+}
+
 cfg_async!(
     /// An asynchronous database connection over Skyhash/TCP
     pub struct Connection {
         stream: BufWriter<TcpStream>,
         buffer: BytesMut,
+        state: ConnectionState,
+        protocol_error: bool,
+        recoverable: bool,
+        in_flight: bool,
+        bytes_written: u64,
+        bytes_read: u64,
+        name: Option<Arc<str>>,
+        query_hook: Option<QueryHook>,
+        uppercase_actions: bool,
+        compression: Option<Compression>,
+        protocol_version: Option<f32>,
+        server_version: Option<Version>,
+        validate_utf8: bool,
+        entity: Option<String>,
+        reservation: Option<Reservation>,
+        max_response_size: Option<usize>,
+        limiter: Option<Arc<Semaphore>>,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
     }
 
     impl Connection {
         /// Create a new connection to a Skytable instance hosted on `host` and running on `port`
         pub async fn new(host: &str, port: u16) -> SkyResult<Self> {
-            let stream = TcpStream::connect((host, port)).await?;
+            Self::_new(host, port, None, None, None, AddressFamily::Any, None).await
+        }
+        /// Create a new connection using the details in the provided URL, authenticating and
+        /// selecting the entity if provided. See [`ConnectionBuilder::from_url`] for the format
+        /// of the URL
+        ///
+        /// [`ConnectionBuilder::from_url`]: crate::ConnectionBuilder::from_url
+        pub async fn from_url(url: &str) -> SkyResult<Self> {
+            crate::ConnectionBuilder::from_url(url)?
+                .get_async_connection()
+                .await
+        }
+        /// Create a new connection to a Skytable instance hosted on `host` and running on
+        /// `port`, retrying until the server responds to a `HEYA` or `total_timeout` elapses.
+        /// This is useful when the application and the database are started at the same time
+        /// (for example, in containers). The delay between two attempts starts at 50 ms and is
+        /// doubled after every attempt, up to a second
+        ///
+        /// If the server isn't ready in time, the error of the last attempt is returned (or
+        /// [`SkyhashError::Timeout`] if the last attempt didn't complete)
+        pub async fn connect_ready(
+            host: &str,
+            port: u16,
+            total_timeout: Duration,
+        ) -> SkyResult<Self> {
+            let deadline = Instant::now() + total_timeout;
+            let mut delay = crate::READY_RETRY_DELAY;
+            loop {
+                let e = match timeout_at(deadline, Self::try_connect_ready(host, port)).await {
+                    Ok(Ok(con)) => return Ok(con),
+                    Ok(Err(e)) => e,
+                    Err(_) => return Err(SkyhashError::Timeout.into()),
+                };
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(e);
+                }
+                sleep_until((now + delay).min(deadline)).await;
+                delay = (delay * 2).min(crate::READY_MAX_RETRY_DELAY);
+            }
+        }
+        async fn try_connect_ready(host: &str, port: u16) -> SkyResult<Self> {
+            let mut con = Self::new(host, port).await?;
+            let ret = con.run_query_raw(Query::from("heya")).await;
+            crate::check_heya(ret?)?;
+            Ok(con)
+        }
+        /// Returns the address of the server this connection is connected to
+        pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.get_ref().peer_addr()
+        }
+        /// Returns the local address of this connection, which is useful to match the
+        /// connection with the logs of the server
+        pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.get_ref().local_addr()
+        }
+        /// Enable or disable Nagle's algorithm (`TCP_NODELAY`) on the socket of this
+        /// connection. With Nagle's algorithm disabled, small writes are sent right away instead
+        /// of being coalesced
+        pub fn set_nodelay(&self, nodelay: bool) -> std::io::Result<()> {
+            self.stream.get_ref().set_nodelay(nodelay)
+        }
+        /// Set the size of the send buffer (`SO_SNDBUF`) of the socket of this connection. A
+        /// larger buffer lets large pipelines be written without waiting for the server to
+        /// read them. The OS may round (or cap) the size
+        pub fn set_send_buffer_size(&self, size: usize) -> std::io::Result<()> {
+            SockRef::from(self.stream.get_ref()).set_send_buffer_size(size)
+        }
+        /// Cork (or uncork) the socket of this connection (`TCP_CORK`). While the socket is
+        /// corked, only full packets are sent, so that many small writes (like queries sent with
+        /// `send_query` or `queue_query`) are coalesced. Uncorking sends whatever is left, so **the socket has
+        /// to be uncorked before reading the responses**; otherwise the last queries are only
+        /// sent once the OS gives up waiting (after 200ms on Linux)
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        pub fn set_cork(&self, cork: bool) -> std::io::Result<()> {
+            SockRef::from(self.stream.get_ref()).set_tcp_cork(cork)
+        }
+        pub(crate) async fn _new(
+            host: &str,
+            port: u16,
+            local_addr: Option<SocketAddr>,
+            proxy: Option<&Socks5Proxy>,
+            dns_cache: Option<&DnsCache>,
+            address_family: AddressFamily,
+            connect_timeout: Option<Duration>,
+        ) -> SkyResult<Self> {
+            let stream = connect(
+                host,
+                port,
+                local_addr,
+                proxy,
+                dns_cache,
+                address_family,
+                connect_timeout,
+            )
+            .await?;
             Ok(Connection {
                 stream: BufWriter::new(stream),
                 buffer: BytesMut::with_capacity(BUF_CAP),
+                state: ConnectionState::Ready,
+                protocol_error: false,
+                recoverable: false,
+                in_flight: false,
+                bytes_written: 0,
+                bytes_read: 0,
+                name: None,
+                query_hook: None,
+                uppercase_actions: false,
+                compression: None,
+                protocol_version: None,
+                server_version: None,
+                validate_utf8: true,
+                entity: None,
+                reservation: None,
+                max_response_size: None,
+                limiter: None,
+                read_timeout: None,
+                write_timeout: None,
             })
         }
     }
     impl_async_methods!(Connection, BufWriter<TcpStream>);
+    impl_async_builder_methods!(Connection);
 );
 
 cfg_async_ssl_any!(
     use tokio_openssl::SslStream;
-    use openssl::ssl::{SslContext, SslMethod, Ssl};
+    use crate::TlsConfig;
     use core::pin::Pin;
-    use crate::error::Error;
 
     /// An asynchronous database connection over Skyhash/TLS
     pub struct TlsConnection {
         stream: SslStream<TcpStream>,
-        buffer: BytesMut
+        buffer: BytesMut,
+        state: ConnectionState,
+        protocol_error: bool,
+        recoverable: bool,
+        in_flight: bool,
+        bytes_written: u64,
+        bytes_read: u64,
+        name: Option<Arc<str>>,
+        query_hook: Option<QueryHook>,
+        uppercase_actions: bool,
+        compression: Option<Compression>,
+        protocol_version: Option<f32>,
+        server_version: Option<Version>,
+        validate_utf8: bool,
+        entity: Option<String>,
+        reservation: Option<Reservation>,
+        max_response_size: Option<usize>,
+        limiter: Option<Arc<Semaphore>>,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
     }
 
     impl TlsConnection {
         /// Pass the `host` and `port` and the path to the CA certificate to use for TLS
         pub async fn new(host: &str, port: u16, sslcert: &str) -> Result<Self, Error> {
-            let mut ctx = SslContext::builder(SslMethod::tls_client())?;
-            ctx.set_ca_file(sslcert)?;
-            let ssl = Ssl::new(&ctx.build())?;
-            let stream = TcpStream::connect((host, port)).await?;
+            let tls = TlsConfig::new(sslcert);
+            Self::_new(host, port, &tls, None, None, None, AddressFamily::Any, None).await
+        }
+        /// Pass the `host` and `port`, the path to the CA certificate and the paths to the
+        /// (PEM encoded) client certificate and private key to use for mutual TLS
+        pub async fn new_with_client_cert(
+            host: &str,
+            port: u16,
+            sslcert: &str,
+            client_cert: &str,
+            client_key: &str,
+        ) -> Result<Self, Error> {
+            let tls = TlsConfig {
+                client_cert: Some((client_cert, client_key)),
+                ..TlsConfig::new(sslcert)
+            };
+            Self::_new(host, port, &tls, None, None, None, AddressFamily::Any, None).await
+        }
+        /// Returns the address of the server this connection is connected to
+        pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.get_ref().peer_addr()
+        }
+        /// Returns the local address of this connection, which is useful to match the
+        /// connection with the logs of the server
+        pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.get_ref().local_addr()
+        }
+        #[allow(clippy::too_many_arguments)]
+        pub(crate) async fn _new(
+            host: &str,
+            port: u16,
+            tls: &TlsConfig<'_>,
+            local_addr: Option<SocketAddr>,
+            proxy: Option<&Socks5Proxy>,
+            dns_cache: Option<&DnsCache>,
+            address_family: AddressFamily,
+            connect_timeout: Option<Duration>,
+        ) -> Result<Self, Error> {
+            let ssl = tls.new_ssl()?;
+            let stream = connect(
+                host,
+                port,
+                local_addr,
+                proxy,
+                dns_cache,
+                address_family,
+                connect_timeout,
+            )
+            .await?;
             let mut stream = SslStream::new(ssl, stream)?;
-            Pin::new(&mut stream).connect().await?;
+            Pin::new(&mut stream)
+                .connect()
+                .await
+                .map_err(Error::TlsHandshakeError)?;
             Ok(Self {
                 stream,
                 buffer: BytesMut::with_capacity(BUF_CAP),
+                state: ConnectionState::Ready,
+                protocol_error: false,
+                recoverable: false,
+                in_flight: false,
+                bytes_written: 0,
+                bytes_read: 0,
+                name: None,
+                query_hook: None,
+                uppercase_actions: false,
+                compression: None,
+                protocol_version: None,
+                server_version: None,
+                validate_utf8: true,
+                entity: None,
+                reservation: None,
+                max_response_size: None,
+                limiter: None,
+                read_timeout: None,
+                write_timeout: None,
             })
         }
     }
     impl_async_methods!(TlsConnection, SslStream<TcpStream>);
+    impl_async_builder_methods!(TlsConnection);
+);
+
+cfg_async!(
+    /// A connection that reconnects to the server when the connection is lost (for example,
+    /// because the server restarted), so that long-lived clients recover by themselves. See
+    /// [`sync::ReconnectingConnection`](crate::sync::ReconnectingConnection) for which queries
+    /// are retried
+    ///
+    /// All the [async actions](crate::actions::AsyncActions) can be run on this connection
+    pub struct ReconnectingConnection {
+        builder: ConnectionBuilder,
+        policy: RetryPolicy,
+        con: Option<Connection>,
+        reconnects: u64,
+        retry_writes: bool,
+    }
+
+    impl ReconnectingConnection {
+        /// Connect to the server at `host:port`, reconnecting as per the provided policy
+        pub async fn new(host: &str, port: u16, policy: RetryPolicy) -> SkyResult<Self> {
+            let builder = ConnectionBuilder::new()
+                .set_host(host.to_owned())
+                .set_port(port);
+            Self::with_builder(builder, policy).await
+        }
+        /// Connect with the provided builder, which is used again for every reconnect (so that
+        /// the new connections use the same entity, credentials and settings)
+        pub async fn with_builder(
+            builder: ConnectionBuilder,
+            policy: RetryPolicy,
+        ) -> SkyResult<Self> {
+            let con = builder.get_async_connection().await?;
+            Ok(Self {
+                builder,
+                policy,
+                con: Some(con),
+                reconnects: 0,
+                retry_writes: false,
+            })
+        }
+        /// Retry every query after reconnecting, not only the ones that only read data. Only
+        /// enable this if applying any of the queries twice is harmless
+        pub fn set_retry_writes(&mut self, retry_writes: bool) {
+            self.retry_writes = retry_writes;
+        }
+        /// Returns the number of times this connection reconnected
+        pub fn reconnects(&self) -> u64 {
+            self.reconnects
+        }
+        /// Returns the current connection, reconnecting first if it can't be used anymore
+        pub async fn connection(&mut self) -> SkyResult<&mut Connection> {
+            if !matches!(&self.con, Some(con) if con.state() == ConnectionState::Ready) {
+                self.con = None;
+                let mut retry = 0;
+                let con = loop {
+                    match self.builder.get_async_connection().await {
+                        Ok(con) => break con,
+                        Err(Error::IoError(_) | Error::SkyError(SkyhashError::Timeout))
+                        if retry < self.policy.max_retries() =>
+                    {
+                            tokio::time::sleep(self.policy.delay(retry)).await;
+                            retry += 1;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                };
+                self.reconnects += 1;
+                self.con = Some(con);
+            }
+            Ok(self.con.as_mut().unwrap())
+        }
+        /// Runs a query on the current connection like [`Connection::run_query_raw`],
+        /// reconnecting (and retrying the query, if it can be retried) if the connection is lost
+        pub async fn run_query_raw<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<Element> {
+            self._run(query.as_ref(), false).await
+        }
+        /// Runs a query using [`Self::run_query_raw`] and attempts to return a type provided by
+        /// the user
+        pub async fn run_query<T: FromSkyhashBytes, Q: AsRef<Query>>(
+            &mut self,
+            query: Q,
+        ) -> SkyResult<T> {
+            self.run_query_raw(query).await?.try_element_into()
+        }
+        /// Run `query` (as an action, if `action` is set), retrying it on a new connection if
+        /// the connection is lost and the query can be retried
+        async fn _run(&mut self, query: &Query, action: bool) -> SkyResult<Element> {
+            let retry_query = self.retry_writes || crate::is_read_only(query);
+            let mut retry = 0;
+            loop {
+                let con = self.connection().await?;
+                let ret = if action {
+                    AsyncSocket::run(con, query.clone()).await
+                } else {
+                    con.run_query_raw(query).await
+                };
+                match &ret {
+                    Err(e)
+                        if crate::is_connection_lost(e)
+                            && retry_query
+                            && retry < self.policy.max_retries() =>
+                    {
+                        tokio::time::sleep(self.policy.delay(retry)).await;
+                        retry += 1;
+                    }
+                    _ => return ret,
+                }
+            }
+        }
+    }
+
+    impl AsyncSocket for ReconnectingConnection {
+        fn run(&mut self, q: Query) -> crate::AsyncResult<'_, SkyQueryResult> {
+            Box::pin(async move { self._run(&q, true).await })
+        }
+    }
 );
+
+cfg_async_unix!(
+    mod unix {
+        use super::*;
+        use std::path::Path;
+        use tokio::net::UnixStream;
+
+        /// An asynchronous database connection over Skyhash on a UNIX domain socket. See
+        /// [`Connection::new_unix`]
+        pub struct UnixConnection {
+            stream: BufWriter<UnixStream>,
+            buffer: BytesMut,
+            state: ConnectionState,
+            protocol_error: bool,
+            recoverable: bool,
+            in_flight: bool,
+            bytes_written: u64,
+            bytes_read: u64,
+            name: Option<Arc<str>>,
+            query_hook: Option<QueryHook>,
+            uppercase_actions: bool,
+            compression: Option<Compression>,
+            protocol_version: Option<f32>,
+            server_version: Option<Version>,
+            validate_utf8: bool,
+            entity: Option<String>,
+            reservation: Option<Reservation>,
+            max_response_size: Option<usize>,
+            limiter: Option<Arc<Semaphore>>,
+            read_timeout: Option<Duration>,
+            write_timeout: Option<Duration>,
+        }
+
+        impl Connection {
+            /// Create a new connection to a Skytable instance listening on the UNIX domain socket
+            /// at `path`. This avoids the overhead of the TCP loopback when the client runs on the
+            /// same host as the server; otherwise, the connection behaves exactly like a
+            /// [`Connection`]
+            pub async fn new_unix(path: impl AsRef<Path>) -> SkyResult<UnixConnection> {
+                let stream = UnixStream::connect(path).await?;
+                Ok(UnixConnection {
+                    stream: BufWriter::new(stream),
+                    buffer: BytesMut::with_capacity(BUF_CAP),
+                    state: ConnectionState::Ready,
+                    protocol_error: false,
+                    recoverable: false,
+                    in_flight: false,
+                    bytes_written: 0,
+                    bytes_read: 0,
+                    name: None,
+                    query_hook: None,
+                    uppercase_actions: false,
+                    compression: None,
+                    protocol_version: None,
+                    server_version: None,
+                    validate_utf8: true,
+                    entity: None,
+                    reservation: None,
+                    max_response_size: None,
+                    limiter: None,
+                    read_timeout: None,
+                    write_timeout: None,
+                })
+            }
+        }
+        impl_async_methods!(UnixConnection, BufWriter<UnixStream>);
+    }
+    pub use self::unix::UnixConnection;
+);
+
+#[tokio::test]
+async fn test_cancelled_query_poisons_connection() {
+    use tokio::time::{sleep, timeout, Duration};
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        // the query is `*1\n4\nheya`
+        let mut buf = [0u8; 9];
+        stream.read_exact(&mut buf).await.unwrap();
+        // respond only after the client gave up
+        sleep(Duration::from_millis(100)).await;
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    let query = crate::query!("heya");
+    let ret = timeout(Duration::from_millis(20), con.run_query_raw(&query)).await;
+    assert!(ret.is_err(), "query should have timed out");
+    assert!(con.is_broken(ErrorPolicy::KeepOnProtocolError));
+    // the pending response must not be mistaken for the response to this query
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    let e = con.run_query_raw(&query).await.unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    // but the connection can be recovered
+    con.discard_pending().await.unwrap();
+    assert_eq!(con.state(), ConnectionState::Ready);
+    let ret = con.run_query_raw(&query).await.unwrap();
+    assert_eq!(ret, Element::String("HEY!".to_owned()));
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unknown_data_type() {
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        stream.read_exact(&mut [0u8; 9]).await.unwrap();
+        stream.write_all(b"*~4\nHEY!").await.unwrap();
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    let e = con.run_query_raw(crate::query!("heya")).await.unwrap_err();
+    assert_eq!(e, SkyhashError::UnknownDataType(b'~').into());
+    // the rest of the element can't be skipped, so the connection can't be used anymore
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    let e = con.run_query_raw(crate::query!("heya")).await.unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_discard_pending_unrecoverable() {
+    use tokio::time::{sleep, Duration};
+    let (port, server) = crate::mock::serve_async(|listener| async move {
+        for response in [&b"*~4\nHEY!"[..], b"*+64\n"] {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.read_exact(&mut [0u8; 9]).await.unwrap();
+            stream.write_all(response).await.unwrap();
+            stream.write_all(&[b'a'; 64]).await.unwrap();
+            // something that looks like a response, which mustn't be read as one
+            sleep(Duration::from_millis(50)).await;
+            stream.write_all(b"*+4\nHEY!").await.unwrap();
+            stream.read_to_end(&mut Vec::new()).await.unwrap();
+        }
+    })
+    .await;
+    // an unknown data type
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    let e = con.run_query_raw(crate::query!("heya")).await.unwrap_err();
+    assert_eq!(e, SkyhashError::UnknownDataType(b'~').into());
+    let e = con.discard_pending().await.unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    drop(con);
+    // a response that is too large
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    con.set_max_response_size(Some(32));
+    let e = con.run_query_raw(crate::query!("heya")).await.unwrap_err();
+    assert_eq!(e, SkyhashError::ResponseTooLarge.into());
+    let e = con.discard_pending().await.unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    drop(con);
+    server.await.unwrap();
+}
+
+cfg_dbg!(
+    #[tokio::test]
+    async fn test_raw_frames() {
+        let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+            // this is a multi-query frame
+            let mut buf = [0u8; 15];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"*2\n1\na*2\n1\nb1\nc");
+            stream.write_all(b"*!0\n*!1\n").await.unwrap();
+        })
+        .await;
+        let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+        con.write_frame(b"*2\n1\na*2\n1\nb1\nc").await.unwrap();
+        assert_eq!(con.read_frame().await.unwrap(), b"*!0\n");
+        assert_eq!(con.read_frame().await.unwrap(), b"*!1\n");
+        server.await.unwrap();
+    }
+);
+
+#[tokio::test]
+async fn test_read_timeout() {
+    use tokio::sync::oneshot;
+    let (done_tx, done_rx) = oneshot::channel::<()>();
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        stream.read_exact(&mut [0u8; 9]).await.unwrap();
+        // the response stalls midway
+        stream.write_all(b"*+4\nHE").await.unwrap();
+        done_rx.await.unwrap();
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    con.set_read_timeout(Some(Duration::from_millis(20)));
+    let e = con.run_query_raw(crate::query!("heya")).await.unwrap_err();
+    assert_eq!(e, SkyhashError::Timeout.into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    done_tx.send(()).unwrap();
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_write_timeout() {
+    use tokio::sync::oneshot;
+    let (done_tx, done_rx) = oneshot::channel::<()>();
+    let (port, server) = crate::mock::serve_once_async(|stream| async move {
+        // nothing is read, so the socket buffers fill up
+        done_rx.await.unwrap();
+        drop(stream);
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    con.set_write_timeout(Some(Duration::from_millis(20)));
+    let value = "x".repeat(64 * 1024 * 1024);
+    let e = con
+        .run_query_raw(crate::query!("set", "x", value))
+        .await
+        .unwrap_err();
+    assert_eq!(e, SkyhashError::Timeout.into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    done_tx.send(()).unwrap();
+    server.await.unwrap();
+}
+
+#[cfg(feature = "unix")]
+#[tokio::test]
+async fn test_unix_connection() {
+    use tokio::net::UnixListener;
+    let path = std::env::temp_dir().join(format!("skytable-test-aio-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream.read_exact(&mut [0u8; 9]).await.unwrap();
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+    });
+    let mut con = Connection::new_unix(&path).await.unwrap();
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).await.unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    server.await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_queue_query() {
+    use tokio::sync::oneshot;
+    use tokio::time::timeout;
+    let (queued_tx, queued_rx) = oneshot::channel();
+    let (checked_tx, checked_rx) = oneshot::channel();
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        queued_rx.await.unwrap();
+        // nothing is written before the flush
+        let mut buf = [0u8; 21];
+        let ret = timeout(Duration::from_millis(20), stream.read(&mut buf)).await;
+        assert!(ret.is_err(), "queued queries shouldn't be flushed");
+        checked_tx.send(()).unwrap();
+        // the queries are `*1\n4\nheya` and `*1\n7\nflushdb`
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"*1\n4\nheya*1\n7\nflushdb");
+        stream.write_all(b"*+4\nHEY!*!0\n").await.unwrap();
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    con.queue_query(crate::query!("heya")).await.unwrap();
+    con.queue_query(crate::query!("flushdb")).await.unwrap();
+    assert_eq!(con.bytes_written(), 21);
+    queued_tx.send(()).unwrap();
+    checked_rx.await.unwrap();
+    con.flush().await.unwrap();
+    assert_eq!(
+        con.read_response().await.unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    assert_eq!(
+        con.read_response().await.unwrap(),
+        Element::RespCode(RespCode::Okay)
+    );
+    assert_eq!(con.state(), ConnectionState::Ready);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_run_pipeline_stream() {
+    use futures_util::StreamExt;
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        // the pipeline is `$2\n1\n4\nheya1\n7\nsys:get`
+        let mut buf = [0u8; 22];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(b"$2\n+4\nHEY!").await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        stream.write_all(b"!0\n").await.unwrap();
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    let pipeline = Pipeline::new()
+        .append(crate::query!("heya"))
+        .append(crate::query!("sys:get"));
+    let stream = con.run_pipeline_stream(&pipeline).await.unwrap();
+    let responses: Vec<_> = stream.collect().await;
+    assert_eq!(
+        responses,
+        vec![
+            Ok(Element::String("HEY!".to_owned())),
+            Ok(Element::RespCode(RespCode::Okay))
+        ]
+    );
+    assert_eq!(con.state(), ConnectionState::Ready);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_empty_pipeline() {
+    use futures_util::StreamExt;
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        // nothing is written, so the server never sees an empty frame
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert!(received.is_empty());
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    assert_eq!(con.run_pipeline(Pipeline::new()).await.unwrap(), []);
+    assert_eq!(con.run_pipeline_checked(Pipeline::new()).await.unwrap(), []);
+    assert_eq!(con.run_pipeline_partial(Pipeline::new()).await.unwrap(), []);
+    assert_eq!(
+        con.run_pipeline_windowed(Pipeline::new(), 1).await.unwrap(),
+        []
+    );
+    let stream = con.run_pipeline_stream(Pipeline::new()).await.unwrap();
+    assert_eq!(stream.collect::<Vec<_>>().await, []);
+    assert_eq!(con.state(), ConnectionState::Ready);
+    drop(con);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_compression() {
+    use crate::types::RawString;
+    use crate::CompressionAlgorithm;
+    let compression = Compression::new(CompressionAlgorithm::Lz4).set_threshold(64);
+    let value = vec![b'x'; 1000];
+    let query = Query::from("set")
+        .arg("x")
+        .arg(RawString::from(value.clone()));
+    let query = compression.compress_query(query);
+    let sent = query.as_bytes();
+    let stored = query.iter_args().last().unwrap().to_vec();
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        let mut query = vec![0u8; sent.len()];
+        stream.read_exact(&mut query).await.unwrap();
+        assert_eq!(query, sent);
+        stream.write_all(b"*!0\n").await.unwrap();
+        // `*2\n3\nget1\nx`
+        stream.read_exact(&mut [0u8; 11]).await.unwrap();
+        let mut response = format!("*?{}\n", stored.len()).into_bytes();
+        response.extend(stored);
+        stream.write_all(&response).await.unwrap();
+        stream.read_to_end(&mut Vec::new()).await.unwrap();
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    con.set_compression(Some(compression));
+    assert!(con.set("x", RawString::from(value.clone())).await.unwrap());
+    let ret: Element = con.get("x").await.unwrap();
+    assert_eq!(ret, Element::Binstr(value));
+    drop(con);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_empty_query() {
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert!(received.is_empty());
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    let empty = Query::new().arg(Vec::<String>::new());
+    assert_eq!(con.run_query_raw(&empty).await, Err(crate::empty_query()));
+    assert_eq!(con.run_args(&[]).await, Err(crate::empty_query()));
+    assert_eq!(con.queue_query(&empty).await, Err(crate::empty_query()));
+    let pipeline = Pipeline::new()
+        .append(Query::from("get").arg("x"))
+        .append(empty);
+    assert_eq!(con.run_pipeline(&pipeline).await, Err(crate::empty_query()));
+    assert_eq!(
+        con.run_pipeline_windowed(&pipeline, 1).await,
+        Err(crate::empty_query())
+    );
+    assert_eq!(con.state(), ConnectionState::Ready);
+    drop(con);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_max_concurrency() {
+    use tokio::time::timeout;
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        stream.read_exact(&mut [0u8; 9]).await.unwrap();
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+    })
+    .await;
+    let limiter = Arc::new(Semaphore::new(1));
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    con.set_limiter(Some(limiter.clone()));
+    // the only permit is taken, so the query has to wait
+    let permit = limiter.clone().acquire_owned().await.unwrap();
+    let ret = timeout(
+        Duration::from_millis(20),
+        con.run_query_raw(crate::query!("heya")),
+    )
+    .await;
+    assert!(ret.is_err(), "the query shouldn't run without a permit");
+    let ret = timeout(
+        Duration::from_millis(20),
+        con.send_query(crate::query!("heya")),
+    )
+    .await;
+    assert!(ret.is_err(), "the query shouldn't be sent without a permit");
+    let ret = timeout(
+        Duration::from_millis(20),
+        con.queue_query(crate::query!("heya")),
+    )
+    .await;
+    assert!(ret.is_err(), "the query shouldn't be queued without a permit");
+    // nothing was sent, so the connection is still usable
+    assert_eq!(con.state(), ConnectionState::Ready);
+    drop(permit);
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).await.unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    assert_eq!(limiter.available_permits(), 1);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reconnecting_connection() {
+    use tokio::net::TcpListener;
+    async fn accept(listener: &TcpListener) -> TcpStream {
+        // every new connection switches to the default entity first
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut query = [0u8; 26];
+        stream.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"*2\n3\nuse15\ndefault:default");
+        stream.write_all(b"*!0\n").await.unwrap();
+        stream
+    }
+    let (port, server) = crate::mock::serve_async(|listener| async move {
+        let mut stream = accept(&listener).await;
+        stream.read_exact(&mut [0u8; 9]).await.unwrap();
+        drop(stream);
+        let mut stream = accept(&listener).await;
+        stream.read_exact(&mut [0u8; 9]).await.unwrap();
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+    })
+    .await;
+    let mut con = ReconnectingConnection::new("127.0.0.1", port, RetryPolicy::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).await.unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    assert_eq!(con.reconnects(), 1);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_responses() {
+    use futures_util::StreamExt;
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        // both responses arrive at once
+        stream.write_all(b"*+4\nHEY!*+4\nHEY!").await.unwrap();
+    })
+    .await;
+    let mut con = Connection::new("127.0.0.1", port).await.unwrap();
+    // the stream ends once the connection is closed
+    let responses: Vec<_> = con.responses().collect().await;
+    server.await.unwrap();
+    assert_eq!(
+        responses,
+        [
+            Ok(Element::String("HEY!".to_owned())),
+            Ok(Element::String("HEY!".to_owned())),
+            Err(SkyhashError::ConnectionClosed(None).into())
+        ]
+    );
+}
@@ -0,0 +1,147 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Memory budgets
+//!
+//! A cap on the memory used by the response buffers of a group of connections, so that many
+//! large responses being read at once can't exhaust the memory of the process
+//!
+
+use crate::error::SkyhashError;
+use crate::SkyResult;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug)]
+struct Usage {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+/// A budget for the memory used by the buffers that hold responses while they're read, shared
+/// by all the connections made with a [`ConnectionBuilder`](crate::ConnectionBuilder) it's set
+/// on (see [`ConnectionBuilder::set_memory_budget`](crate::ConnectionBuilder::set_memory_budget)).
+/// Clones share the same budget, so the same budget can be set on several builders (or pools)
+/// to cap all of them together
+///
+/// When reading a response would take the buffers of the connections over the limit, the
+/// query fails with [`SkyhashError::MemoryBudgetExceeded`] and the connection is poisoned
+/// (since the rest of the response wasn't read), which frees its buffer. The budget only
+/// covers buffered bytes: a response's memory is given back once it was parsed, so the
+/// [`Element`](crate::Element)s returned to the caller aren't counted
+///
+/// ## Example
+/// ```no_run
+/// use skytable::{ConnectionBuilder, MemoryBudget};
+///
+/// // at most 64 MiB of responses are buffered across all the connections
+/// let budget = MemoryBudget::new(64 * 1024 * 1024);
+/// let builder = ConnectionBuilder::new().set_memory_budget(budget.clone());
+/// let con = builder.get_connection().unwrap();
+/// println!("{} bytes in use", budget.used());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    usage: Arc<Usage>,
+}
+
+impl MemoryBudget {
+    /// Create a budget allowing at most `limit` bytes to be buffered at once
+    pub fn new(limit: usize) -> Self {
+        Self {
+            usage: Arc::new(Usage {
+                limit,
+                used: AtomicUsize::new(0),
+            }),
+        }
+    }
+    /// Returns the maximum number of bytes that can be buffered at once
+    pub fn limit(&self) -> usize {
+        self.usage.limit
+    }
+    /// Returns the number of bytes that are currently buffered by the connections sharing this
+    /// budget
+    pub fn used(&self) -> usize {
+        self.usage.used.load(Ordering::Acquire)
+    }
+    /// Take `bytes` out of the budget, returning `false` if that would exceed the limit
+    fn reserve(&self, bytes: usize) -> bool {
+        self.usage
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes)
+                    .filter(|&total| total <= self.usage.limit)
+            })
+            .is_ok()
+    }
+    fn release(&self, bytes: usize) {
+        self.usage.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+/// The part of a [`MemoryBudget`] held by a connection for its buffer, which is given back
+/// when this is dropped
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    budget: MemoryBudget,
+    reserved: usize,
+}
+
+impl Reservation {
+    pub(crate) fn new(budget: MemoryBudget) -> Self {
+        Self {
+            budget,
+            reserved: 0,
+        }
+    }
+    /// Grow or shrink the reservation to `len` bytes. If growing it would exceed the limit,
+    /// the reservation is left unchanged and [`SkyhashError::MemoryBudgetExceeded`] is returned
+    pub(crate) fn resize(&mut self, len: usize) -> SkyResult<()> {
+        if len > self.reserved {
+            if !self.budget.reserve(len - self.reserved) {
+                return Err(SkyhashError::MemoryBudgetExceeded.into());
+            }
+        } else {
+            self.budget.release(self.reserved - len);
+        }
+        self.reserved = len;
+        Ok(())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.release(self.reserved);
+    }
+}
+
+#[test]
+fn test_memory_budget() {
+    let budget = MemoryBudget::new(100);
+    let mut a = Reservation::new(budget.clone());
+    let mut b = Reservation::new(budget.clone());
+    a.resize(60).unwrap();
+    b.resize(40).unwrap();
+    assert_eq!(budget.used(), 100);
+    assert!(b.resize(41).is_err());
+    assert_eq!(budget.used(), 100);
+    a.resize(10).unwrap();
+    b.resize(90).unwrap();
+    drop(a);
+    assert_eq!(budget.used(), 90);
+    drop(b);
+    assert_eq!(budget.used(), 0);
+}
@@ -0,0 +1,401 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Client-side compression
+//!
+//! Skyhash has no notion of compressed values, so values are compressed by the client before
+//! they're sent and decompressed by the client after they're read. Compressed values start with
+//! a marker (followed by the algorithm and the length of the original value) so that plain
+//! values are never touched. The compressed data is an LZ4 block
+//!
+
+use crate::types::Array;
+use crate::Element;
+use crate::Query;
+
+/// The bytes that every compressed value starts with. `0xFF` never appears in valid UTF-8, so a
+/// string value can't be mistaken for a compressed value
+const MARKER: [u8; 4] = *b"\xffsky";
+/// The length of the header of a compressed value: the marker, the algorithm and the length of
+/// the original value (as a little-endian `u64`)
+const HEADER_LEN: usize = MARKER.len() + 1 + 8;
+/// The default size (in bytes) from which values are compressed
+const DEFAULT_THRESHOLD: usize = 4096;
+
+/// A compression algorithm that can be used with [`Compression`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompressionAlgorithm {
+    /// LZ4 (the block format), which is fast enough to be used for every large value
+    Lz4,
+}
+
+impl CompressionAlgorithm {
+    /// Returns the byte that identifies this algorithm in the header of compressed values
+    fn id(self) -> u8 {
+        match self {
+            Self::Lz4 => 1,
+        }
+    }
+}
+
+/// Client-side compression for large values, set with
+/// [`ConnectionBuilder::set_compression`](crate::ConnectionBuilder::set_compression)
+///
+/// Every argument of an [action](crate::actions) (except the action itself) that's at least
+/// [`threshold`](Compression::set_threshold) bytes long is compressed before the query is sent,
+/// if that makes it smaller. Compressed values in the responses to actions are decompressed
+/// before they're returned, while the other values are returned as they are. Queries run with
+/// `run_query` are always sent (and their responses returned) as-is
+///
+/// This is a convention of this library, not a feature of the server: the server stores the
+/// compressed bytes, so the values can only be read back by clients that enable compression.
+/// Since compressed values aren't valid UTF-8, they can only be stored in tables holding binary
+/// values. Keys are compressed too if they reach the threshold, so keep keys shorter than that
+///
+/// ## Example
+/// ```no_run
+/// use skytable::actions::Actions;
+/// use skytable::{Compression, CompressionAlgorithm, ConnectionBuilder};
+///
+/// let mut con = ConnectionBuilder::new()
+///     // values of 64 KiB or more are compressed
+///     .set_compression(Compression::new(CompressionAlgorithm::Lz4).set_threshold(64 * 1024))
+///     .get_connection()
+///     .unwrap();
+/// con.set("large", "x".repeat(1024 * 1024)).unwrap();
+/// let value: String = con.get("large").unwrap();
+/// assert_eq!(value.len(), 1024 * 1024);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    algorithm: CompressionAlgorithm,
+    threshold: usize,
+}
+
+impl Compression {
+    /// Compress values with `algorithm`. Values of at least 4 KiB are compressed; use
+    /// [`Compression::set_threshold`] to change this
+    pub fn new(algorithm: CompressionAlgorithm) -> Self {
+        Self {
+            algorithm,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+    /// Set the size (in bytes) from which values are compressed
+    pub fn set_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+    /// Returns the algorithm used to compress values
+    pub fn algorithm(&self) -> CompressionAlgorithm {
+        self.algorithm
+    }
+    /// Returns the size (in bytes) from which values are compressed
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+    /// Returns `query` with its large arguments compressed. The action is never compressed
+    pub(crate) fn compress_query(&self, query: Query) -> Query {
+        let compress = |arg: &[u8]| arg.len() >= self.threshold.max(HEADER_LEN);
+        if !query.iter_args().skip(1).any(compress) {
+            return query;
+        }
+        let mut compressed = Query::new();
+        for (i, arg) in query.iter_args().enumerate() {
+            let value = if i != 0 && compress(arg) {
+                self.compress(arg)
+            } else {
+                None
+            };
+            compressed._push_arg(value.unwrap_or_else(|| arg.to_vec()));
+        }
+        compressed
+    }
+    /// Returns the compressed form of `value` (with its header), or `None` if that isn't
+    /// smaller than `value`
+    fn compress(&self, value: &[u8]) -> Option<Vec<u8>> {
+        let mut compressed = Vec::with_capacity(HEADER_LEN + value.len() / 2);
+        compressed.extend_from_slice(&MARKER);
+        compressed.push(self.algorithm.id());
+        compressed.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        match self.algorithm {
+            CompressionAlgorithm::Lz4 => lz4::compress(value, &mut compressed),
+        }
+        if compressed.len() < value.len() {
+            Some(compressed)
+        } else {
+            None
+        }
+    }
+    /// Returns `element` with its compressed values (including the ones held by binary arrays)
+    /// decompressed
+    pub(crate) fn decompress_element(&self, element: Element) -> Element {
+        match element {
+            Element::Binstr(value) => Element::Binstr(decompress(value)),
+            Element::Str { raw } => match String::from_utf8(decompress(raw)) {
+                Ok(string) => Element::String(string),
+                Err(e) => Element::Str {
+                    raw: e.into_bytes(),
+                },
+            },
+            Element::Array(Array::Bin(values)) => Element::Array(Array::Bin(
+                values
+                    .into_iter()
+                    .map(|value| value.map(decompress))
+                    .collect(),
+            )),
+            Element::Array(Array::NonNullBin(values)) => Element::Array(Array::NonNullBin(
+                values.into_iter().map(decompress).collect(),
+            )),
+            other => other,
+        }
+    }
+}
+
+/// Returns the original bytes of `value` if it's a compressed value, or `value` itself if it
+/// isn't (or if it can't be decompressed)
+fn decompress(value: Vec<u8>) -> Vec<u8> {
+    if value.len() < HEADER_LEN || value[..MARKER.len()] != MARKER {
+        return value;
+    }
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&value[MARKER.len() + 1..HEADER_LEN]);
+    let len = u64::from_le_bytes(len) as usize;
+    let decompressed = match value[MARKER.len()] {
+        1 => lz4::decompress(&value[HEADER_LEN..], len),
+        _ => None,
+    };
+    decompressed.unwrap_or(value)
+}
+
+/// An implementation of the LZ4 block format (without the frame format)
+mod lz4 {
+    const MIN_MATCH: usize = 4;
+    /// The last literals of a block can't be part of a match
+    const LAST_LITERALS: usize = 5;
+    /// The last match must start at least this many bytes before the end of the block
+    const MF_LIMIT: usize = 12;
+    const MAX_OFFSET: usize = u16::MAX as usize;
+    const HASH_LOG: u32 = 12;
+
+    fn read_u32(input: &[u8], pos: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&input[pos..pos + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn hash(sequence: u32) -> usize {
+        (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+    }
+
+    fn write_length(out: &mut Vec<u8>, mut len: usize) {
+        while len >= 255 {
+            out.push(255);
+            len -= 255;
+        }
+        out.push(len as u8);
+    }
+
+    /// Write a sequence: `literals` followed by a match of `(offset, length)`, if any
+    fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+        let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+        out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+        if literals.len() >= 15 {
+            write_length(out, literals.len() - 15);
+        }
+        out.extend_from_slice(literals);
+        if let Some((offset, _)) = matched {
+            out.extend_from_slice(&(offset as u16).to_le_bytes());
+            if match_len >= 15 {
+                write_length(out, match_len - 15);
+            }
+        }
+    }
+
+    /// Compress `input` into `out`, using a greedy search for the matches
+    pub(super) fn compress(input: &[u8], out: &mut Vec<u8>) {
+        let mut table = vec![usize::MAX; 1 << HASH_LOG];
+        let mut anchor = 0;
+        let mut pos = 0;
+        if input.len() > MF_LIMIT {
+            let match_limit = input.len() - MF_LIMIT;
+            let end_limit = input.len() - LAST_LITERALS;
+            while pos < match_limit {
+                let sequence = read_u32(input, pos);
+                let slot = hash(sequence);
+                let candidate = table[slot];
+                table[slot] = pos;
+                if candidate == usize::MAX
+                    || pos - candidate > MAX_OFFSET
+                    || read_u32(input, candidate) != sequence
+                {
+                    pos += 1;
+                    continue;
+                }
+                let mut len = MIN_MATCH;
+                while pos + len < end_limit && input[candidate + len] == input[pos + len] {
+                    len += 1;
+                }
+                write_sequence(out, &input[anchor..pos], Some((pos - candidate, len)));
+                pos += len;
+                anchor = pos;
+            }
+        }
+        write_sequence(out, &input[anchor..], None);
+    }
+
+    /// Read a length that continues over the following bytes (if its 4 bits are all set)
+    fn read_length(input: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+        if len == 15 {
+            loop {
+                let byte = *input.get(*pos)?;
+                *pos += 1;
+                len = len.checked_add(byte as usize)?;
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Some(len)
+    }
+
+    /// Decompress `input`, returning `None` if it isn't a valid block or if it doesn't
+    /// decompress to exactly `len` bytes
+    pub(super) fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+        // every byte of the input can't decompress to more than 255 bytes, so a corrupted
+        // length can't make this allocate too much memory
+        let mut out = Vec::with_capacity(len.min(input.len().saturating_mul(255)));
+        let mut pos = 0;
+        loop {
+            let token = *input.get(pos)?;
+            pos += 1;
+            let literals = read_length(input, &mut pos, (token >> 4) as usize)?;
+            if literals > len - out.len() {
+                return None;
+            }
+            out.extend_from_slice(input.get(pos..pos.checked_add(literals)?)?);
+            pos += literals;
+            if pos == input.len() {
+                // the last sequence only has literals
+                break;
+            }
+            let offset = u16::from_le_bytes([*input.get(pos)?, *input.get(pos + 1)?]) as usize;
+            pos += 2;
+            let match_len = read_length(input, &mut pos, (token & 15) as usize)? + MIN_MATCH;
+            if offset == 0 || offset > out.len() || match_len > len - out.len() {
+                return None;
+            }
+            // the match can overlap with the bytes that it writes, so it's copied byte by byte
+            let start = out.len() - offset;
+            for i in start..start + match_len {
+                let byte = out[i];
+                out.push(byte);
+            }
+        }
+        if out.len() == len {
+            Some(out)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_lz4_roundtrip() {
+    let text = b"the quick brown fox jumps over the lazy dog. ".repeat(200);
+    let pseudo_random: Vec<u8> = (0..10_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    let inputs: [&[u8]; 7] = [
+        b"",
+        b"a",
+        b"abcdefghijklm",
+        &[0u8; 100_000],
+        &text,
+        &pseudo_random,
+        &[b'x'; 300],
+    ];
+    for input in inputs.iter() {
+        let mut compressed = Vec::new();
+        lz4::compress(input, &mut compressed);
+        assert_eq!(lz4::decompress(&compressed, input.len()).unwrap(), *input);
+    }
+    // repetitive data actually gets smaller
+    let mut compressed = Vec::new();
+    lz4::compress(&text, &mut compressed);
+    assert!(compressed.len() < text.len() / 10);
+}
+
+#[test]
+fn test_lz4_decompress_reference() {
+    // a literal `a`, a match of 24 bytes at offset 1 and the last 5 bytes as literals
+    let compressed = b"\x1fa\x01\x00\x05Paaaab";
+    let mut out = Vec::new();
+    lz4::compress(b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaab", &mut out);
+    assert_eq!(out, compressed);
+    assert_eq!(
+        lz4::decompress(compressed, 30).unwrap(),
+        b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaab"
+    );
+    // the length must match
+    assert_eq!(lz4::decompress(compressed, 29), None);
+    assert_eq!(lz4::decompress(compressed, 31), None);
+    // a match can't point before the start of the output
+    assert_eq!(lz4::decompress(b"\x1fa\x02\x00\x05Paaaab", 30), None);
+    assert_eq!(lz4::decompress(b"", 0), None);
+}
+
+#[test]
+fn test_compress_query() {
+    use crate::types::RawString;
+    let compression = Compression::new(CompressionAlgorithm::Lz4).set_threshold(64);
+    let value = vec![b'x'; 1000];
+    let query = Query::from("set")
+        .arg("key")
+        .arg(RawString::from(value.clone()));
+    let compressed = compression.compress_query(query.clone());
+    let args: Vec<_> = compressed.iter_args().collect();
+    assert_eq!(args[..2], [&b"set"[..], b"key"]);
+    assert!(args[2].starts_with(&MARKER) && args[2].len() < 100);
+    // the value is decompressed when it's read back
+    let element = Element::Binstr(args[2].to_vec());
+    assert_eq!(
+        compression.decompress_element(element),
+        Element::Binstr(value.clone())
+    );
+    let element = Element::Array(Array::Bin(vec![Some(args[2].to_vec()), None]));
+    assert_eq!(
+        compression.decompress_element(element),
+        Element::Array(Array::Bin(vec![Some(value), None]))
+    );
+    // small values and values that don't get smaller are sent as they are
+    let query = Query::from("set").arg("key").arg("value");
+    assert_eq!(compression.compress_query(query.clone()), query);
+    let noise: Vec<u8> = (0..100u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    let query = Query::from("set").arg("key").arg(RawString::from(noise));
+    assert_eq!(compression.compress_query(query.clone()), query);
+    // plain values are never touched
+    for element in [
+        Element::Binstr(b"\xffsky plain".to_vec()),
+        Element::String("plain".to_owned()),
+        Element::UnsignedInt(1),
+    ] {
+        assert_eq!(compression.decompress_element(element.clone()), element);
+    }
+}
@@ -34,13 +34,15 @@
 //! ```
 //!
 
-use crate::error::{errorstring, SkyhashError};
-use crate::types::{Array, FlatElement};
+use crate::error::{errorstring, Error, SkyhashError};
+use crate::types::{Array, FlatElement, FromSkyhashBytes};
 use crate::Element;
 use crate::IntoSkyhashBytes;
 use crate::Query;
 use crate::RespCode;
 use crate::SkyResult;
+use core::fmt;
+use core::str::FromStr;
 
 cfg_async! {
     use crate::AsyncResult;
@@ -85,6 +87,116 @@ impl WhereAmI {
     }
 }
 
+/// The maximum length of the name of a keyspace or a table, in bytes
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A validated entity: either a keyspace or a table in a keyspace (`<keyspace>:<table>`)
+///
+/// Names are checked like the server checks them: they can only have ASCII letters, digits and
+/// underscores, can't start with a digit and are at most 64 bytes long, so a malformed entity
+/// is caught before any query is sent. An `Entity` can be passed to any action that takes an
+/// entity (like [`Ddl::switch`] or [`Ddl::drop_table`]) and to [`Keymap::new`]
+///
+/// ## Example
+/// ```
+/// use skytable::ddl::Entity;
+///
+/// let entity = Entity::table("mykeyspace", "mytable").unwrap();
+/// assert_eq!(entity.to_string(), "mykeyspace:mytable");
+/// let entity: Entity = "mykeyspace:mytable".parse().unwrap();
+/// assert_eq!(entity.keyspace_name(), "mykeyspace");
+/// assert_eq!(entity.table_name(), Some("mytable"));
+/// assert!("mykeyspace:".parse::<Entity>().is_err());
+/// ```
+pub struct Entity {
+    repr: String,
+    /// The position of the `:` in `repr`, if this is a table
+    colon: Option<usize>,
+}
+
+impl Entity {
+    /// Create an entity for the provided keyspace, returning an [`Error::ParseError`] if the
+    /// name isn't valid
+    pub fn keyspace(keyspace: &str) -> SkyResult<Self> {
+        validate_name(keyspace)?;
+        Ok(Self {
+            repr: keyspace.to_owned(),
+            colon: None,
+        })
+    }
+    /// Create an entity for the provided table in the provided keyspace, returning an
+    /// [`Error::ParseError`] if either name isn't valid
+    pub fn table(keyspace: &str, table: &str) -> SkyResult<Self> {
+        validate_name(keyspace)?;
+        validate_name(table)?;
+        Ok(Self {
+            repr: format!("{}:{}", keyspace, table),
+            colon: Some(keyspace.len()),
+        })
+    }
+    /// Returns the name of the keyspace
+    pub fn keyspace_name(&self) -> &str {
+        match self.colon {
+            Some(colon) => &self.repr[..colon],
+            None => &self.repr,
+        }
+    }
+    /// Returns the name of the table, or `None` if this entity is a keyspace
+    pub fn table_name(&self) -> Option<&str> {
+        self.colon.map(|colon| &self.repr[colon + 1..])
+    }
+}
+
+fn validate_name(name: &str) -> SkyResult<()> {
+    let empty_or_digit = matches!(name.bytes().next(), None | Some(b'0'..=b'9'));
+    if empty_or_digit
+        || name.len() > MAX_NAME_LEN
+        || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    {
+        return Err(Error::ParseError(format!(
+            "invalid keyspace or table name `{}`: names must have 1 to {} ASCII letters, digits \
+            or underscores and can't start with a digit",
+            name, MAX_NAME_LEN
+        )));
+    }
+    Ok(())
+}
+
+impl FromStr for Entity {
+    type Err = Error;
+    fn from_str(entity: &str) -> SkyResult<Self> {
+        match entity.split_once(':') {
+            Some((keyspace, table)) => Self::table(keyspace, table),
+            None => Self::keyspace(entity),
+        }
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.repr)
+    }
+}
+
+impl AsRef<str> for Entity {
+    fn as_ref(&self) -> &str {
+        &self.repr
+    }
+}
+
+impl IntoSkyhashBytes for Entity {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.repr.as_bytes().to_vec()
+    }
+}
+
+impl IntoSkyhashBytes for &Entity {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.repr.as_bytes().to_vec()
+    }
+}
+
 impl KeymapType {
     fn priv_to_string(&self) -> String {
         match self {
@@ -182,6 +294,7 @@ macro_rules! implement_ddl {
             $(
                 $(#[$attr])*
                 #[inline]
+                #[allow(clippy::needless_lifetimes)]
                 fn $name<'s, $($($tyargs: $ty $(+$tye)*, )*)?>(&'s mut self $(, $argname: $argty)*) -> SkyResult<$ret> {
                     gen_match!(self.run($($block)?), $($($mtch)+, $expect),*)
                 }
@@ -195,6 +308,7 @@ macro_rules! implement_ddl {
             $(
                 $(#[$attr])*
                 #[inline]
+                #[allow(clippy::needless_lifetimes)]
                 fn $name<'s, $($($tyargs: $ty $(+$tye)*, )*)?>(&'s mut self $(, $argname: $argty)*) -> AsyncResult<SkyResult<$ret>> {
                     Box::pin(async move {gen_match!(self.run($($block)?).await, $($($mtch)+, $expect),*)})
                 }
@@ -309,6 +423,37 @@ implement_ddl! {
             }
         }
     }
+    /// Returns the names of all the keyspaces
+    ///
+    /// This is equivalent to:
+    /// ```text
+    /// INSPECT KEYSPACES
+    /// ```
+    /// If the user isn't allowed to inspect the keyspaces, this returns
+    /// [`SkyhashError::Code`] with [`RespCode::AuthPermissionError`]
+    fn list_keyspaces() -> Vec<String> {
+        { Query::from("INSPECT").arg("KEYSPACES") }
+        x @ Element::Array(Array::Bin(_))
+        | x @ Element::Array(Array::Str(_))
+        | x @ Element::Array(Array::NonNullBin(_))
+        | x @ Element::Array(Array::NonNullStr(_)) => FromSkyhashBytes::from_element(x)?
+    }
+    /// Returns the names of all the tables in the provided keyspace
+    ///
+    /// This is equivalent to:
+    /// ```text
+    /// INSPECT KEYSPACE <keyspace>
+    /// ```
+    /// If the keyspace doesn't exist, this returns [`SkyhashError::Code`] with the
+    /// [`CONTAINER_NOT_FOUND`](errorstring::CONTAINER_NOT_FOUND) error string and if the user
+    /// isn't allowed to inspect it, [`SkyhashError::Code`] with [`RespCode::AuthPermissionError`]
+    fn list_tables(keyspace: impl IntoSkyhashBytes + 's) -> Vec<String> {
+        { Query::from("INSPECT").arg("KEYSPACE").arg(keyspace) }
+        x @ Element::Array(Array::Bin(_))
+        | x @ Element::Array(Array::Str(_))
+        | x @ Element::Array(Array::NonNullBin(_))
+        | x @ Element::Array(Array::NonNullStr(_)) => FromSkyhashBytes::from_element(x)?
+    }
 }
 
 #[cfg(test)]
@@ -323,4 +468,33 @@ mod tests {
         let qman = crate::query!("CREATE", "TABLE", "mytbl", "keymap(str,binstr)");
         assert_eq!(qgen, qman);
     }
+
+    #[test]
+    fn test_entity() {
+        use super::Entity;
+        let entity: Entity = "default:my_table2".parse().unwrap();
+        assert_eq!(entity.keyspace_name(), "default");
+        assert_eq!(entity.table_name(), Some("my_table2"));
+        assert_eq!(entity, Entity::table("default", "my_table2").unwrap());
+        assert_eq!(
+            crate::query!("use", &entity),
+            crate::query!("use", "default:my_table2")
+        );
+        let entity: Entity = "_ks".parse().unwrap();
+        assert_eq!(entity.keyspace_name(), "_ks");
+        assert_eq!(entity.table_name(), None);
+        assert_eq!(
+            Keymap::new(&entity).into_query(),
+            Keymap::new("_ks").into_query()
+        );
+        let long = "a".repeat(65);
+        for bad in ["", ":", "ks:", ":tbl", "1ks", "ks:tbl:x", "my-ks", &long] {
+            assert!(
+                bad.parse::<Entity>().is_err(),
+                "{:?} should be invalid",
+                bad
+            );
+        }
+        assert!("a".repeat(64).parse::<Entity>().is_ok());
+    }
 }
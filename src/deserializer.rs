@@ -33,11 +33,38 @@ use crate::{
     RespCode, SkyResult,
 };
 use core::{
+    convert::TryInto,
+    hash::{Hash, Hasher},
+    mem,
     num::{ParseFloatError, ParseIntError},
     slice,
     str::{self, Utf8Error},
 };
 
+cfg_serde! {
+    /// Binary strings are converted to (padded) base64 strings
+    fn binary_to_json(bytes: &[u8]) -> serde_json::Value {
+        use base64::Engine;
+        serde_json::Value::from(base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+
+    /// JSON has no representation for NaN and the infinities, so they're converted to `null`
+    fn float_to_json(float: f32) -> serde_json::Value {
+        serde_json::Number::from_f64(float as f64).map_or(serde_json::Value::Null, Into::into)
+    }
+
+    /// Response codes are converted to `{"respcode": <code>}`, with an additional `error` field
+    /// for [`RespCode::ErrorString`]
+    fn respcode_to_json(rc: &RespCode) -> serde_json::Value {
+        let mut json = serde_json::Map::new();
+        json.insert("respcode".to_owned(), rc.code().into());
+        if let RespCode::ErrorString(error) = rc {
+            json.insert("error".to_owned(), error.as_str().into());
+        }
+        json.into()
+    }
+}
+
 #[derive(Debug)]
 /// # Skyhash Deserializer (Parser)
 ///
@@ -51,6 +78,9 @@ use core::{
 /// happily not report any errors if some part of the next query was passed. This is very much a possibility
 /// and so has been accounted for
 ///
+/// Since every array declares its number of elements ahead of them, the vectors holding them
+/// are allocated with the right capacity upfront and never need to be grown
+///
 /// ## Important note
 ///
 /// All developers willing to modify the deserializer must keep this in mind: the cursor is always Ahead-Of-Position
@@ -63,13 +93,24 @@ pub(super) struct Parser<'a> {
     cursor: usize,
     /// The buffer slice
     slice: &'a [u8],
+    /// Whether strings that aren't valid UTF-8 are kept as raw bytes (instead of being converted
+    /// lossily). See [`Parser::parse_lossy_utf8`]
+    validate_utf8: bool,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone)]
 #[non_exhaustive]
 /// # Data Types
 ///
 /// This enum represents the data types supported by the Skyhash Protocol
+///
+/// ## Equality and hashing
+///
+/// Elements implement [`Eq`] and [`Hash`] so that they can be used as the keys of a
+/// `HashMap` (for example, to cache responses). For this, [`Element::Float`]s are compared
+/// (and hashed) by their bit patterns instead of with `f32`'s `==`: a `NaN` is equal to an
+/// identical `NaN`, while `0.0` and `-0.0` aren't equal. To compare floats numerically,
+/// compare the `f32`s that they hold
 pub enum Element {
     /// Array types
     Array(Array),
@@ -77,6 +118,13 @@ pub enum Element {
     String(String),
     /// A binary string (`?`)
     Binstr(Vec<u8>),
+    /// A string (`+`) that isn't valid UTF-8. Its bytes are kept as they were sent by the
+    /// server, so that no data is lost; use [`Element::as_str`] or [`Element::as_bytes`] to
+    /// access them
+    Str {
+        /// The bytes of the string
+        raw: Vec<u8>,
+    },
     /// An unsigned integer value; `<tsymbol>` is `:`
     UnsignedInt(u64),
     /// A response code
@@ -85,16 +133,281 @@ pub enum Element {
     Float(f32),
 }
 
+impl PartialEq for Element {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Array(a), Self::Array(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Binstr(a), Self::Binstr(b)) => a == b,
+            (Self::Str { raw: a }, Self::Str { raw: b }) => a == b,
+            (Self::UnsignedInt(a), Self::UnsignedInt(b)) => a == b,
+            (Self::RespCode(a), Self::RespCode(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Element {}
+
+impl Hash for Element {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Self::Array(array) => array.hash(state),
+            Self::String(st) => st.hash(state),
+            Self::Binstr(bstr) => bstr.hash(state),
+            Self::Str { raw } => raw.hash(state),
+            Self::UnsignedInt(int) => int.hash(state),
+            Self::RespCode(rc) => rc.hash(state),
+            Self::Float(float) => float.to_bits().hash(state),
+        }
+    }
+}
+
 impl Element {
     /// Try to convert an element to a type that implements [`FromSkyhashBytes`]
     pub fn try_element_into<T: FromSkyhashBytes>(self) -> SkyResult<T> {
         T::from_element(self)
     }
+    /// Turns this element into a [`Result`], returning `Err` for any response code other than
+    /// [`RespCode::Okay`] and `Ok` otherwise
+    ///
+    /// Since a [`RespCode`] can be converted into an [`Error`](crate::error::Error), this can be
+    /// used with `?` alongside connection errors:
+    /// ```no_run
+    /// use skytable::{query, Connection, SkyResult};
+    ///
+    /// fn run() -> SkyResult<()> {
+    ///     let mut con = Connection::new("127.0.0.1", 2003)?;
+    ///     let element = con.run_query_raw(query!("get", "x"))?.into_result()?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn into_result(self) -> Result<Element, RespCode> {
+        match self {
+            Self::RespCode(RespCode::Okay) => Ok(Self::RespCode(RespCode::Okay)),
+            Self::RespCode(rc) => Err(rc),
+            element => Ok(element),
+        }
+    }
+    /// Returns a description of this element's type (like `"a string"`), for use in error
+    /// messages
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Self::Array(_) => "an array",
+            Self::String(_) | Self::Str { .. } => "a string",
+            Self::Binstr(_) => "a binary string",
+            Self::UnsignedInt(_) => "an unsigned integer",
+            Self::RespCode(_) => "a response code",
+            Self::Float(_) => "a float",
+        }
+    }
+    /// Returns the type of this element
+    pub fn element_type(&self) -> ElementType {
+        match self {
+            Self::Array(_) => ElementType::Array,
+            Self::String(_) | Self::Str { .. } => ElementType::String,
+            Self::Binstr(_) => ElementType::Binstr,
+            Self::UnsignedInt(_) => ElementType::UnsignedInt,
+            Self::RespCode(_) => ElementType::RespCode,
+            Self::Float(_) => ElementType::Float,
+        }
+    }
+    /// Returns the number of elements if this is an [`Element::Array`] or `None` for any other
+    /// element
+    pub fn array_len(&self) -> Option<usize> {
+        match self {
+            Self::Array(array) => Some(array.len()),
+            _ => None,
+        }
+    }
+    /// Returns a reference to the array held by an [`Element::Array`] or `None` for any other
+    /// element. This lets an array be iterated over without taking (or cloning) the element
+    ///
+    /// The elements of an array are stored by type (for example, a string array holds
+    /// `String`s rather than [`Element`]s), so match on the [`Array`] to get a slice of them
+    ///
+    /// ## Example
+    /// ```
+    /// use skytable::types::Array;
+    /// use skytable::Element;
+    ///
+    /// let element = Element::Array(Array::NonNullStr(vec!["a".to_owned(), "b".to_owned()]));
+    /// if let Some(Array::NonNullStr(strings)) = element.as_array() {
+    ///     let lengths: Vec<usize> = strings.iter().map(|st| st.len()).collect();
+    ///     assert_eq!(lengths, [1, 1]);
+    /// }
+    /// assert!(Element::UnsignedInt(1).as_array().is_none());
+    /// ```
+    pub fn as_array(&self) -> Option<&Array> {
+        match self {
+            Self::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+    /// Returns (a copy of) the element at `index` of an [`Element::Array`], whatever the kind
+    /// of the array is. `None` is returned for any other element, if `index` is out of bounds or
+    /// if the element is null. See [`Array::get`]
+    ///
+    /// To take all the elements of an array, use `try_element_into::<Vec<Element>>()`
+    pub fn element_at(&self, index: usize) -> Option<Element> {
+        self.as_array()?.get(index)
+    }
+    /// Returns the value of an [`Element::UnsignedInt`] or `None` for any other element
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::UnsignedInt(int) => Some(*int),
+            _ => None,
+        }
+    }
+    /// Returns the value of an [`Element::UnsignedInt`] as an `i64` if it fits (or `None`)
+    ///
+    /// Skyhash has no signed integer type, so signed values (like `-1`) are sent by the server
+    /// as strings. Use `try_element_into::<i64>()` to parse those
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_u64().and_then(|int| int.try_into().ok())
+    }
+    /// Returns the value of an [`Element::String`] or an [`Element::Str`] as a `&str`, or
+    /// `None` for any other element. The bytes of an [`Element::Str`] aren't valid UTF-8, so
+    /// `Some(Err(_))` is returned for it
+    pub fn as_str(&self) -> Option<Result<&str, Utf8Error>> {
+        match self {
+            Self::String(st) => Some(Ok(st)),
+            Self::Str { raw } => Some(str::from_utf8(raw)),
+            _ => None,
+        }
+    }
+    /// Returns the bytes of an [`Element::String`], an [`Element::Str`] or an
+    /// [`Element::Binstr`], or `None` for any other element
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::String(st) => Some(st.as_bytes()),
+            Self::Str { raw } | Self::Binstr(raw) => Some(raw),
+            _ => None,
+        }
+    }
+    /// Returns all the strings in this element, walking arrays (including nested arrays)
+    /// recursively. Binary strings are converted lossily (replacing invalid UTF-8 with `�`).
+    /// Any other element (integers, floats and response codes) and null array elements are
+    /// skipped
+    ///
+    /// ## Example
+    /// ```
+    /// use skytable::types::Array;
+    /// use skytable::Element;
+    ///
+    /// let element = Element::Array(Array::Recursive(vec![
+    ///     Element::String("hello".to_owned()),
+    ///     Element::UnsignedInt(1),
+    ///     Element::Array(Array::Bin(vec![Some(b"world".to_vec()), None])),
+    /// ]));
+    /// assert_eq!(element.collect_strings(), ["hello", "world"]);
+    /// ```
+    pub fn collect_strings(&self) -> Vec<String> {
+        let mut strings = Vec::new();
+        self.push_strings(&mut strings);
+        strings
+    }
+    fn push_strings(&self, strings: &mut Vec<String>) {
+        let lossy = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        match self {
+            Self::String(st) => strings.push(st.clone()),
+            Self::Binstr(bytes) | Self::Str { raw: bytes } => strings.push(lossy(bytes)),
+            Self::Array(Array::Bin(arr)) => strings.extend(arr.iter().flatten().map(|b| lossy(b))),
+            Self::Array(Array::NonNullBin(arr)) => strings.extend(arr.iter().map(|b| lossy(b))),
+            Self::Array(Array::Str(arr)) => strings.extend(arr.iter().flatten().cloned()),
+            Self::Array(Array::NonNullStr(arr)) => strings.extend(arr.iter().cloned()),
+            Self::Array(Array::Flat(arr)) => {
+                for element in arr {
+                    match element {
+                        FlatElement::String(st) => strings.push(st.clone()),
+                        FlatElement::Binstr(bytes) => strings.push(lossy(bytes)),
+                        _ => {}
+                    }
+                }
+            }
+            Self::Array(Array::Recursive(arr)) => {
+                arr.iter().for_each(|element| element.push_strings(strings))
+            }
+            Self::UnsignedInt(_) | Self::RespCode(_) | Self::Float(_) => {}
+        }
+    }
+    cfg_serde! {
+        /// Returns this element as a JSON value. This is useful for generic tooling (like an
+        /// HTTP API) that passes responses on without knowing their types:
+        /// - Strings are converted to JSON strings and binary strings to base64 encoded JSON
+        ///   strings
+        /// - Integers and floats are converted to numbers (floats that aren't finite are converted
+        ///   to `null`)
+        /// - Arrays are converted to JSON arrays, with null elements converted to `null`
+        /// - Response codes are converted to `{"respcode": <code>}`, with an `error` field holding
+        ///   the error string for [`RespCode::ErrorString`]
+        ///
+        /// ## Example
+        /// ```
+        /// use serde_json::json;
+        /// use skytable::types::Array;
+        /// use skytable::{Element, RespCode};
+        ///
+        /// let element = Element::Array(Array::Recursive(vec![
+        ///     Element::String("hello".to_owned()),
+        ///     Element::Binstr(b"world".to_vec()),
+        ///     Element::UnsignedInt(1),
+        ///     Element::RespCode(RespCode::NotFound),
+        /// ]));
+        /// assert_eq!(element.to_json(), json!(["hello", "d29ybGQ=", 1, {"respcode": 1}]));
+        /// ```
+        pub fn to_json(&self) -> serde_json::Value {
+            use serde_json::Value;
+            match self {
+                Element::String(st) => Value::from(st.as_str()),
+                Element::Binstr(bytes) => binary_to_json(bytes),
+                // JSON strings must be valid UTF-8
+                Element::Str { raw } => Value::from(String::from_utf8_lossy(raw)),
+                Element::UnsignedInt(int) => Value::from(*int),
+                Element::Float(float) => float_to_json(*float),
+                Element::RespCode(rc) => respcode_to_json(rc),
+                Element::Array(array) => match array {
+                    Array::Bin(arr) => arr
+                        .iter()
+                        .map(|item| item.as_deref().map_or(Value::Null, binary_to_json))
+                        .collect(),
+                    Array::NonNullBin(arr) => arr
+                        .iter()
+                        .map(|bytes| binary_to_json(bytes))
+                        .collect(),
+                    Array::Str(arr) => arr
+                        .iter()
+                        .map(|item| item.as_deref().map_or(Value::Null, Value::from))
+                        .collect(),
+                    Array::NonNullStr(arr) => {
+                        arr.iter().map(|st| Value::from(st.as_str())).collect()
+                    }
+                    Array::Flat(arr) => arr
+                        .iter()
+                        .map(|item| match item {
+                            FlatElement::String(st) => Value::from(st.as_str()),
+                            FlatElement::Binstr(bytes) => binary_to_json(bytes),
+                            FlatElement::UnsignedInt(int) => Value::from(*int),
+                            FlatElement::Float(float) => float_to_json(*float),
+                            FlatElement::RespCode(rc) => respcode_to_json(rc),
+                        })
+                        .collect(),
+                    Array::Recursive(arr) => arr.iter().map(Element::to_json).collect(),
+                },
+            }
+        }
+    }
 }
 
 /// A generic result to indicate parsing errors thorugh the [`ParseError`] enum
 pub type ParseResult<T> = Result<T, ParseError>;
 
+/// The type symbol and raw payload of each element of a response. See [`Parser::parse_flat`]
+#[cfg(any(feature = "sync", feature = "aio"))]
+type RawElements = Vec<(u8, Vec<u8>)>;
+
 #[derive(Debug, PartialEq, Eq)]
 #[non_exhaustive]
 #[repr(u8)]
@@ -111,8 +424,12 @@ pub enum ParseError {
     /// A data type that the client doesn't know was passed into the query
     ///
     /// This is a frequent problem that can arise between different server editions as more data types
-    /// can be added with changing server versions
-    UnknownDatatype,
+    /// can be added with changing server versions. The type symbol is recorded so that it
+    /// can be reported
+    UnknownDatatype(u8),
+    /// A line wasn't terminated by a lone LF. This holds the byte found right before the LF (a
+    /// CR, if something rewrote the line endings to CRLF)
+    BadDelimiter(u8),
 }
 
 impl From<ParseIntError> for ParseError {
@@ -145,12 +462,178 @@ pub enum RawResponse {
     PipelinedQuery(Vec<Element>),
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// # Response headers
+///
+/// The header of a response, as declared in its frame. This can be obtained without reading the
+/// entire response and can be used to decide how a (possibly large) response should be handled
+pub enum ResponseHeader {
+    /// A simple response holding a single non-array element
+    Scalar,
+    /// A simple response holding an array with the given number of elements
+    Array(usize),
+    /// A pipelined response holding the given number of responses
+    Pipeline(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[non_exhaustive]
+/// # Element types
+///
+/// The type of an [`Element`], which can be read from the first bytes of a response (for
+/// example, with `peek_element_type` on connections) to decide how to handle the response
+/// before it's parsed
+pub enum ElementType {
+    /// A string (`+`)
+    String,
+    /// A binary string (`?`)
+    Binstr,
+    /// An unsigned integer (`:`)
+    UnsignedInt,
+    /// A float (`%`)
+    Float,
+    /// A response code (`!`)
+    RespCode,
+    /// An array of any kind (`_`, `@` or `^`)
+    Array,
+}
+
+/// # Response visitors
+///
+/// A visitor is driven by the parser while it reads a response (for example, with `run_visit`
+/// on connections), so that a response can be processed without building an [`Element`] for
+/// it. The parser doesn't allocate anything for the response: strings are handed to the
+/// visitor as slices of the connection's buffer
+///
+/// Every method does nothing by default, so a visitor only needs to implement the methods for
+/// the data it cares about. An array calls [`ResponseVisitor::on_array_start`], then the
+/// method for each of its elements and finally [`ResponseVisitor::on_array_end`]
+///
+/// ## Example
+/// ```
+/// use skytable::ResponseVisitor;
+///
+/// /// Adds up the lengths of all the strings in a response
+/// #[derive(Default)]
+/// struct TotalLen(usize);
+///
+/// impl ResponseVisitor for TotalLen {
+///     fn on_string(&mut self, string: &[u8]) {
+///         self.0 += string.len();
+///     }
+/// }
+/// ```
+pub trait ResponseVisitor {
+    /// Called for a string (`+`). The bytes aren't checked to be valid UTF-8
+    fn on_string(&mut self, _string: &[u8]) {}
+    /// Called for a binary string (`?`)
+    fn on_binstr(&mut self, _binstr: &[u8]) {}
+    /// Called for an unsigned integer (`:`)
+    fn on_int(&mut self, _int: u64) {}
+    /// Called for a float (`%`)
+    fn on_float(&mut self, _float: f32) {}
+    /// Called for a response code (`!`)
+    fn on_code(&mut self, _code: RespCode) {}
+    /// Called for a null element of a typed array
+    fn on_null(&mut self) {}
+    /// Called before the elements of an array, with the number of elements it holds
+    fn on_array_start(&mut self, _len: usize) {}
+    /// Called after the last element of an array
+    fn on_array_end(&mut self) {}
+}
+
+/// A visitor that ignores everything, used to find out if the whole response is in the buffer
+/// before the actual visitor is driven
+#[cfg(any(feature = "sync", feature = "aio"))]
+struct Delimit;
+
+#[cfg(any(feature = "sync", feature = "aio"))]
+impl ResponseVisitor for Delimit {}
+
+/// The kind of an array response, which decides how its elements are framed
+#[cfg(feature = "sync")]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum ArrayKind {
+    /// A flat array (`_`), where every element carries its own type symbol
+    Flat,
+    /// A typed array of strings that can be null (`@+`)
+    Str,
+    /// A typed array of binary strings that can be null (`@?`)
+    Bin,
+    /// A typed array of strings (`^+`)
+    NonNullStr,
+    /// A typed array of binary strings (`^?`)
+    NonNullBin,
+}
+
+impl ResponseHeader {
+    /// Returns the number of top-level elements declared in the response
+    pub fn element_count(&self) -> usize {
+        match self {
+            Self::Scalar => 1,
+            Self::Array(count) | Self::Pipeline(count) => *count,
+        }
+    }
+}
+
+/// Lines are terminated by a lone LF. Something (like a proxy) rewriting the line endings to CRLF
+/// would otherwise silently corrupt lines (for example, response codes), so this is reported
+#[inline(always)]
+fn check_delimiter(line: &[u8]) -> ParseResult<&[u8]> {
+    match line.last() {
+        Some(b'\r') => Err(ParseError::BadDelimiter(b'\r')),
+        _ => Ok(line),
+    }
+}
+
+/// Copy the bytes of a string into a [`String`]. If `validate_utf8` is set, an error is
+/// returned if they aren't valid UTF-8; otherwise, invalid sequences are replaced with
+/// `U+FFFD`
+#[inline(always)]
+fn to_string(bytes: &[u8], validate_utf8: bool) -> Result<String, str::Utf8Error> {
+    if validate_utf8 {
+        Ok(str::from_utf8(bytes)?.to_owned())
+    } else {
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Turn the bytes of a string into a [`String`], replacing invalid sequences with `U+FFFD`.
+/// Valid strings aren't copied
+#[inline(always)]
+fn into_string_lossy(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(st) => st,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+/// Turn the bytes of a string into a [`String`]. If `validate_utf8` is set, the bytes are
+/// handed back if they aren't valid UTF-8; otherwise, invalid sequences are replaced with
+/// `U+FFFD`
+#[inline(always)]
+fn into_string(bytes: Vec<u8>, validate_utf8: bool) -> Result<String, Vec<u8>> {
+    if validate_utf8 {
+        String::from_utf8(bytes).map_err(|e| e.into_bytes())
+    } else {
+        Ok(into_string_lossy(bytes))
+    }
+}
+
+/// Whether the bytes of every string are valid UTF-8 (which doesn't matter, and so is assumed,
+/// if `validate_utf8` isn't set)
+#[inline(always)]
+fn all_utf8<'b>(mut strings: impl Iterator<Item = &'b Vec<u8>>, validate_utf8: bool) -> bool {
+    !validate_utf8 || strings.all(|bytes| str::from_utf8(bytes).is_ok())
+}
+
 impl<'a> Parser<'a> {
     #[inline(always)]
     pub fn new(slice: &'a [u8]) -> Self {
         Self {
             slice,
             cursor: 0usize,
+            validate_utf8: true,
         }
     }
     #[inline(always)]
@@ -231,10 +714,11 @@ impl<'a> Parser<'a> {
         {
             let len = self.cursor - cursor;
             self.incr_cursor(); // skip LF
-            Ok(unsafe {
+            let line = unsafe {
                 // UNSAFE(@ohsayan): Just verified length
                 self.direct_read(cursor, len)
-            })
+            };
+            check_delimiter(line)
         } else {
             Err(ParseError::NotEnough)
         }
@@ -260,10 +744,11 @@ impl<'a> Parser<'a> {
             } == b'\n';
         if self.not_exhausted() && has_lf && len != 0 {
             self.incr_cursor(); // skip LF
-            Ok(unsafe {
+            let line = unsafe {
                 // UNSAFE(@ohsayan): Just verified lengths
                 self.direct_read(cursor, len)
-            })
+            };
+            check_delimiter(line)
         } else {
             let r = if has_lf {
                 ParseError::BadPacket
@@ -316,22 +801,21 @@ impl<'a> Parser<'a> {
             }
         }
     }
+    /// Read a string, keeping its raw bytes (as [`Element::Str`]) if it isn't valid UTF-8
     #[inline(always)]
-    fn read_string(&mut self) -> ParseResult<String> {
+    fn read_string_element(&mut self) -> ParseResult<Element> {
+        let validate_utf8 = self.validate_utf8;
         let size = self.read_usize()?;
         let line = self.read_until(size)?;
-        let r = str::from_utf8(line)?.to_owned();
+        let r = match to_string(line, validate_utf8) {
+            Ok(st) => Element::String(st),
+            Err(_) => Element::Str {
+                raw: line.to_owned(),
+            },
+        };
         Ok(r)
     }
     #[inline(always)]
-    fn read_string_nullck(&mut self) -> ParseResult<Option<String>> {
-        if let Some(size) = self.read_usize_nullck()? {
-            Ok(Some(str::from_utf8(self.read_until(size)?)?.to_owned()))
-        } else {
-            Ok(None)
-        }
-    }
-    #[inline(always)]
     fn read_binary_nullck(&mut self) -> ParseResult<Option<Vec<u8>>> {
         if let Some(size) = self.read_usize_nullck()? {
             Ok(Some(self.read_until(size)?.to_owned()))
@@ -356,73 +840,92 @@ impl<'a> Parser<'a> {
         let st = str::from_utf8(line)?;
         Ok(st.parse()?)
     }
+    /// Read the elements of a sequence (an array or a pipelined response) with
+    /// `read_element`. Every sequence declares its number of elements upfront; this is the only
+    /// place that knows how the end of a sequence is found, so a sequence ended by a terminator
+    /// instead would only need another variant of this method
     #[inline(always)]
-    fn read_flat_array(&mut self) -> ParseResult<Vec<FlatElement>> {
-        let array_len = self.read_usize()?;
-        let mut data = Vec::with_capacity(array_len);
-        for _ in 0..array_len {
-            match self.try_read_cursor()? {
-                b'+' => data.push(FlatElement::String(self.read_string()?)),
-                b'?' => data.push(FlatElement::Binstr(self.read_binary()?)),
-                b'!' => data.push(FlatElement::RespCode(self.read_respcode()?)),
-                b':' => data.push(FlatElement::UnsignedInt(self.read_u64()?)),
-                b'%' => data.push(FlatElement::Float(self.read_float()?)),
-                _ => return Err(ParseError::UnknownDatatype),
-            }
+    fn read_sized<T>(
+        &mut self,
+        mut read_element: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        let size = self.read_usize()?;
+        // every element takes at least a byte, so a (corrupted) size that's larger than the
+        // rest of the buffer can't make us allocate more than the buffer
+        let mut data = Vec::with_capacity(size.min(self.remaining()));
+        for _ in 0..size {
+            data.push(read_element(self)?);
         }
         Ok(data)
     }
+    /// Read a flat array. Strings that aren't valid UTF-8 are returned as
+    /// [`FlatElement::Binstr`]s, so that their bytes aren't lost
     #[inline(always)]
-    fn read_typed_array_string(&mut self) -> ParseResult<Vec<Option<String>>> {
-        let size = self.read_usize()?;
-        let mut data = Vec::with_capacity(size);
-        for _ in 0..size {
-            data.push(self.read_string_nullck()?);
+    fn read_flat_array(&mut self) -> ParseResult<Vec<FlatElement>> {
+        let validate_utf8 = self.validate_utf8;
+        self.read_sized(|slf| {
+            let r = match slf.try_read_cursor()? {
+                b'+' => match into_string(slf.read_binary()?, validate_utf8) {
+                    Ok(st) => FlatElement::String(st),
+                    Err(raw) => FlatElement::Binstr(raw),
+                },
+                b'?' => FlatElement::Binstr(slf.read_binary()?),
+                b'!' => FlatElement::RespCode(slf.read_respcode()?),
+                b':' => FlatElement::UnsignedInt(slf.read_u64()?),
+                b'%' => FlatElement::Float(slf.read_float()?),
+                tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+            };
+            Ok(r)
+        })
+    }
+    /// Read a typed string array. If any of its strings isn't valid UTF-8, the array is
+    /// returned as an [`Array::Bin`] (holding the raw bytes of every string) instead
+    #[inline(always)]
+    fn read_typed_array_string(&mut self) -> ParseResult<Array> {
+        let arr = self.read_sized(Self::read_binary_nullck)?;
+        if !all_utf8(arr.iter().flatten(), self.validate_utf8) {
+            return Ok(Array::Bin(arr));
         }
-        Ok(data)
+        let arr = arr
+            .into_iter()
+            .map(|item| item.map(into_string_lossy))
+            .collect();
+        Ok(Array::Str(arr))
     }
     #[inline(always)]
     fn read_typed_array_binary(&mut self) -> ParseResult<Vec<Option<Vec<u8>>>> {
-        let size = self.read_usize()?;
-        let mut data = Vec::with_capacity(size);
-        for _ in 0..size {
-            data.push(self.read_binary_nullck()?);
-        }
-        Ok(data)
+        self.read_sized(Self::read_binary_nullck)
     }
     #[inline(always)]
     fn read_typed_array(&mut self) -> ParseResult<Element> {
         let r = match self.try_read_cursor()? {
-            b'+' => Element::Array(Array::Str(self.read_typed_array_string()?)),
+            b'+' => Element::Array(self.read_typed_array_string()?),
             b'?' => Element::Array(Array::Bin(self.read_typed_array_binary()?)),
-            _ => return Err(ParseError::UnknownDatatype),
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
         };
         Ok(r)
     }
+    /// Read a non-null typed string array. If any of its strings isn't valid UTF-8, the array
+    /// is returned as an [`Array::NonNullBin`] (holding the raw bytes of every string) instead
     #[inline(always)]
-    fn read_typed_nonnull_array_string(&mut self) -> ParseResult<Vec<String>> {
-        let size = self.read_usize()?;
-        let mut data = Vec::with_capacity(size);
-        for _ in 0..size {
-            data.push(self.read_string()?);
+    fn read_typed_nonnull_array_string(&mut self) -> ParseResult<Array> {
+        let arr = self.read_sized(Self::read_binary)?;
+        if !all_utf8(arr.iter(), self.validate_utf8) {
+            return Ok(Array::NonNullBin(arr));
         }
-        Ok(data)
+        let arr = arr.into_iter().map(into_string_lossy).collect();
+        Ok(Array::NonNullStr(arr))
     }
     #[inline(always)]
     fn read_typed_nonnull_array_binary(&mut self) -> ParseResult<Vec<Vec<u8>>> {
-        let size = self.read_usize()?;
-        let mut data = Vec::with_capacity(size);
-        for _ in 0..size {
-            data.push(self.read_binary()?);
-        }
-        Ok(data)
+        self.read_sized(Self::read_binary)
     }
     #[inline(always)]
     fn read_typed_nonnull_array(&mut self) -> ParseResult<Element> {
         let r = match self.try_read_cursor()? {
-            b'+' => Element::Array(Array::NonNullStr(self.read_typed_nonnull_array_string()?)),
+            b'+' => Element::Array(self.read_typed_nonnull_array_string()?),
             b'?' => Element::Array(Array::NonNullBin(self.read_typed_nonnull_array_binary()?)),
-            _ => return Err(ParseError::UnknownDatatype),
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
         };
         Ok(r)
     }
@@ -437,7 +940,7 @@ impl<'a> Parser<'a> {
     #[inline(always)]
     fn _read_simple_resp(&mut self) -> ParseResult<Element> {
         let r = match self.try_read_cursor()? {
-            b'+' => Element::String(self.read_string()?),
+            b'+' => self.read_string_element()?,
             b'?' => Element::Binstr(self.read_binary()?),
             b'!' => Element::RespCode(self.read_respcode()?),
             b':' => Element::UnsignedInt(self.read_u64()?),
@@ -445,7 +948,7 @@ impl<'a> Parser<'a> {
             b'@' => self.read_typed_array()?,
             b'^' => self.read_typed_nonnull_array()?,
             b'_' => Element::Array(Array::Flat(self.read_flat_array()?)),
-            _ => return Err(ParseError::UnknownDatatype),
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
         };
         Ok(r)
     }
@@ -455,12 +958,7 @@ impl<'a> Parser<'a> {
     }
     #[inline(always)]
     fn read_pipeline_resp(&mut self) -> ParseResult<Vec<Element>> {
-        let size = self.read_usize()?;
-        let mut resps = Vec::with_capacity(size);
-        for _ in 0..size {
-            resps.push(self._read_simple_resp()?);
-        }
-        Ok(resps)
+        self.read_sized(Self::_read_simple_resp)
     }
     #[inline(always)]
     fn _parse(&mut self) -> ParseResult<RawResponse> {
@@ -477,6 +975,354 @@ impl<'a> Parser<'a> {
         let r = slf._parse()?;
         Ok((r, slf.consumed()))
     }
+    /// Parse the response in the buffer like [`Parser::parse`], but convert strings that aren't
+    /// valid UTF-8 lossily (replacing invalid sequences with `U+FFFD`) instead of keeping their
+    /// raw bytes
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    pub fn parse_lossy_utf8(buffer: &'a [u8]) -> ParseResult<(RawResponse, usize)> {
+        let mut slf = Self::new(buffer);
+        slf.validate_utf8 = false;
+        let r = slf._parse()?;
+        Ok((r, slf.consumed()))
+    }
+    /// Parse the head of a simple response holding an array, returning its kind and the number
+    /// of elements, along with the length of the head. `None` is returned if the response
+    /// doesn't hold an array
+    #[cfg(feature = "sync")]
+    pub fn parse_array_head(buffer: &'a [u8]) -> ParseResult<Option<(ArrayKind, usize, usize)>> {
+        let mut slf = Self::new(buffer);
+        if slf.try_read_cursor()? != b'*' {
+            return Ok(None);
+        }
+        let kind = match slf.try_read_cursor()? {
+            b'_' => ArrayKind::Flat,
+            b'@' => match slf.try_read_cursor()? {
+                b'+' => ArrayKind::Str,
+                b'?' => ArrayKind::Bin,
+                _ => return Ok(None),
+            },
+            b'^' => match slf.try_read_cursor()? {
+                b'+' => ArrayKind::NonNullStr,
+                b'?' => ArrayKind::NonNullBin,
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let count = slf.read_usize()?;
+        Ok(Some((kind, count, slf.consumed())))
+    }
+    /// Parse the head of a pipelined response, returning the number of responses it holds along
+    /// with the length of the head. `None` is returned if the response isn't pipelined
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    pub fn parse_pipeline_head(buffer: &'a [u8]) -> ParseResult<Option<(usize, usize)>> {
+        let mut slf = Self::new(buffer);
+        if slf.try_read_cursor()? != b'$' {
+            return Ok(None);
+        }
+        let count = slf.read_usize()?;
+        Ok(Some((count, slf.consumed())))
+    }
+    /// Parse the next response of a pipelined response, returning it along with the number of
+    /// bytes it takes up
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    pub fn parse_pipeline_element(buffer: &'a [u8]) -> ParseResult<(Element, usize)> {
+        let mut slf = Self::new(buffer);
+        let r = slf._read_simple_resp()?;
+        Ok((r, slf.consumed()))
+    }
+    /// Parse the next element of an array of the given kind, returning it along with the number
+    /// of bytes it takes up. The outer error is returned if the element couldn't be delimited
+    /// while the inner error is returned if the element was delimited (and hence can be skipped)
+    /// but couldn't be decoded
+    ///
+    /// Null elements of typed arrays are returned as [`RespCode::NotFound`], like in flat arrays
+    #[cfg(feature = "sync")]
+    pub fn parse_array_element(
+        buffer: &'a [u8],
+        kind: ArrayKind,
+    ) -> ParseResult<(ParseResult<Element>, usize)> {
+        let mut slf = Self::new(buffer);
+        let tsymbol = match kind {
+            ArrayKind::Flat => slf.try_read_cursor()?,
+            ArrayKind::Str | ArrayKind::NonNullStr => b'+',
+            ArrayKind::Bin | ArrayKind::NonNullBin => b'?',
+        };
+        if let ArrayKind::Str | ArrayKind::Bin = kind {
+            if slf.try_read_cursor()? == b'\0' {
+                return Ok((Ok(Element::RespCode(RespCode::NotFound)), slf.consumed()));
+            }
+            slf.decr_cursor();
+        }
+        let r = match tsymbol {
+            b'+' => slf.read_string_element(),
+            b'?' => {
+                let size = slf.read_usize()?;
+                Ok(Element::Binstr(slf.read_until(size)?.to_owned()))
+            }
+            b'!' => str::from_utf8(slf.read_line()?)
+                .map(|st| Element::RespCode(RespCode::from_str(st)))
+                .map_err(ParseError::from),
+            b':' => str::from_utf8(slf.read_line_pedantic()?)
+                .map_err(ParseError::from)
+                .and_then(|st| Ok(Element::UnsignedInt(st.parse()?))),
+            b'%' => str::from_utf8(slf.read_line()?)
+                .map_err(ParseError::from)
+                .and_then(|st| Ok(Element::Float(st.parse()?))),
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+        };
+        Ok((r, slf.consumed()))
+    }
+    /// Find the length of the next element of an array of the given kind, without parsing it
+    /// into an [`Element`]
+    #[cfg(feature = "sync")]
+    pub fn skip_array_element(buffer: &'a [u8], kind: ArrayKind) -> ParseResult<usize> {
+        let mut slf = Self::new(buffer);
+        let tsymbol = match kind {
+            ArrayKind::Flat => slf.try_read_cursor()?,
+            ArrayKind::Str | ArrayKind::NonNullStr => b'+',
+            ArrayKind::Bin | ArrayKind::NonNullBin => b'?',
+        };
+        if let ArrayKind::Str | ArrayKind::Bin = kind {
+            if slf.try_read_cursor()? == b'\0' {
+                return Ok(slf.consumed());
+            }
+            slf.decr_cursor();
+        }
+        match tsymbol {
+            b'+' | b'?' => slf.skip_sized()?,
+            b'!' | b'%' => {
+                slf.read_line()?;
+            }
+            b':' => {
+                slf.read_line_pedantic()?;
+            }
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+        }
+        Ok(slf.consumed())
+    }
+    /// Parse a simple response into the type symbol and raw payload of each of its top-level
+    /// elements (the elements of the array if the response holds an array, or else the one
+    /// element it holds), along with the length of the response. The payloads are neither
+    /// validated nor decoded, and null elements of typed arrays are returned as `(b'\0', [])`
+    ///
+    /// [`ParseError::BadPacket`] is returned for pipelined responses
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    pub fn parse_flat(buffer: &'a [u8]) -> ParseResult<(RawElements, usize)> {
+        let mut slf = Self::new(buffer);
+        if slf.try_read_cursor()? != b'*' {
+            return Err(ParseError::BadPacket);
+        }
+        let elements = match slf.try_read_cursor()? {
+            b'_' => slf.read_sized(|slf| {
+                let tsymbol = slf.try_read_cursor()?;
+                slf.read_raw_payload(tsymbol)
+            })?,
+            array @ (b'@' | b'^') => {
+                let tsymbol = match slf.try_read_cursor()? {
+                    tsymbol @ (b'+' | b'?') => tsymbol,
+                    tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+                };
+                slf.read_sized(|slf| {
+                    if array == b'@' {
+                        if slf.try_read_cursor()? == b'\0' {
+                            return Ok((b'\0', Vec::new()));
+                        }
+                        slf.decr_cursor();
+                    }
+                    slf.read_raw_payload(tsymbol)
+                })?
+            }
+            tsymbol => vec![slf.read_raw_payload(tsymbol)?],
+        };
+        Ok((elements, slf.consumed()))
+    }
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    fn read_raw_payload(&mut self, tsymbol: u8) -> ParseResult<(u8, Vec<u8>)> {
+        let payload = match tsymbol {
+            b'+' | b'?' => {
+                let size = self.read_usize()?;
+                self.read_until(size)?
+            }
+            b'!' | b'%' => self.read_line()?,
+            b':' => self.read_line_pedantic()?,
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+        };
+        Ok((tsymbol, payload.to_owned()))
+    }
+    /// Drive `visitor` with the simple response in the buffer, returning the length of the
+    /// response. The visitor is only called once the whole response is in the buffer, so a
+    /// [`ParseError::NotEnough`] never leaves it with a partial response
+    ///
+    /// [`ParseError::BadPacket`] is returned for pipelined responses
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    pub fn visit<V: ResponseVisitor>(buffer: &'a [u8], visitor: &mut V) -> ParseResult<usize> {
+        let mut slf = Self::new(buffer);
+        slf.visit_simple_resp(&mut Delimit)?;
+        let mut slf = Self::new(buffer);
+        slf.visit_simple_resp(visitor)?;
+        Ok(slf.consumed())
+    }
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    fn visit_simple_resp<V: ResponseVisitor>(&mut self, visitor: &mut V) -> ParseResult<()> {
+        if self.try_read_cursor()? != b'*' {
+            return Err(ParseError::BadPacket);
+        }
+        match self.try_read_cursor()? {
+            b'_' => {
+                let size = self.read_usize()?;
+                visitor.on_array_start(size);
+                for _ in 0..size {
+                    let tsymbol = self.try_read_cursor()?;
+                    self.visit_scalar(tsymbol, visitor)?;
+                }
+                visitor.on_array_end();
+            }
+            array @ (b'@' | b'^') => {
+                let tsymbol = match self.try_read_cursor()? {
+                    tsymbol @ (b'+' | b'?') => tsymbol,
+                    tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+                };
+                let size = self.read_usize()?;
+                visitor.on_array_start(size);
+                for _ in 0..size {
+                    if array == b'@' {
+                        if self.try_read_cursor()? == b'\0' {
+                            visitor.on_null();
+                            continue;
+                        }
+                        self.decr_cursor();
+                    }
+                    self.visit_scalar(tsymbol, visitor)?;
+                }
+                visitor.on_array_end();
+            }
+            tsymbol => self.visit_scalar(tsymbol, visitor)?,
+        }
+        Ok(())
+    }
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    fn visit_scalar<V: ResponseVisitor>(
+        &mut self,
+        tsymbol: u8,
+        visitor: &mut V,
+    ) -> ParseResult<()> {
+        match tsymbol {
+            b'+' => {
+                let size = self.read_usize()?;
+                visitor.on_string(self.read_until(size)?);
+            }
+            b'?' => {
+                let size = self.read_usize()?;
+                visitor.on_binstr(self.read_until(size)?);
+            }
+            b'!' => visitor.on_code(self.read_respcode()?),
+            b':' => visitor.on_int(self.read_u64()?),
+            b'%' => visitor.on_float(self.read_float()?),
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+        }
+        Ok(())
+    }
+    /// Find the length of the response in the buffer, without parsing it into [`Element`]s
+    #[cfg(all(feature = "dbg", feature = "sync"))]
+    pub fn skip(buffer: &'a [u8]) -> ParseResult<usize> {
+        let mut slf = Self::new(buffer);
+        match slf.try_read_cursor()? {
+            b'*' => slf.skip_element()?,
+            b'$' => {
+                for _ in 0..slf.read_usize()? {
+                    slf.skip_element()?;
+                }
+            }
+            _ => return Err(ParseError::BadPacket),
+        }
+        Ok(slf.consumed())
+    }
+    #[cfg(all(feature = "dbg", feature = "sync"))]
+    fn skip_element(&mut self) -> ParseResult<()> {
+        match self.try_read_cursor()? {
+            b'+' | b'?' => self.skip_sized()?,
+            b'!' | b'%' => {
+                self.read_line()?;
+            }
+            b':' => {
+                self.read_line_pedantic()?;
+            }
+            b'@' => {
+                self.skip_array_tsymbol()?;
+                for _ in 0..self.read_usize()? {
+                    if self.try_read_cursor()? != b'\0' {
+                        self.decr_cursor();
+                        self.skip_sized()?;
+                    }
+                }
+            }
+            b'^' => {
+                self.skip_array_tsymbol()?;
+                for _ in 0..self.read_usize()? {
+                    self.skip_sized()?;
+                }
+            }
+            b'_' => {
+                for _ in 0..self.read_usize()? {
+                    self.skip_element()?;
+                }
+            }
+            tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+        }
+        Ok(())
+    }
+    #[cfg(all(feature = "dbg", feature = "sync"))]
+    fn skip_array_tsymbol(&mut self) -> ParseResult<()> {
+        match self.try_read_cursor()? {
+            b'+' | b'?' => Ok(()),
+            tsymbol => Err(ParseError::UnknownDatatype(tsymbol)),
+        }
+    }
+    #[cfg(feature = "sync")]
+    fn skip_sized(&mut self) -> ParseResult<()> {
+        let size = self.read_usize()?;
+        self.read_until(size)?;
+        Ok(())
+    }
+    /// Parse only the header of the response in the buffer. Unlike [`Parser::parse`], this only
+    /// needs the first few bytes of the response to be present
+    pub fn parse_header(buffer: &'a [u8]) -> ParseResult<ResponseHeader> {
+        let mut slf = Self::new(buffer);
+        let r = match slf.try_read_cursor()? {
+            b'*' => match slf.try_read_cursor()? {
+                b'+' | b'?' | b'!' | b':' | b'%' => ResponseHeader::Scalar,
+                b'@' | b'^' => match slf.try_read_cursor()? {
+                    b'+' | b'?' => ResponseHeader::Array(slf.read_usize()?),
+                    tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+                },
+                b'_' => ResponseHeader::Array(slf.read_usize()?),
+                tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+            },
+            b'$' => ResponseHeader::Pipeline(slf.read_usize()?),
+            _ => return Err(ParseError::BadPacket),
+        };
+        Ok(r)
+    }
+    /// Parse only the type of the element held by the simple response in the buffer, or `None`
+    /// for a pipelined response. This only needs the first two bytes of the response
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    pub fn parse_element_type(buffer: &'a [u8]) -> ParseResult<Option<ElementType>> {
+        let mut slf = Self::new(buffer);
+        let r = match slf.try_read_cursor()? {
+            b'*' => match slf.try_read_cursor()? {
+                b'+' => ElementType::String,
+                b'?' => ElementType::Binstr,
+                b':' => ElementType::UnsignedInt,
+                b'%' => ElementType::Float,
+                b'!' => ElementType::RespCode,
+                b'_' | b'@' | b'^' => ElementType::Array,
+                tsymbol => return Err(ParseError::UnknownDatatype(tsymbol)),
+            },
+            b'$' => return Ok(None),
+            _ => return Err(ParseError::BadPacket),
+        };
+        Ok(Some(r))
+    }
 }
 
 #[test]
@@ -539,3 +1385,417 @@ fn lskeys_resp() {
     );
     assert_eq!(skip, resp.len());
 }
+
+#[test]
+fn element_into_result() {
+    assert_eq!(
+        Element::String("hello".to_owned()).into_result(),
+        Ok(Element::String("hello".to_owned()))
+    );
+    assert_eq!(
+        Element::RespCode(RespCode::Okay).into_result(),
+        Ok(Element::RespCode(RespCode::Okay))
+    );
+    assert_eq!(
+        Element::RespCode(RespCode::NotFound).into_result(),
+        Err(RespCode::NotFound)
+    );
+}
+
+#[test]
+fn parse_response_header() {
+    // the payload isn't needed
+    assert_eq!(
+        Parser::parse_header(b"*_3\n+3\nse").unwrap(),
+        ResponseHeader::Array(3)
+    );
+    assert_eq!(
+        Parser::parse_header(b"*^+2\n").unwrap(),
+        ResponseHeader::Array(2)
+    );
+    assert_eq!(Parser::parse_header(b"*!").unwrap(), ResponseHeader::Scalar);
+    assert_eq!(
+        Parser::parse_header(b"$2\n!1\n0\n")
+            .unwrap()
+            .element_count(),
+        2
+    );
+    // the size line is incomplete
+    assert_eq!(
+        Parser::parse_header(b"*_3").unwrap_err(),
+        ParseError::NotEnough
+    );
+    assert_eq!(
+        Parser::parse_header(b"#").unwrap_err(),
+        ParseError::BadPacket
+    );
+}
+
+#[test]
+#[cfg(any(feature = "sync", feature = "aio"))]
+fn parse_element_type() {
+    let element_type = |resp: &[u8]| Parser::parse_element_type(resp).unwrap();
+    // the payload isn't needed
+    assert_eq!(element_type(b"*+"), Some(ElementType::String));
+    assert_eq!(element_type(b"*?5\nhe"), Some(ElementType::Binstr));
+    assert_eq!(element_type(b"*:"), Some(ElementType::UnsignedInt));
+    assert_eq!(element_type(b"*%"), Some(ElementType::Float));
+    assert_eq!(element_type(b"*!"), Some(ElementType::RespCode));
+    for array in [&b"*_"[..], b"*@", b"*^"] {
+        assert_eq!(element_type(array), Some(ElementType::Array));
+    }
+    assert_eq!(element_type(b"$2\n"), None);
+    assert_eq!(
+        Parser::parse_element_type(b"*").unwrap_err(),
+        ParseError::NotEnough
+    );
+    assert_eq!(
+        Parser::parse_element_type(b"*~").unwrap_err(),
+        ParseError::UnknownDatatype(b'~')
+    );
+    let (resp, _) = Parser::parse(b"*%2.5\n").unwrap();
+    match resp {
+        RawResponse::SimpleQuery(element) => assert_eq!(element.element_type(), ElementType::Float),
+        RawResponse::PipelinedQuery(_) => panic!("expected a simple response"),
+    }
+}
+
+#[test]
+fn unknown_datatype_records_tsymbol() {
+    assert_eq!(
+        Parser::parse(b"*#3\nabc").unwrap_err(),
+        ParseError::UnknownDatatype(b'#')
+    );
+    assert_eq!(
+        Parser::parse(b"*@#1\n").unwrap_err(),
+        ParseError::UnknownDatatype(b'#')
+    );
+}
+
+#[test]
+fn crlf_delimiters_are_reported() {
+    // without the check, the response code would be parsed as the error string `0\r`
+    assert_eq!(
+        Parser::parse(b"*!0\r\n").unwrap_err(),
+        ParseError::BadDelimiter(b'\r')
+    );
+    assert_eq!(
+        Parser::parse(b"*+4\r\nHEY!").unwrap_err(),
+        ParseError::BadDelimiter(b'\r')
+    );
+    assert_eq!(
+        Parser::parse(b"$1\r\n!0\n").unwrap_err(),
+        ParseError::BadDelimiter(b'\r')
+    );
+}
+
+#[test]
+fn element_as_int() {
+    assert_eq!(Element::UnsignedInt(10).as_u64(), Some(10));
+    assert_eq!(Element::UnsignedInt(10).as_i64(), Some(10));
+    assert_eq!(Element::UnsignedInt(u64::MAX).as_i64(), None);
+    assert_eq!(Element::String("-1".to_owned()).as_i64(), None);
+    assert_eq!(
+        Element::String("-1".to_owned())
+            .try_element_into::<i64>()
+            .unwrap(),
+        -1
+    );
+}
+
+#[test]
+fn element_array_len() {
+    let (resp, _) = Parser::parse(b"*@+3\n1\na\x001\nc").unwrap();
+    match resp {
+        RawResponse::SimpleQuery(element) => assert_eq!(element.array_len(), Some(3)),
+        RawResponse::PipelinedQuery(_) => panic!("expected a simple response"),
+    }
+    assert_eq!(Element::String("a".to_owned()).array_len(), None);
+}
+
+#[test]
+#[cfg(any(feature = "sync", feature = "aio"))]
+fn parse_lossy_utf8_resp() {
+    for resp in [
+        &b"*+5\nhe\xc3\xbfy"[..],
+        b"*_2\n+1\na:10\n",
+        b"*@+3\n1\na\x001\nc",
+        b"*^+1\n1\nx",
+        b"$2\n+1\na!0\n",
+    ] {
+        // valid strings are parsed the same way
+        assert_eq!(Parser::parse_lossy_utf8(resp), Parser::parse(resp));
+    }
+    let lossy = |resp: &[u8]| match Parser::parse_lossy_utf8(resp).unwrap().0 {
+        RawResponse::SimpleQuery(element) => element,
+        RawResponse::PipelinedQuery(_) => panic!("expected a simple query"),
+    };
+    assert_eq!(
+        lossy(b"*+3\nab\xff"),
+        Element::String("ab\u{fffd}".to_owned())
+    );
+    assert_eq!(
+        lossy(b"*@+2\n1\n\xff\x00"),
+        Element::Array(Array::Str(vec![Some("\u{fffd}".to_owned()), None]))
+    );
+    assert_eq!(
+        lossy(b"*^+1\n1\n\xff"),
+        Element::Array(Array::NonNullStr(vec!["\u{fffd}".to_owned()]))
+    );
+    assert_eq!(
+        lossy(b"*_1\n+1\n\xff"),
+        Element::Array(Array::Flat(vec![FlatElement::String(
+            "\u{fffd}".to_owned()
+        )]))
+    );
+}
+
+#[test]
+#[cfg(any(feature = "sync", feature = "aio"))]
+fn parse_flat_resp() {
+    let resp = b"*+4\nHEY!".to_vec();
+    let (elements, len) = Parser::parse_flat(&resp).unwrap();
+    assert_eq!(elements, [(b'+', b"HEY!".to_vec())]);
+    assert_eq!(len, resp.len());
+    // payloads are neither validated nor decoded
+    let resp = b"*_4\n+2\n\xff\xfe:100\n%2.5\n!0\n".to_vec();
+    let (elements, len) = Parser::parse_flat(&resp).unwrap();
+    assert_eq!(
+        elements,
+        [
+            (b'+', vec![0xff, 0xfe]),
+            (b':', b"100".to_vec()),
+            (b'%', b"2.5".to_vec()),
+            (b'!', b"0".to_vec()),
+        ]
+    );
+    assert_eq!(len, resp.len());
+    let resp = b"*@?2\n2\nab\0".to_vec();
+    let (elements, _) = Parser::parse_flat(&resp).unwrap();
+    assert_eq!(elements, [(b'?', b"ab".to_vec()), (b'\0', Vec::new())]);
+    assert_eq!(
+        Parser::parse_flat(&resp[..resp.len() - 1]).unwrap_err(),
+        ParseError::NotEnough
+    );
+    assert_eq!(
+        Parser::parse_flat(b"$1\n!0\n").unwrap_err(),
+        ParseError::BadPacket
+    );
+}
+
+#[test]
+#[cfg(any(feature = "sync", feature = "aio"))]
+fn visit_resp() {
+    #[derive(Default)]
+    struct Events(Vec<String>);
+    impl ResponseVisitor for Events {
+        fn on_string(&mut self, string: &[u8]) {
+            self.0.push(format!("string {:?}", string));
+        }
+        fn on_binstr(&mut self, binstr: &[u8]) {
+            self.0.push(format!("binstr {:?}", binstr));
+        }
+        fn on_int(&mut self, int: u64) {
+            self.0.push(format!("int {}", int));
+        }
+        fn on_float(&mut self, float: f32) {
+            self.0.push(format!("float {}", float));
+        }
+        fn on_code(&mut self, code: RespCode) {
+            self.0.push(format!("code {}", code.code()));
+        }
+        fn on_null(&mut self) {
+            self.0.push("null".to_owned());
+        }
+        fn on_array_start(&mut self, len: usize) {
+            self.0.push(format!("start {}", len));
+        }
+        fn on_array_end(&mut self) {
+            self.0.push("end".to_owned());
+        }
+    }
+    let mut events = Events::default();
+    assert_eq!(Parser::visit(b"*:100\n", &mut events).unwrap(), 6);
+    assert_eq!(events.0, ["int 100"]);
+    let resp = b"*_4\n+2\nab:100\n%2.5\n!1\n".to_vec();
+    let mut events = Events::default();
+    assert_eq!(Parser::visit(&resp, &mut events).unwrap(), resp.len());
+    assert_eq!(
+        events.0,
+        [
+            "start 4",
+            "string [97, 98]",
+            "int 100",
+            "float 2.5",
+            "code 1",
+            "end"
+        ]
+    );
+    let resp = b"*@?2\n2\nab\0".to_vec();
+    let mut events = Events::default();
+    // the visitor isn't called until the whole response is there
+    assert_eq!(
+        Parser::visit(&resp[..resp.len() - 1], &mut events).unwrap_err(),
+        ParseError::NotEnough
+    );
+    assert!(events.0.is_empty());
+    Parser::visit(&resp, &mut events).unwrap();
+    assert_eq!(events.0, ["start 2", "binstr [97, 98]", "null", "end"]);
+    assert_eq!(
+        Parser::visit(b"$1\n!0\n", &mut events).unwrap_err(),
+        ParseError::BadPacket
+    );
+}
+
+#[cfg(all(feature = "dbg", feature = "sync"))]
+#[test]
+fn skip_resp() {
+    for resp in [
+        &b"*!0\n"[..],
+        b"*:100\n",
+        b"*@+4\n5\nsayan2\nis8\nthinking\0",
+        b"*^?2\n1\na0\n",
+        b"*_3\n+1\na:10\n%1.5\n",
+        b"$2\n!0\n@+4\n5\nsayan2\nis8\nthinking\0",
+    ] {
+        let (_, len) = Parser::parse(resp).unwrap();
+        assert_eq!(Parser::skip(resp), Ok(len));
+        // more data is needed for partial responses
+        assert_eq!(
+            Parser::skip(&resp[..resp.len() - 1]),
+            Err(ParseError::NotEnough)
+        );
+    }
+    assert_eq!(
+        Parser::skip(b"*~\n"),
+        Err(ParseError::UnknownDatatype(b'~'))
+    );
+}
+
+#[test]
+fn test_element_hash_eq() {
+    use std::collections::HashSet;
+    let elements: HashSet<Element> = vec![
+        Element::Float(f32::NAN),
+        Element::Float(f32::NAN),
+        Element::Float(0.0),
+        Element::Float(-0.0),
+        Element::String("a".to_owned()),
+        Element::String("a".to_owned()),
+        Element::Binstr(b"a".to_vec()),
+        Element::Array(Array::Flat(vec![FlatElement::Float(f32::NAN)])),
+        Element::Array(Array::Flat(vec![FlatElement::Float(f32::NAN)])),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(elements.len(), 6);
+    assert!(elements.contains(&Element::Float(f32::NAN)));
+    assert_ne!(Element::Float(0.0), Element::Float(-0.0));
+}
+
+#[test]
+fn test_huge_array_size() {
+    // a corrupted size must neither allocate for the declared number of elements nor panic
+    for resp in [
+        &b"*_18446744073709551615\n:1\n"[..],
+        b"*@+18446744073709551615\n\0",
+        b"$18446744073709551615\n",
+    ] {
+        assert_eq!(Parser::parse(resp).unwrap_err(), ParseError::NotEnough);
+    }
+}
+
+#[test]
+fn test_collect_strings() {
+    let element = Element::Array(Array::Recursive(vec![
+        Element::Array(Array::NonNullStr(vec!["a".to_owned(), "b".to_owned()])),
+        Element::Array(Array::Flat(vec![
+            FlatElement::Binstr(vec![b'c', 0xff]),
+            FlatElement::UnsignedInt(1),
+            FlatElement::String("d".to_owned()),
+        ])),
+        Element::RespCode(RespCode::Okay),
+        Element::Array(Array::Str(vec![None, Some("e".to_owned())])),
+        Element::Float(1.5),
+    ]));
+    assert_eq!(element.collect_strings(), ["a", "b", "c\u{fffd}", "d", "e"]);
+    assert!(Element::UnsignedInt(1).collect_strings().is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_element_to_json() {
+    let element = Element::Array(Array::Recursive(vec![
+        Element::String("say \"hi\"\n\u{1}".to_owned()),
+        Element::Float(1.5),
+        Element::Float(f32::NAN),
+        Element::Array(Array::Str(vec![Some("a".to_owned()), None])),
+        Element::Array(Array::Flat(vec![
+            FlatElement::UnsignedInt(1),
+            FlatElement::Binstr(vec![0xff]),
+        ])),
+        Element::RespCode(RespCode::ErrorString("err-snapshot-busy".to_owned())),
+        Element::Array(Array::Recursive(vec![])),
+    ]));
+    assert_eq!(
+        element.to_json().to_string(),
+        r#"["say \"hi\"\n\u0001",1.5,null,["a",null],[1,"/w=="],{"error":"err-snapshot-busy","respcode":6},[]]"#
+    );
+    let binary = |bytes: &[u8]| Element::Binstr(bytes.to_vec()).to_json();
+    assert_eq!(binary(b""), "");
+    assert_eq!(binary(b"f"), "Zg==");
+    assert_eq!(binary(b"fo"), "Zm8=");
+    assert_eq!(binary(b"foobar"), "Zm9vYmFy");
+    assert_eq!(binary(&[0xff, 0xfe]), "//4=");
+}
+
+#[test]
+fn test_invalid_utf8_string() {
+    let resp = b"*+3\nab\xff".to_vec();
+    let (ret, skip) = Parser::parse(&resp).unwrap();
+    assert_eq!(skip, resp.len());
+    let element = match ret {
+        RawResponse::SimpleQuery(element) => element,
+        RawResponse::PipelinedQuery(_) => panic!("expected a simple query"),
+    };
+    assert_eq!(
+        element,
+        Element::Str {
+            raw: b"ab\xff".to_vec()
+        }
+    );
+    assert!(element.as_str().unwrap().is_err());
+    assert_eq!(element.as_bytes(), Some(&b"ab\xff"[..]));
+    // valid strings are still parsed eagerly
+    let (ret, _) = Parser::parse(b"*+2\nab").unwrap();
+    assert_eq!(
+        ret,
+        RawResponse::SimpleQuery(Element::String("ab".to_owned()))
+    );
+    // arrays with an invalid string keep the bytes of all their strings
+    let (ret, _) = Parser::parse(b"*@+3\n2\nab\x001\n\xff").unwrap();
+    assert_eq!(
+        ret,
+        RawResponse::SimpleQuery(Element::Array(Array::Bin(vec![
+            Some(b"ab".to_vec()),
+            None,
+            Some(b"\xff".to_vec())
+        ])))
+    );
+    let (ret, _) = Parser::parse(b"*^+2\n2\nab1\n\xff").unwrap();
+    assert_eq!(
+        ret,
+        RawResponse::SimpleQuery(Element::Array(Array::NonNullBin(vec![
+            b"ab".to_vec(),
+            b"\xff".to_vec()
+        ])))
+    );
+    let (ret, _) = Parser::parse(b"*_2\n+2\nab+1\n\xff").unwrap();
+    assert_eq!(
+        ret,
+        RawResponse::SimpleQuery(Element::Array(Array::Flat(vec![
+            FlatElement::String("ab".to_owned()),
+            FlatElement::Binstr(b"\xff".to_vec())
+        ])))
+    );
+}
@@ -0,0 +1,106 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # DNS cache
+//!
+//! A cache of resolved addresses, so that reconnecting to the same host (for example, when many
+//! connections are reestablished during a failover) doesn't resolve the host over and over
+//!
+
+use crate::IoResult;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct CachedAddrs {
+    resolved_at: Instant,
+    addrs: Vec<SocketAddr>,
+}
+
+/// A cache of resolved addresses that keeps every entry for `ttl`. Clones share the same
+/// entries
+#[derive(Debug, Clone)]
+pub(crate) struct DnsCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<(String, u16), CachedAddrs>>>,
+}
+
+impl DnsCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<(String, u16), CachedAddrs>> {
+        // entries are replaced in one go, so a poisoned lock is fine
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Returns the cached addresses of `host:port`, unless they expired
+    fn get(&self, host: &str, port: u16) -> Option<Vec<SocketAddr>> {
+        let entries = self.entries();
+        let cached = entries.get(&(host.to_owned(), port))?;
+        if cached.resolved_at.elapsed() < self.ttl {
+            Some(cached.addrs.clone())
+        } else {
+            None
+        }
+    }
+    fn insert(&self, host: &str, port: u16, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        if !addrs.is_empty() {
+            let cached = CachedAddrs {
+                resolved_at: Instant::now(),
+                addrs: addrs.clone(),
+            };
+            self.entries().insert((host.to_owned(), port), cached);
+        }
+        addrs
+    }
+    /// Resolve `host:port`, using the cached addresses if they haven't expired
+    #[cfg(feature = "sync")]
+    pub(crate) fn resolve(&self, host: &str, port: u16) -> IoResult<Vec<SocketAddr>> {
+        use std::net::ToSocketAddrs;
+        match self.get(host, port) {
+            Some(addrs) => Ok(addrs),
+            None => Ok(self.insert(host, port, (host, port).to_socket_addrs()?.collect())),
+        }
+    }
+    /// Resolve `host:port` without blocking, using the cached addresses if they haven't expired
+    #[cfg(feature = "aio")]
+    pub(crate) async fn resolve_async(&self, host: &str, port: u16) -> IoResult<Vec<SocketAddr>> {
+        match self.get(host, port) {
+            Some(addrs) => Ok(addrs),
+            None => {
+                let addrs = tokio::net::lookup_host((host, port)).await?.collect();
+                Ok(self.insert(host, port, addrs))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dns_cache_expiry() {
+    let addrs: Vec<SocketAddr> = vec!["10.0.0.1:2003".parse().unwrap()];
+    let cache = DnsCache::new(Duration::from_millis(50));
+    assert_eq!(cache.get("db.local", 2003), None);
+    cache.insert("db.local", 2003, addrs.clone());
+    assert_eq!(cache.clone().get("db.local", 2003), Some(addrs));
+    assert_eq!(cache.get("db.local", 2004), None);
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(cache.get("db.local", 2003), None);
+}
@@ -19,8 +19,11 @@
 //!
 //! This module contains error types that the client returns in different cases
 
+use crate::schema::Arity;
+use crate::Element;
 use crate::RespCode;
 use core::fmt;
+use std::sync::Arc;
 
 #[derive(Debug, PartialEq, Eq)]
 #[non_exhaustive]
@@ -33,13 +36,147 @@ pub enum SkyhashError {
     /// The server sent data, that is valid, however, for this specific query, it
     /// was unexpected. This indicates a bug in the server
     UnexpectedResponse,
-    /// The server sent an unknown data type that we cannot parse
-    UnknownDataType,
+    /// The server sent an unknown data type that we cannot parse. This holds the type symbol
+    /// of the data type, which can be used to tell what kind of element was sent
+    UnknownDataType(u8),
     /// The server sent an invalid response
     InvalidResponse,
     /// The server returned a response code **other than the one that should have been returned
     /// for this action** (if any)
     Code(RespCode),
+    /// The server doesn't know about this action. This usually means that the server is running
+    /// an older version that doesn't support it
+    UnknownAction,
+    /// The server couldn't parse the query sent by the client (it returned a
+    /// [`RespCode::PacketError`]). This indicates a bug in how the query was serialized
+    ProtocolError,
+    /// An action was run on a key whose value has a different type (the server returned a
+    /// [`RespCode::Wrongtype`]), like running a list action on a string key. This is returned by
+    /// the [actions](crate::actions) and [DDL queries](crate::ddl); queries run with
+    /// `run_query_raw` return the response code as-is
+    WrongType,
+    /// A query in a pipeline failed. This holds the index of the query and the response code
+    /// it returned
+    PipelineFailed(usize, RespCode),
+    /// A line of the response wasn't terminated by a lone LF. This holds the unexpected byte
+    /// found before the LF, which is a CR if something between the client and the server
+    /// (like a proxy) rewrote the line endings to CRLF
+    BadDelimiter(u8),
+    /// The server closed the connection while a response was being read. The connection can't
+    /// be used anymore and a new connection has to be established. This holds the name of the
+    /// connection, if it was named
+    ConnectionClosed(Option<Arc<str>>),
+    /// Reading the response would have exceeded the
+    /// [`MemoryBudget`](crate::MemoryBudget) of the connection. The rest of the response
+    /// wasn't read, so the connection is poisoned
+    MemoryBudgetExceeded,
+    /// The response is larger than the maximum response size of the connection (see
+    /// `set_max_response_size`). The rest of the response wasn't read, so the connection is
+    /// poisoned
+    ResponseTooLarge,
+    /// Connecting, or a read or write on the connection, didn't complete within the timeout
+    /// set with the [`ConnectionBuilder`](crate::ConnectionBuilder) (or with
+    /// `set_read_timeout` and `set_write_timeout`). A query that timed out poisons the
+    /// connection, since the rest of its response might still arrive
+    Timeout,
+    /// The server speaks a version of the Skyhash protocol that isn't compatible with the
+    /// version spoken by this client ([`PROTOCOL_VERSION`](crate::PROTOCOL_VERSION)). This
+    /// holds the version reported by the server (or `unknown`, if the server is too old to
+    /// report it)
+    UnsupportedProtocol(String),
+    /// A snapshot couldn't be created because the snapshot engine is busy creating another one
+    SnapshotBusy,
+    /// A snapshot couldn't be created because snapshots are disabled on the server
+    SnapshotDisabled,
+    /// A snapshot couldn't be created because its name isn't valid
+    InvalidSnapshotName,
+    /// The server's response didn't match the expected response. See
+    /// [`Connection::run_expecting`](crate::Connection::run_expecting)
+    ResponseMismatch {
+        /// The response that was expected
+        expected: Element,
+        /// The response that the server actually sent
+        actual: Element,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+/// An error returned when a query doesn't match an [`ActionSchema`]. See
+/// [`Query::validate`](crate::Query::validate)
+///
+/// [`ActionSchema`]: crate::schema::ActionSchema
+pub enum ValidationError {
+    /// The query has no arguments
+    EmptyQuery,
+    /// The action isn't in the schema (and the schema rejects unknown actions)
+    UnknownAction(String),
+    /// The action was passed a number of arguments it doesn't accept
+    WrongArity {
+        /// The action
+        action: String,
+        /// The number of arguments the action accepts
+        expected: Arity,
+        /// The number of arguments that were passed
+        found: usize,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::EmptyQuery => write!(f, "query has no action"),
+            Self::UnknownAction(action) => write!(f, "unknown action '{}'", action),
+            Self::WrongArity {
+                action,
+                expected,
+                found,
+            } => write!(
+                f,
+                "action '{}' expects {} arguments but got {}",
+                action, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An error returned by [`Query::try_arg`](crate::Query::try_arg) when the argument is empty
+/// (like `""`), or adds no arguments at all (like an empty [`Vec`])
+pub struct EmptyArgError;
+
+impl fmt::Display for EmptyArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "argument is empty")
+    }
+}
+
+impl std::error::Error for EmptyArgError {}
+
+cfg_serde! {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    /// An error returned by [`Query::from_serde`](crate::Query::from_serde) when a value can't
+    /// be turned into query arguments
+    pub enum SerializeError {
+        /// The value isn't a flat struct (for example, it has a nested struct, map or sequence)
+        Unsupported(&'static str),
+        /// An error raised by the [`Serialize`](serde::Serialize) implementation of the value
+        Custom(String),
+    }
+
+    impl fmt::Display for SerializeError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Self::Unsupported(e) => write!(f, "unsupported value: {}", e),
+                Self::Custom(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl std::error::Error for SerializeError {}
 }
 
 pub mod errorstring {
@@ -64,6 +201,16 @@ pub mod errorstring {
     pub const ERR_SNAPSHOT_BUSY: &str = "err-snapshot-busy";
     /// The error string returned when periodic snapshots are busy
     pub const ERR_SNAPSHOT_DISABLED: &str = "err-snapshot-disabled";
+    /// The error string returned when a snapshot with the same name already exists
+    pub const ERR_DUPLICATE_SNAPSHOT: &str = "duplicate-snapshot";
+    /// The error string returned when the snapshot name is invalid
+    pub const ERR_INVALID_SNAPSHOT_NAME: &str = "err-invalid-snapshot-name";
+    /// The error string returned when a list index is out of bounds
+    pub const ERR_BAD_LIST_INDEX: &str = "bad-list-index";
+    /// The error string returned when the server doesn't recognize an action
+    pub const ERR_UNKNOWN_ACTION: &str = "Unknown action";
+    /// The error string returned by `AUTH` actions when authn is disabled on the server
+    pub const ERR_AUTH_DISABLED: &str = "err-auth-disabled";
 }
 
 #[derive(Debug)]
@@ -89,6 +236,24 @@ pub enum Error {
     )]
     /// An SSL error occurred
     SslError(openssl::ssl::Error),
+    #[cfg(any(
+        feature = "ssl",
+        feature = "sslv",
+        feature = "aio-ssl",
+        feature = "aio-sslv"
+    ))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(
+            feature = "ssl",
+            feature = "sslv",
+            feature = "aio-ssl",
+            feature = "aio-sslv"
+        )))
+    )]
+    /// The TLS handshake failed. This can happen if the server rejected the client certificate
+    /// (for example, because it has expired) or if the server's certificate couldn't be verified
+    TlsHandshakeError(openssl::ssl::Error),
     /// A Skyhash error occurred
     SkyError(SkyhashError),
     /// An application level parse error occurred
@@ -120,6 +285,22 @@ impl PartialEq for Error {
                 )))
             )]
             (SslError(a), SslError(b)) => a.to_string() == b.to_string(),
+            #[cfg(any(
+                feature = "ssl",
+                feature = "sslv",
+                feature = "aio-ssl",
+                feature = "aio-sslv"
+            ))]
+            #[cfg_attr(
+                docsrs,
+                doc(cfg(any(
+                    feature = "ssl",
+                    feature = "sslv",
+                    feature = "aio-ssl",
+                    feature = "aio-sslv"
+                )))
+            )]
+            (TlsHandshakeError(a), TlsHandshakeError(b)) => a.to_string() == b.to_string(),
             (ConfigurationError(a), ConfigurationError(b)) => a == b,
             _ => false,
         }
@@ -146,6 +327,22 @@ impl fmt::Display for Error {
                 )))
             )]
             Self::SslError(essl) => write!(f, "{}", essl),
+            #[cfg(any(
+                feature = "ssl",
+                feature = "sslv",
+                feature = "aio-ssl",
+                feature = "aio-sslv"
+            ))]
+            #[cfg_attr(
+                docsrs,
+                doc(cfg(any(
+                    feature = "ssl",
+                    feature = "sslv",
+                    feature = "aio-ssl",
+                    feature = "aio-sslv"
+                )))
+            )]
+            Self::TlsHandshakeError(essl) => write!(f, "TLS handshake failed: {}", essl),
             Self::ParseError(apperr) => {
                 write!(f, "custom type parse error: {}", apperr)
             }
@@ -159,9 +356,61 @@ impl fmt::Display for Error {
                 }
                 SkyhashError::ParseError => write!(f, "Client-side datatype parse error"),
                 SkyhashError::UnexpectedDataType => write!(f, "Wrong type sent by server"),
-                SkyhashError::UnknownDataType => {
-                    write!(f, "Server sent unknown data type for this client version")
+                SkyhashError::UnknownDataType(tsymbol) => write!(
+                    f,
+                    "Server sent unknown data type (type symbol: {:?}) for this client version",
+                    *tsymbol as char
+                ),
+                SkyhashError::BadDelimiter(byte) => write!(
+                    f,
+                    "Server sent a line terminated by {:?} and LF instead of a lone LF (is a proxy rewriting line endings?)",
+                    *byte as char
+                ),
+                SkyhashError::UnknownAction => {
+                    write!(f, "Action not supported by this server version")
+                }
+                SkyhashError::PipelineFailed(index, rcode) => {
+                    write!(f, "Query {} of the pipeline failed: {}", index, rcode)
+                }
+                SkyhashError::ConnectionClosed(name) => {
+                    if let Some(name) = name {
+                        write!(f, "connection '{}': ", name)?;
+                    }
+                    write!(f, "Server closed the connection while a response was being read")
+                }
+                SkyhashError::MemoryBudgetExceeded => {
+                    write!(f, "Reading the response would exceed the memory budget")
                 }
+                SkyhashError::ResponseTooLarge => {
+                    write!(f, "Response exceeds the maximum response size of the connection")
+                }
+                SkyhashError::Timeout => write!(f, "Timed out waiting for the server"),
+                SkyhashError::UnsupportedProtocol(version) => write!(
+                    f,
+                    "Server speaks Skyhash {} which isn't supported by this client (Skyhash {})",
+                    version,
+                    crate::PROTOCOL_VERSION
+                ),
+                SkyhashError::ProtocolError => write!(
+                    f,
+                    "Server failed to parse the query sent by the client (packet error)"
+                ),
+                SkyhashError::WrongType => write!(
+                    f,
+                    "Action run on a key whose value has a different type (wrongtype error)"
+                ),
+                SkyhashError::SnapshotBusy => {
+                    write!(f, "Snapshot engine is busy creating another snapshot")
+                }
+                SkyhashError::SnapshotDisabled => {
+                    write!(f, "Snapshots are disabled on the server")
+                }
+                SkyhashError::InvalidSnapshotName => write!(f, "Invalid snapshot name"),
+                SkyhashError::ResponseMismatch { expected, actual } => write!(
+                    f,
+                    "Response mismatch\n- expected: {:?}\n+   actual: {:?}",
+                    expected, actual
+                ),
             },
             Self::ConfigurationError(e) => write!(f, "Configuration error: {}", e),
         }
@@ -182,6 +431,25 @@ cfg_ssl_any! {
     }
 }
 
+impl Error {
+    /// Turn an I/O error caused by a socket timeout into [`SkyhashError::Timeout`]. Sockets
+    /// report timeouts as `WouldBlock` on some platforms and as `TimedOut` on others
+    #[cfg(feature = "sync")]
+    pub(crate) fn map_timeout(self) -> Self {
+        match self {
+            Self::IoError(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                SkyhashError::Timeout.into()
+            }
+            e => e,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         Self::IoError(err)
@@ -194,6 +462,12 @@ impl From<SkyhashError> for Error {
     }
 }
 
+impl From<RespCode> for Error {
+    fn from(rc: RespCode) -> Self {
+        Self::SkyError(SkyhashError::Code(rc))
+    }
+}
+
 impl From<std::num::ParseIntError> for Error {
     fn from(e: std::num::ParseIntError) -> Self {
         Self::ParseError(e.to_string())
@@ -225,3 +499,37 @@ impl From<std::string::FromUtf8Error> for Error {
 }
 
 impl std::error::Error for Error {}
+
+/// The error returned by `run_pipeline_partial` when a pipeline fails midway. This holds the
+/// responses that were received before the error, so that only the remaining queries of the
+/// pipeline have to be run again
+#[derive(Debug, PartialEq)]
+pub struct PipelineError {
+    /// The responses that were received before the error, in the order of the queries
+    pub received: Vec<Element>,
+    /// The error that stopped the pipeline
+    pub source: Error,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pipeline failed after {} responses: {}",
+            self.received.len(),
+            self.source
+        )
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<PipelineError> for Error {
+    fn from(e: PipelineError) -> Self {
+        e.source
+    }
+}
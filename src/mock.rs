@@ -0,0 +1,76 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Mock servers
+//!
+//! Fake servers for the tests, listening on a free local port. Every server runs on a thread
+//! (or task) of its own, which the test must join once it's done, so that the assertions made
+//! by the server aren't lost
+//!
+
+cfg_sync!(
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::{self, JoinHandle};
+
+    /// Start a server that runs `serve` with its listener, so that it can accept any number of
+    /// connections. Returns the port it listens on and the thread running it
+    pub(crate) fn serve<T: Send + 'static>(
+        serve: impl FnOnce(TcpListener) -> T + Send + 'static,
+    ) -> (u16, JoinHandle<T>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (port, thread::spawn(move || serve(listener)))
+    }
+
+    /// Start a server that accepts a single connection and runs `serve` with it
+    pub(crate) fn serve_once<T: Send + 'static>(
+        serve: impl FnOnce(TcpStream) -> T + Send + 'static,
+    ) -> (u16, JoinHandle<T>) {
+        self::serve(move |listener| serve(listener.accept().unwrap().0))
+    }
+);
+
+cfg_async!(
+    use core::future::Future;
+    use tokio::task;
+
+    /// Start a server that runs `serve` with its listener on a task of its own, so that it can
+    /// accept any number of connections. Returns the port it listens on and the task running it
+    pub(crate) async fn serve_async<F, Fut>(serve: F) -> (u16, task::JoinHandle<Fut::Output>)
+    where
+        F: FnOnce(tokio::net::TcpListener) -> Fut,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (port, task::spawn(serve(listener)))
+    }
+
+    /// Start a server that accepts a single connection and runs `serve` with it
+    pub(crate) async fn serve_once_async<F, Fut>(serve: F) -> (u16, task::JoinHandle<Fut::Output>)
+    where
+        F: FnOnce(tokio::net::TcpStream) -> Fut + Send + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        serve_async(|listener| async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve(stream).await
+        })
+        .await
+    }
+);
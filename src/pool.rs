@@ -44,18 +44,23 @@
 //! let notls_pool = pool::get("127.0.0.1", 2003, 10).unwrap();
 //! notls_pool.get().unwrap().set("x", "100").unwrap();
 //!
+//! # #[cfg(any(feature = "ssl", feature = "sslv"))]
+//! # {
 //! let tls_pool = pool::get_tls("127.0.0.1", 2004, "cert.pem", 10).unwrap();
 //! let ret: u8 = tls_pool.get().unwrap().get("x").unwrap();
 //!
 //! assert_eq!(ret, 100);
+//! # }
 //! ```
 //!
 //! ### Async Usage
 //!
 //! ```no_run
 //! use skytable::pool;
+//! # #[cfg(all(feature = "aio", any(feature = "aio-ssl", feature = "aio-sslv")))]
 //! use skytable::actions::AsyncActions;
 //!
+//! # #[cfg(all(feature = "aio", any(feature = "aio-ssl", feature = "aio-sslv")))]
 //! async fn run() {
 //!     let notls_pool = pool::get_async("127.0.0.1", 2003, 10).await.unwrap();
 //!     notls_pool.get().await.unwrap().set("x", "100").await.unwrap();
@@ -67,18 +72,77 @@
 //! }
 //! ```
 //!
+//! Checking out a connection with `get()` returns a guard that returns the connection to the
+//! pool once it's dropped. When all the connections are in use, `get()` waits for one to be
+//! returned (up to the connection timeout of the pool, which is 30 seconds by default).
+//! Connections are checked with a `HEYA` before they're handed out, so a connection that died
+//! while it was idle (for example, because the server restarted) is transparently replaced by
+//! a new one.
+//!
 //! ## Advanced usage
 //! If you want to configure a pool with custom settings, then you can use
 //! [r2d2's `Builder`](https://docs.rs/r2d2/0.8.9/r2d2/struct.Builder.html) or
 //! [bb8's `Builder`](https://docs.rs/bb8/0.7.1/bb8/struct.Builder.html) to configure your pool.
+//! You can also decide when connections that ran into errors are dropped from the pool by
+//! setting an [`ErrorPolicy`] with [`ConnectionManager::set_error_policy`], and retry new
+//! connections that fail to connect (for example, during a rolling restart of the server) with
+//! [`ConnectionManager::set_connect_retry`].
+//!
+//! ## Using the connection manager with your own pool
+//!
+//! [`ConnectionManager`] implements both
+//! [r2d2's `ManageConnection`](https://docs.rs/r2d2/0.8.9/r2d2/trait.ManageConnection.html) and
+//! [bb8's `ManageConnection`](https://docs.rs/bb8/0.8.0/bb8/trait.ManageConnection.html) for
+//! the sync and async connections (TCP and TLS), so it can be used with an existing `r2d2` or
+//! `bb8` pool setup without any custom code (`Pool` and `AsyncPool` are just aliases for those
+//! pools). The manager:
+//! - connects with the [`ConnectionBuilder`] set with
+//!   [`ConnectionManager::set_connection_builder`] (if any)
+//! - checks connections with a `HEYA` query in `is_valid`
+//! - reports connections as broken in `has_broken` as per its [`ErrorPolicy`] (and if they're
+//!   connected to an old endpoint)
+//!
+//! ```no_run
+//! # #[cfg(feature = "aio")]
+//! # async fn run() {
+//! use skytable::pool::ConnectionManager;
+//! use skytable::aio::Connection;
+//!
+//! let manager: ConnectionManager<Connection> = ConnectionManager::new_notls("127.0.0.1", 2003);
+//! let pool = bb8::Pool::builder().max_size(10).build(manager).await.unwrap();
+//! # }
+//! ```
+//!
+//! ## Changing the endpoint of a pool
+//!
+//! If the server moves to a new address (for example, during a blue/green failover), you can
+//! point an existing pool at the new endpoint with [`ConnectionManager::update_endpoint`]. Clones
+//! of a `ConnectionManager` share their endpoint, so keep a clone of the manager you build the
+//! pool with:
+//!
+//! ```no_run
+//! use skytable::pool::{ConnectionManager, Pool};
+//!
+//! let manager = ConnectionManager::new_notls("db-blue.local", 2003);
+//! let pool = Pool::builder().max_size(10).build(manager.clone()).unwrap();
+//! // ... later
+//! manager.update_endpoint("db-green.local", 2003);
+//! ```
+//!
+//! New connections are made to the new endpoint. Connections to the old endpoint aren't closed
+//! right away: idle ones are replaced when they're next checked out (if the pool tests
+//! connections on checkout, which is the default) and the ones in use are dropped as they're
+//! returned to the pool. Calling it with the same host after a DNS change works too, since
+//! connections are compared by the address they're connected to.
 //!
 //! ### Sync usage
 //!
 //! Example usage for TLS and non-TLS connection pools are given below.
 //!
 //! ```no_run
-//! use skytable::pool::{ConnectionManager, Pool, TlsPool};
-//! use skytable::sync::{Connection, TlsConnection};
+//! use skytable::pool::{ConnectionManager, Pool};
+//! # #[cfg(any(feature = "ssl", feature = "sslv"))]
+//! use skytable::pool::TlsPool;
 //!
 //! // non-TLS (TCP pool)
 //! let notls_manager = ConnectionManager::new_notls("127.0.0.1", 2003);
@@ -88,11 +152,14 @@
 //!    .unwrap();
 //!
 //! // TLS pool
+//! # #[cfg(any(feature = "ssl", feature = "sslv"))]
+//! # {
 //! let tls_manager = ConnectionManager::new_tls("127.0.0.1", 2003, "cert.pem");
 //! let notls_pool = TlsPool::builder()
 //!    .max_size(10)
 //!    .build(tls_manager)
 //!    .unwrap();
+//! # }
 //!```
 //!
 //! ### Async usage
@@ -100,8 +167,9 @@
 //! Example usage for TLS and non-TLS connection pools are given below.
 //!
 //! ```no_run
+//! # #[cfg(all(feature = "aio", any(feature = "aio-ssl", feature = "aio-sslv")))]
 //! use skytable::pool::{ConnectionManager, AsyncPool, AsyncTlsPool};
-//! use skytable::aio::{Connection, TlsConnection};
+//! # #[cfg(all(feature = "aio", any(feature = "aio-ssl", feature = "aio-sslv")))]
 //! async fn run() {
 //!     // non-TLS (TCP pool)
 //!     let notls_manager = ConnectionManager::new_notls("127.0.0.1", 2003);
@@ -124,6 +192,9 @@
 
 /// The default connection pool size
 pub const DEFAULT_POOL_SIZE: usize = 10;
+/// The error returned when checking out a connection to an old endpoint
+#[cfg(any(feature = "sync", feature = "aio"))]
+const ERR_STALE_ENDPOINT: &str = "the connection is to an old endpoint of the pool";
 
 // re-exports
 // sync
@@ -155,7 +226,7 @@ cfg_async! {
     /// [`bb8`](https://docs.rs/bb8)'s error type
     pub use bb8::RunError as bb8Error;
     pub use self::async_impls::Pool as AsyncPool;
-    use crate::error::Error;
+    pub use self::async_impls::{spawn_idle_ping, AsyncPoolBuilderExt, IdlePing, IdlePingBuilder};
     /// Returns an async TCP pool of the specified size and provided settings
     pub async fn get_async(host: impl ToString, port: u16, max_size: u32) -> Result<AsyncPool, Error> {
         AsyncPool::builder()
@@ -173,27 +244,181 @@ cfg_async_ssl_any! {
     }
 }
 
+#[cfg(any(feature = "sync", feature = "aio"))]
+use crate::error::{Error, SkyhashError};
+use crate::ConnectionBuilder;
+use crate::IoResult;
+#[cfg(any(feature = "sync", feature = "aio"))]
+use crate::RetryPolicy;
 use core::marker::PhantomData;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+#[cfg(any(feature = "sync", feature = "aio"))]
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Decides whether a pooled connection that ran into an error is handed out again or is
+/// dropped by the pool
+///
+/// Connections that run into an I/O error are always dropped since the state of the stream is
+/// unknown. So are connections that couldn't find the end of a response (like when it holds an
+/// unknown data type), since the rest of it would be read as the response to the next query.
+/// Response codes returned by the server (like "not found") are not errors at the connection
+/// level and never cause a connection to be dropped
+pub enum ErrorPolicy {
+    /// Keep connections that ran into a Skyhash protocol error after reading the whole response
+    /// (like an unexpected data type) and only drop connections that ran into I/O errors or lost
+    /// track of the response. This is the default
+    #[default]
+    KeepOnProtocolError,
+    /// Drop connections that ran into any I/O or Skyhash protocol error
+    DropOnAnyError,
+}
+
+#[derive(Debug)]
+/// The endpoint of a pool that is shared by the clones of a [`ConnectionManager`]
+struct Endpoint {
+    host: String,
+    port: u16,
+    /// incremented every time the endpoint is updated
+    generation: u64,
+    /// the addresses that connections to the current endpoint are connected to
+    peers: HashSet<SocketAddr>,
+}
+
+#[derive(Debug)]
 /// A [`ConnectionManager`] for connection pools. See the [module level documentation](crate::pool)
 /// for examples and more information
+///
+/// Clones of a `ConnectionManager` share the same endpoint (see
+/// [`ConnectionManager::update_endpoint`])
 pub struct ConnectionManager<C> {
-    host: String,
-    port: u16,
+    endpoint: Arc<Mutex<Endpoint>>,
     cert: Option<String>,
+    error_policy: ErrorPolicy,
+    template: Option<ConnectionBuilder>,
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    connect_retry: Option<RetryPolicy>,
     _m: PhantomData<C>,
 }
 
+impl<C> Clone for ConnectionManager<C> {
+    fn clone(&self) -> Self {
+        Self {
+            endpoint: self.endpoint.clone(),
+            cert: self.cert.clone(),
+            error_policy: self.error_policy,
+            template: self.template.clone(),
+            #[cfg(any(feature = "sync", feature = "aio"))]
+            connect_retry: self.connect_retry,
+            _m: PhantomData,
+        }
+    }
+}
+
 impl<C> ConnectionManager<C> {
     fn _new(host: String, port: u16, cert: Option<String>) -> Self {
         Self {
-            host,
-            port,
+            endpoint: Arc::new(Mutex::new(Endpoint {
+                host,
+                port,
+                generation: 0,
+                peers: HashSet::new(),
+            })),
             cert,
+            error_policy: ErrorPolicy::default(),
+            template: None,
+            #[cfg(any(feature = "sync", feature = "aio"))]
+            connect_retry: None,
             _m: PhantomData,
         }
     }
+    /// Point the pool at a new endpoint. New connections are made to `host:port` while
+    /// connections to the old endpoint are replaced when they're checked out or dropped when
+    /// they're returned to the pool. See the [module level documentation](crate::pool) for more
+    /// information
+    pub fn update_endpoint(&self, host: impl ToString, port: u16) {
+        let mut endpoint = self.endpoint();
+        endpoint.host = host.to_string();
+        endpoint.port = port;
+        endpoint.generation += 1;
+        endpoint.peers.clear();
+    }
+    fn endpoint(&self) -> std::sync::MutexGuard<'_, Endpoint> {
+        // the endpoint is always left in a consistent state, so a poisoned lock is fine
+        self.endpoint.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Returns the current host, port and generation of the endpoint
+    fn current_endpoint(&self) -> (String, u16, u64) {
+        let endpoint = self.endpoint();
+        (endpoint.host.clone(), endpoint.port, endpoint.generation)
+    }
+    /// Remember the peer of a connection made to the endpoint of `generation`, unless the
+    /// endpoint was updated in the meantime
+    fn add_peer(&self, generation: u64, peer: IoResult<SocketAddr>) {
+        let mut endpoint = self.endpoint();
+        if let (true, Ok(peer)) = (endpoint.generation == generation, peer) {
+            endpoint.peers.insert(peer);
+        }
+    }
+    /// Check if a connection to `peer` is connected to an old endpoint
+    fn is_stale(&self, peer: IoResult<SocketAddr>) -> bool {
+        match peer {
+            Ok(peer) => !self.endpoint().peers.contains(&peer),
+            Err(_) => true,
+        }
+    }
+    /// Set the [`ErrorPolicy`] for connections in this pool (defaults to
+    /// [`ErrorPolicy::KeepOnProtocolError`])
+    pub fn set_error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+    /// Set up every connection made by the pool (including the connections that replace broken
+    /// or stale ones) with the provided [`ConnectionBuilder`], so that all the connections in
+    /// the pool are configured the same way (for example, with the same entity, credentials and
+    /// bind address). The host and port of the builder are ignored since connections are made
+    /// to the endpoint of the pool. By default, connections are made with
+    /// [`Connection::new`](crate::Connection::new) and use the default entity
+    pub fn set_connection_builder(mut self, builder: ConnectionBuilder) -> Self {
+        self.template = Some(builder);
+        self
+    }
+    /// Retry (as per the provided [`RetryPolicy`]) when a new connection for the pool fails to
+    /// connect because of an I/O error or a timeout (for example, while the server is
+    /// restarting), before returning the error to the caller checking out a connection. Other
+    /// errors (like bad credentials) are returned right away. By default, failed connections
+    /// aren't retried
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use skytable::pool::{ConnectionManager, Pool};
+    /// use skytable::RetryPolicy;
+    /// use std::time::Duration;
+    ///
+    /// let manager = ConnectionManager::new_notls("127.0.0.1", 2003)
+    ///     .set_connect_retry(RetryPolicy::new(5, Duration::from_millis(100)));
+    /// let pool = Pool::builder().max_size(10).build(manager).unwrap();
+    /// ```
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    pub fn set_connect_retry(mut self, policy: RetryPolicy) -> Self {
+        self.connect_retry = Some(policy);
+        self
+    }
+    /// Returns how long to wait before retrying a connection that failed for the `retry`th time
+    /// with `error`, or `None` if it shouldn't be retried
+    #[cfg(any(feature = "sync", feature = "aio"))]
+    fn connect_retry_delay(&self, retry: u32, error: &Error) -> Option<Duration> {
+        match (self.connect_retry, error) {
+            (Some(policy), Error::IoError(_) | Error::SkyError(SkyhashError::Timeout))
+                if retry < policy.max_retries() =>
+            {
+                Some(policy.delay(retry))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl<C> ConnectionManager<C> {
@@ -210,16 +435,17 @@ impl<C> ConnectionManager<C> {
 #[cfg(feature = "sync")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
 mod sync_impls {
-    use super::ConnectionManager;
+    use super::{ConnectionManager, ErrorPolicy};
     use crate::sync::Connection as SyncConnection;
     cfg_sync_ssl_any! {
         use crate::sync::TlsConnection as SyncTlsConnection;
     }
     use crate::{
         error::{Error, SkyhashError},
-        Element, Query, SkyQueryResult, SkyResult,
+        ConnectionBuilder, Element, IoResult, Query, SkyQueryResult, SkyResult,
     };
     use r2d2::ManageConnection;
+    use std::net::SocketAddr;
 
     /// A non-TLS connection pool to Skytable
     pub type Pool = r2d2::Pool<ConnectionManager<SyncConnection>>;
@@ -229,52 +455,108 @@ mod sync_impls {
     }
 
     pub trait PoolableConnection: Send + Sync + Sized {
-        fn get_connection(host: &str, port: u16, tls_cert: Option<&String>) -> SkyResult<Self>;
+        fn get_connection(
+            host: &str,
+            port: u16,
+            tls_cert: Option<&String>,
+            template: Option<&ConnectionBuilder>,
+        ) -> SkyResult<Self>;
         fn run_query(&mut self, q: Query) -> SkyQueryResult;
+        fn has_broken(&self, policy: ErrorPolicy) -> bool;
+        fn peer_addr(&self) -> IoResult<SocketAddr>;
     }
 
     impl PoolableConnection for SyncConnection {
-        fn get_connection(host: &str, port: u16, _tls_cert: Option<&String>) -> SkyResult<Self> {
-            let c = Self::new(host, port)?;
-            Ok(c)
+        fn get_connection(
+            host: &str,
+            port: u16,
+            _tls_cert: Option<&String>,
+            template: Option<&ConnectionBuilder>,
+        ) -> SkyResult<Self> {
+            match template {
+                Some(template) => template
+                    .clone()
+                    .set_host(host.to_owned())
+                    .set_port(port)
+                    .get_connection(),
+                None => Ok(Self::new(host, port)?),
+            }
         }
         fn run_query(&mut self, q: Query) -> SkyQueryResult {
             self.run_query_raw(&q)
         }
+        fn has_broken(&self, policy: ErrorPolicy) -> bool {
+            self.is_broken(policy)
+        }
+        fn peer_addr(&self) -> IoResult<SocketAddr> {
+            self.peer_addr()
+        }
     }
 
     cfg_sync_ssl_any! {
         impl PoolableConnection for SyncTlsConnection {
-            fn get_connection(host: &str, port: u16, tls_cert: Option<&String>) -> SkyResult<Self> {
-                let c = Self::new(
-                    host,
-                    port,
-                    tls_cert.ok_or(Error::ConfigurationError(
-                        "Expected TLS certificate in `ConnectionManager`",
-                    ))?,
-                )?;
-                Ok(c)
+            fn get_connection(
+                host: &str,
+                port: u16,
+                tls_cert: Option<&String>,
+                template: Option<&ConnectionBuilder>,
+            ) -> SkyResult<Self> {
+                let tls_cert = tls_cert.ok_or(Error::ConfigurationError(
+                    "Expected TLS certificate in `ConnectionManager`",
+                ))?;
+                match template {
+                    Some(template) => template
+                        .clone()
+                        .set_host(host.to_owned())
+                        .set_port(port)
+                        .get_tls_connection(tls_cert.clone()),
+                    None => Ok(Self::new(host, port, tls_cert)?),
+                }
             }
             fn run_query(&mut self, q: Query) -> SkyQueryResult {
                 self.run_query_raw(&q)
             }
+            fn has_broken(&self, policy: ErrorPolicy) -> bool {
+                self.is_broken(policy)
+            }
+            fn peer_addr(&self) -> IoResult<SocketAddr> {
+                self.peer_addr()
+            }
         }
     }
     impl<C: PoolableConnection + 'static> ManageConnection for ConnectionManager<C> {
         type Error = Error;
         type Connection = C;
         fn connect(&self) -> Result<Self::Connection, Self::Error> {
-            C::get_connection(self.host.as_ref(), self.port, self.cert.as_ref())
+            let mut retry = 0;
+            loop {
+                // the endpoint might be updated while retrying
+                let (host, port, generation) = self.current_endpoint();
+                match C::get_connection(&host, port, self.cert.as_ref(), self.template.as_ref()) {
+                    Ok(con) => {
+                        self.add_peer(generation, con.peer_addr());
+                        return Ok(con);
+                    }
+                    Err(e) => match self.connect_retry_delay(retry, &e) {
+                        Some(delay) => std::thread::sleep(delay),
+                        None => return Err(e),
+                    },
+                }
+                retry += 1;
+            }
         }
         fn is_valid(&self, con: &mut Self::Connection) -> Result<(), Self::Error> {
+            if self.is_stale(con.peer_addr()) {
+                return Err(Error::ConfigurationError(super::ERR_STALE_ENDPOINT));
+            }
             let q = crate::query!("HEYA");
             match con.run_query(q)? {
                 Element::String(st) if st.eq("HEY!") => Ok(()),
                 _ => Err(Error::SkyError(SkyhashError::UnexpectedResponse)),
             }
         }
-        fn has_broken(&self, _: &mut Self::Connection) -> bool {
-            false
+        fn has_broken(&self, con: &mut Self::Connection) -> bool {
+            con.has_broken(self.error_policy) || self.is_stale(con.peer_addr())
         }
     }
 }
@@ -282,17 +564,28 @@ mod sync_impls {
 #[cfg(feature = "aio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "aio")))]
 mod async_impls {
-    use super::ConnectionManager;
+    use super::{ConnectionManager, ErrorPolicy};
     cfg_async_ssl_any! {
         use crate::aio::TlsConnection as AsyncTlsConnection;
     }
     use crate::{
         aio::Connection as AsyncConnection,
         error::{Error, SkyhashError},
-        Element, Query, SkyQueryResult, SkyResult,
+        ConnectionBuilder, Element, IoResult, Query, SkyQueryResult, SkyResult,
     };
     use async_trait::async_trait;
     use bb8::ManageConnection;
+    use std::cell::Cell;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Weak};
+    use std::time::Duration;
+    use tokio::task::JoinHandle;
+
+    tokio::task_local! {
+        /// Set by `is_valid` when it pings a connection checked out by the idle ping task (if
+        /// the pool tests connections on checkout), so that the task doesn't ping it again
+        static PINGED_ON_CHECK_OUT: Cell<bool>;
+    }
 
     /// An asynchronous non-TLS connection pool to Skytable
     pub type Pool = bb8::Pool<ConnectionManager<AsyncConnection>>;
@@ -306,8 +599,12 @@ mod async_impls {
             host: &str,
             port: u16,
             tls_cert: Option<&String>,
+            template: Option<&ConnectionBuilder>,
         ) -> SkyResult<Self>;
         async fn run_query(&mut self, q: Query) -> SkyQueryResult;
+        fn has_broken(&self, policy: ErrorPolicy) -> bool;
+        fn peer_addr(&self) -> IoResult<SocketAddr>;
+        fn poison(&mut self);
     }
 
     #[async_trait]
@@ -316,13 +613,32 @@ mod async_impls {
             host: &str,
             port: u16,
             _tls_cert: Option<&String>,
+            template: Option<&ConnectionBuilder>,
         ) -> SkyResult<Self> {
-            let con = AsyncConnection::new(host, port).await?;
-            Ok(con)
+            match template {
+                Some(template) => {
+                    template
+                        .clone()
+                        .set_host(host.to_owned())
+                        .set_port(port)
+                        .get_async_connection()
+                        .await
+                }
+                None => Ok(AsyncConnection::new(host, port).await?),
+            }
         }
         async fn run_query(&mut self, q: Query) -> SkyQueryResult {
             self.run_query_raw(&q).await
         }
+        fn has_broken(&self, policy: ErrorPolicy) -> bool {
+            self.is_broken(policy)
+        }
+        fn peer_addr(&self) -> IoResult<SocketAddr> {
+            self.peer_addr()
+        }
+        fn poison(&mut self) {
+            self.poison()
+        }
     }
 
     cfg_async_ssl_any! {
@@ -332,20 +648,35 @@ mod async_impls {
                 host: &str,
                 port: u16,
                 tls_cert: Option<&String>,
+                template: Option<&ConnectionBuilder>,
             ) -> SkyResult<Self> {
-                let con = AsyncTlsConnection::new(
-                    host,
-                    port,
-                    tls_cert.ok_or(Error::ConfigurationError(
-                        "Expected TLS certificate in `ConnectionManager`",
-                    ))?,
-                )
-                .await?;
-                Ok(con)
+                let tls_cert = tls_cert.ok_or(Error::ConfigurationError(
+                    "Expected TLS certificate in `ConnectionManager`",
+                ))?;
+                match template {
+                    Some(template) => {
+                        template
+                            .clone()
+                            .set_host(host.to_owned())
+                            .set_port(port)
+                            .get_async_tls_connection(tls_cert.clone())
+                            .await
+                    }
+                    None => Ok(AsyncTlsConnection::new(host, port, tls_cert).await?),
+                }
             }
             async fn run_query(&mut self, q: Query) -> SkyQueryResult {
                 self.run_query_raw(&q).await
             }
+            fn has_broken(&self, policy: ErrorPolicy) -> bool {
+                self.is_broken(policy)
+            }
+            fn peer_addr(&self) -> IoResult<SocketAddr> {
+                self.peer_addr()
+            }
+            fn poison(&mut self) {
+                self.poison()
+            }
         }
     }
 
@@ -354,16 +685,488 @@ mod async_impls {
         type Connection = C;
         type Error = Error;
         async fn connect(&self) -> Result<Self::Connection, Self::Error> {
-            C::get_connection(&self.host, self.port, self.cert.as_ref()).await
+            let mut retry = 0;
+            loop {
+                // the endpoint might be updated while retrying
+                let (host, port, generation) = self.current_endpoint();
+                let ret =
+                    C::get_connection(&host, port, self.cert.as_ref(), self.template.as_ref())
+                        .await;
+                match ret {
+                    Ok(con) => {
+                        self.add_peer(generation, con.peer_addr());
+                        return Ok(con);
+                    }
+                    Err(e) => match self.connect_retry_delay(retry, &e) {
+                        Some(delay) => tokio::time::sleep(delay).await,
+                        None => return Err(e),
+                    },
+                }
+                retry += 1;
+            }
         }
         async fn is_valid(&self, con: &mut Self::Connection) -> Result<(), Self::Error> {
-            match con.run_query(crate::query!("HEYA")).await? {
-                Element::String(st) if st.eq("HEY!") => Ok(()),
-                _ => Err(Error::SkyError(SkyhashError::UnexpectedResponse)),
+            if self.is_stale(con.peer_addr()) {
+                return Err(Error::ConfigurationError(super::ERR_STALE_ENDPOINT));
             }
+            let ret = ping(con).await;
+            // this is only set for the checkouts of the idle ping task
+            let _ = PINGED_ON_CHECK_OUT.try_with(|pinged| pinged.set(true));
+            ret
+        }
+        fn has_broken(&self, con: &mut Self::Connection) -> bool {
+            con.has_broken(self.error_policy) || self.is_stale(con.peer_addr())
+        }
+    }
+
+    /// Send a `HEYA` on the connection, checking that the server responds with `HEY!`
+    async fn ping<C: PoolableConnection>(con: &mut C) -> Result<(), Error> {
+        match con.run_query(crate::query!("HEYA")).await? {
+            Element::String(st) if st.eq("HEY!") => Ok(()),
+            _ => Err(Error::SkyError(SkyhashError::UnexpectedResponse)),
+        }
+    }
+
+    /// A background task that keeps the idle connections of an async pool alive, started with
+    /// [`spawn_idle_ping`](super::spawn_idle_ping). The task is stopped when this is dropped
+    #[derive(Debug)]
+    #[must_use = "the task is stopped when this is dropped"]
+    pub struct IdlePing {
+        task: JoinHandle<()>,
+    }
+
+    impl Drop for IdlePing {
+        fn drop(&mut self) {
+            self.task.abort();
         }
-        fn has_broken(&self, _: &mut Self::Connection) -> bool {
-            false
+    }
+
+    /// Spawn a task that sends a `HEYA` on every idle connection of the pool once every
+    /// `interval`, so that connections aren't closed by proxies (or firewalls) that close idle
+    /// connections, which would otherwise make the first query after a quiet period fail.
+    /// Connections that fail the ping are dropped by the pool, and connections that are in use
+    /// are left alone. If the pool tests connections on checkout, checking them out already
+    /// pings them, so they aren't pinged twice
+    ///
+    /// The task only holds a weak reference to the pool, so it stops once the pool is dropped
+    /// (or once the returned [`IdlePing`] is dropped, whichever comes first). This must be
+    /// called from within a Tokio runtime. See also [`AsyncPoolBuilderExt::idle_ping_interval`]
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use skytable::pool::{self, AsyncPool, IdlePing};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// struct Db {
+    ///     pool: Arc<AsyncPool>,
+    ///     _idle_ping: IdlePing,
+    /// }
+    ///
+    /// async fn db() -> Db {
+    ///     let pool = Arc::new(pool::get_async("127.0.0.1", 2003, 10).await.unwrap());
+    ///     let idle_ping = pool::spawn_idle_ping(&pool, Duration::from_secs(30));
+    ///     Db { pool, _idle_ping: idle_ping }
+    /// }
+    /// ```
+    #[must_use = "the task is stopped when the returned `IdlePing` is dropped"]
+    pub fn spawn_idle_ping<C: PoolableConnection + 'static>(
+        pool: &Arc<bb8::Pool<ConnectionManager<C>>>,
+        interval: Duration,
+    ) -> IdlePing {
+        IdlePing {
+            task: spawn_idle_ping_task(Arc::downgrade(pool), interval),
         }
     }
+
+    fn spawn_idle_ping_task<C: PoolableConnection + 'static>(
+        pool: Weak<bb8::Pool<ConnectionManager<C>>>,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                // the pool is only held while its idle connections are pinged
+                match pool.upgrade() {
+                    Some(pool) => ping_idle_connections(&pool).await,
+                    None => break,
+                }
+            }
+        })
+    }
+
+    async fn ping_idle_connections<C: PoolableConnection + 'static>(
+        pool: &bb8::Pool<ConnectionManager<C>>,
+    ) {
+        let idle = pool.state().idle_connections;
+        // every connection is held until all the idle ones were pinged, so that no connection
+        // is pinged twice
+        let mut pinged = Vec::with_capacity(idle as usize);
+        for _ in 0..idle {
+            // don't make new connections (or wait for one) if a caller took the remaining idle
+            // connections in the meantime
+            if pool.state().idle_connections == 0 {
+                break;
+            }
+            let check_out = async { (pool.get().await, PINGED_ON_CHECK_OUT.with(Cell::get)) };
+            let (con, pinged_on_check_out) =
+                PINGED_ON_CHECK_OUT.scope(Cell::new(false), check_out).await;
+            let mut con = match con {
+                Ok(con) => con,
+                Err(_) => break,
+            };
+            if !pinged_on_check_out && ping(&mut *con).await.is_err() {
+                con.poison();
+            }
+            pinged.push(con);
+        }
+    }
+
+    /// Adds [`idle_ping_interval`](Self::idle_ping_interval) to bb8's pool
+    /// [`Builder`](bb8::Builder)
+    pub trait AsyncPoolBuilderExt<C: PoolableConnection + 'static> {
+        /// Ping the idle connections of the pool once every `interval`, like
+        /// [`spawn_idle_ping`] does. The task is spawned when the pool is built and stops once
+        /// the pool is dropped
+        ///
+        /// ## Example
+        /// ```no_run
+        /// use skytable::pool::{AsyncPool, AsyncPoolBuilderExt, ConnectionManager};
+        /// use std::time::Duration;
+        ///
+        /// async fn run() {
+        ///     let pool = AsyncPool::builder()
+        ///         .max_size(10)
+        ///         .idle_ping_interval(Duration::from_secs(30))
+        ///         .build(ConnectionManager::new_notls("127.0.0.1", 2003))
+        ///         .await
+        ///         .unwrap();
+        /// }
+        /// ```
+        fn idle_ping_interval(self, interval: Duration) -> IdlePingBuilder<C>;
+    }
+
+    impl<C: PoolableConnection + 'static> AsyncPoolBuilderExt<C>
+        for bb8::Builder<ConnectionManager<C>>
+    {
+        fn idle_ping_interval(self, interval: Duration) -> IdlePingBuilder<C> {
+            IdlePingBuilder {
+                builder: self,
+                interval,
+            }
+        }
+    }
+
+    /// A pool builder that pings the idle connections of the pool it builds. See
+    /// [`AsyncPoolBuilderExt::idle_ping_interval`]
+    pub struct IdlePingBuilder<C: PoolableConnection + 'static> {
+        builder: bb8::Builder<ConnectionManager<C>>,
+        interval: Duration,
+    }
+
+    impl<C: PoolableConnection + 'static> IdlePingBuilder<C> {
+        /// Build the pool like bb8's `Builder::build` does and spawn the task that pings its
+        /// idle connections. The task only holds a weak reference to the pool, which is why the
+        /// pool is returned in an [`Arc`]. This must be called from within a Tokio runtime
+        pub async fn build(
+            self,
+            manager: ConnectionManager<C>,
+        ) -> Result<Arc<bb8::Pool<ConnectionManager<C>>>, Error> {
+            let pool = Arc::new(self.builder.build(manager).await?);
+            // the task stops by itself once the pool is dropped
+            drop(spawn_idle_ping_task(Arc::downgrade(&pool), self.interval));
+            Ok(pool)
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_update_endpoint() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread::{self, JoinHandle};
+    // a server that answers every `HEYA` on every connection, until it's stopped
+    fn spawn_server(stop: Arc<AtomicBool>) -> (u16, JoinHandle<()>) {
+        crate::mock::serve(move |listener| {
+            let mut handlers = Vec::new();
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let mut stream = stream.unwrap();
+                handlers.push(thread::spawn(move || {
+                    // the query is `*1\n4\nHEYA`
+                    let mut buf = [0u8; 9];
+                    while stream.read_exact(&mut buf).is_ok() {
+                        stream.write_all(b"*+4\nHEY!").unwrap();
+                    }
+                }));
+            }
+            for handler in handlers {
+                handler.join().unwrap();
+            }
+        })
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let (blue, blue_server) = spawn_server(stop.clone());
+    let (green, green_server) = spawn_server(stop.clone());
+    let manager = ConnectionManager::new_notls("127.0.0.1", blue);
+    let pool = Pool::builder().max_size(2).build(manager.clone()).unwrap();
+    let con = pool.get().unwrap();
+    assert_eq!(con.peer_addr().unwrap().port(), blue);
+    manager.update_endpoint("127.0.0.1", green);
+    // the connection in use is dropped when it's returned and the idle one is replaced
+    drop(con);
+    let (a, b) = (pool.get().unwrap(), pool.get().unwrap());
+    assert_eq!(a.peer_addr().unwrap().port(), green);
+    assert_eq!(b.peer_addr().unwrap().port(), green);
+    // close every connection and stop the servers, waking them up so that they see the flag
+    drop((a, b, pool));
+    stop.store(true, Ordering::SeqCst);
+    for port in [blue, green] {
+        drop(TcpStream::connect(("127.0.0.1", port)).unwrap());
+    }
+    blue_server.join().unwrap();
+    green_server.join().unwrap();
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_connection_builder_template() {
+    use std::io::{Read, Write};
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // every new connection switches to the entity of the template
+        let mut query = [0u8; 16];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*2\n3\nuse6\nks:tbl");
+        stream.write_all(b"*!0\n").unwrap();
+        let mut query = [0u8; 9];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*1\n4\nHEYA");
+        stream.write_all(b"*+4\nHEY!").unwrap();
+    });
+    let template = ConnectionBuilder::new()
+        .set_host("ignored.invalid".to_owned())
+        .set_entity("ks:tbl".to_owned())
+        .set_name("pooled".to_owned());
+    let manager = ConnectionManager::new_notls("127.0.0.1", port).set_connection_builder(template);
+    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    let con = pool.get().unwrap();
+    assert_eq!(con.name(), Some("pooled"));
+    server.join().unwrap();
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_template_read_timeout() {
+    use crate::actions::Actions;
+    use crate::error::SkyhashError;
+    use std::io::{Read, Write};
+    use std::sync::mpsc;
+    use std::time::Duration;
+    let (done_tx, done_rx) = mpsc::channel();
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 16]).unwrap();
+        stream.write_all(b"*!0\n").unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        // the response to the `DEL` never arrives
+        stream.read_exact(&mut [0u8; 11]).unwrap();
+        done_rx.recv().unwrap();
+    });
+    let template = ConnectionBuilder::new()
+        .set_entity("ks:tbl".to_owned())
+        .set_read_timeout(Duration::from_millis(20));
+    let manager = ConnectionManager::new_notls("127.0.0.1", port).set_connection_builder(template);
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .build(manager)
+        .unwrap();
+    let mut con = pool.get().unwrap();
+    assert_eq!(con.del("x").unwrap_err(), SkyhashError::Timeout.into());
+    // the connection is poisoned, so it's dropped from the pool
+    drop(con);
+    assert_eq!(pool.state().connections, 0);
+    done_tx.send(()).unwrap();
+    server.join().unwrap();
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_connect_retry() {
+    use crate::RetryPolicy;
+    use r2d2::ManageConnection;
+    use std::net::TcpListener;
+    use std::time::Duration;
+    // find a free port, and start listening on it only after a while
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let manager = ConnectionManager::<crate::Connection>::new_notls("127.0.0.1", port);
+    assert!(matches!(manager.connect(), Err(Error::IoError(_))));
+    let server = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        listener.accept().unwrap();
+    });
+    let manager = manager.set_connect_retry(RetryPolicy::new(10, Duration::from_millis(20)));
+    let con = manager.connect().unwrap();
+    assert_eq!(con.peer_addr().unwrap().port(), port);
+    server.join().unwrap();
+}
+
+#[cfg(feature = "aio")]
+#[tokio::test]
+async fn test_async_pool() {
+    use crate::actions::AsyncActions;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        // the connection is checked with `HEYA` when it's checked out
+        let mut query = [0u8; 9];
+        stream.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"*1\n4\nHEYA");
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+        let mut query = [0u8; 11];
+        stream.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"*2\n3\ndel1\nx");
+        stream.write_all(b"*:1\n").await.unwrap();
+    })
+    .await;
+    let manager = ConnectionManager::new_notls("127.0.0.1", port);
+    let pool = AsyncPool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .build(manager)
+        .await
+        .unwrap();
+    let mut con = pool.get().await.unwrap();
+    assert_eq!(con.del("x").await.unwrap(), 1);
+    drop(con);
+    server.await.unwrap();
+}
+
+/// Start a server that closes the first connection once it's checked out, and answers a `DEL`
+/// on the second one. Returns a pool of a single connection to it, along with the server
+#[cfg(all(test, feature = "sync"))]
+fn serve_closing_first_connection() -> (Pool, std::thread::JoinHandle<()>) {
+    use std::io::{Read, Write};
+    let (port, server) = crate::mock::serve(move |listener| {
+        let mut heya = [0u8; 9];
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_exact(&mut heya).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        drop(stream);
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_exact(&mut heya).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        let mut query = [0u8; 11];
+        stream.read_exact(&mut query).unwrap();
+        stream.write_all(b"*:1\n").unwrap();
+    });
+    let manager = ConnectionManager::new_notls("127.0.0.1", port);
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .build(manager)
+        .unwrap();
+    (pool, server)
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_broken_connection_is_replaced() {
+    use crate::actions::Actions;
+    let (pool, server) = serve_closing_first_connection();
+    // the connection is closed while it's in use
+    let mut con = pool.get().unwrap();
+    assert!(con.del("x").is_err());
+    drop(con);
+    // the broken connection was dropped by the pool instead of being handed out again
+    assert_eq!(pool.get().unwrap().del("x").unwrap(), 1);
+    server.join().unwrap();
+}
+
+#[cfg(feature = "aio")]
+#[tokio::test]
+async fn test_idle_ping() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::time::sleep;
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        let mut query = [0u8; 9];
+        stream.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"*1\n4\nHEYA");
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+        // the second ping fails
+        stream.read_exact(&mut query).await.unwrap();
+        stream.write_all(b"*!0\n").await.unwrap();
+    })
+    .await;
+    let manager = ConnectionManager::new_notls("127.0.0.1", port);
+    let pool = AsyncPool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .test_on_check_out(false)
+        .build(manager)
+        .await
+        .unwrap();
+    let pool = Arc::new(pool);
+    drop(pool.get().await.unwrap());
+    let idle_ping = spawn_idle_ping(&pool, Duration::from_millis(20));
+    server.await.unwrap();
+    // the connection that failed the ping is dropped
+    for _ in 0..100 {
+        if pool.state().connections == 0 {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(pool.state().connections, 0);
+    drop(idle_ping);
+}
+
+#[cfg(feature = "aio")]
+#[tokio::test]
+async fn test_idle_ping_interval() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+    let (pinged_tx, pinged_rx) = oneshot::channel();
+    let (port, server) = crate::mock::serve_once_async(|mut stream| async move {
+        // the pool tests connections on checkout, so the idle ping task doesn't ping again
+        let mut query = [0u8; 9];
+        stream.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"*1\n4\nHEYA");
+        stream.write_all(b"*+4\nHEY!").await.unwrap();
+        pinged_tx.send(()).unwrap();
+        // the task doesn't keep the pool alive, so the connection is closed with the pool
+        assert_eq!(stream.read(&mut query).await.unwrap(), 0);
+    })
+    .await;
+    let pool = AsyncPool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .idle_ping_interval(Duration::from_millis(20))
+        .build(ConnectionManager::new_notls("127.0.0.1", port))
+        .await
+        .unwrap();
+    drop(pool.get().await.unwrap());
+    pinged_rx.await.unwrap();
+    drop(pool);
+    server.await.unwrap();
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_idle_connection_is_reestablished() {
+    use crate::actions::Actions;
+    let (pool, server) = serve_closing_first_connection();
+    // the connection dies while it's idle in the pool
+    drop(pool.get().unwrap());
+    // the dead connection fails the `HEYA` and is replaced before it's handed out
+    assert_eq!(pool.get().unwrap().del("x").unwrap(), 1);
+    server.join().unwrap();
 }
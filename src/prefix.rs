@@ -0,0 +1,61 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Key prefixes
+//!
+//! A wrapper around a connection that prepends a prefix to keys, to keep the keys of different
+//! tenants (or features) apart in the same table. Its `get`, `set` and `del` are implemented for
+//! every sync and async connection, next to the other methods of the connection
+//!
+
+use crate::types::{IntoSkyhashBytes, RawString};
+
+/// A connection that prepends a prefix to keys. See `with_prefix` on the
+/// [sync](crate::sync::Connection::with_prefix) and
+/// [async](crate::aio::Connection::with_prefix) connections
+pub struct Prefixed<'a, C> {
+    pub(crate) con: &'a mut C,
+    prefix: String,
+}
+
+impl<'a, C> Prefixed<'a, C> {
+    pub(crate) fn new(con: &'a mut C, prefix: &str) -> Self {
+        Self {
+            con,
+            prefix: prefix.to_owned(),
+        }
+    }
+    /// Returns the prefix prepended to keys
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    /// Returns `key` with the prefix prepended. This can be used to pass prefixed keys to
+    /// actions that don't have a helper here
+    pub fn key(&self, key: impl IntoSkyhashBytes) -> RawString {
+        let mut prefixed = str::as_bytes(&self.prefix).to_vec();
+        key.write_skyhash(&mut prefixed);
+        RawString::from(prefixed)
+    }
+    /// Returns `key` without the prefix, or `None` if `key` doesn't start with the prefix. This
+    /// can be used on keys returned by the server (for example, by `lskeys`)
+    pub fn strip_prefix<'k>(&self, key: &'k str) -> Option<&'k str> {
+        key.strip_prefix(self.prefix.as_str())
+    }
+    /// Returns the underlying connection
+    pub fn inner(&mut self) -> &mut C {
+        self.con
+    }
+}
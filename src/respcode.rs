@@ -20,7 +20,7 @@
 use core::fmt;
 
 /// Response codes returned by the server
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum RespCode {
     /// `0`: Okay (Empty Response)
@@ -35,7 +35,8 @@ pub enum RespCode {
     PacketError,
     /// `5`: Server Error
     ServerError,
-    /// `6`: Some other Error, which is a string
+    /// `6`: Some other Error, which is a string. The server sends these as response codes that
+    /// aren't numbers (like `!err-snapshot-busy`), so they are never confused with string data
     ErrorString(String),
     /// `6`: The same as [`RespCode::ErrorString`] but without any explicit information
     OtherError,
@@ -73,12 +74,10 @@ impl RespCode {
             Err(_) => ErrorString(st.to_owned()),
         }
     }
-}
-
-impl From<RespCode> for u8 {
-    fn from(rcode: RespCode) -> u8 {
+    /// Returns the numeric code of this response code
+    pub(crate) fn code(&self) -> u8 {
         use RespCode::*;
-        match rcode {
+        match self {
             Okay => 0,
             NotFound => 1,
             OverwriteError => 2,
@@ -95,6 +94,12 @@ impl From<RespCode> for u8 {
     }
 }
 
+impl From<RespCode> for u8 {
+    fn from(rcode: RespCode) -> u8 {
+        rcode.code()
+    }
+}
+
 impl fmt::Display for RespCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use RespCode::*;
@@ -115,3 +120,7 @@ impl fmt::Display for RespCode {
         }
     }
 }
+
+/// Response codes can be returned as errors by themselves (for example, from functions that
+/// only fail with a response code), so that they compose with `?` and other error types
+impl std::error::Error for RespCode {}
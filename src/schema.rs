@@ -0,0 +1,162 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Action schemas
+//!
+//! This module contains the [`ActionSchema`] registry, which maps actions to the number of
+//! arguments they accept. Queries built from user input can then be checked with
+//! [`Query::validate`](crate::Query::validate) before they are sent, instead of finding out
+//! that they were malformed from an [`ActionError`](crate::RespCode::ActionError) returned by
+//! the server
+//!
+//! ## Example
+//! ```
+//! use skytable::query;
+//! use skytable::schema::{ActionSchema, Arity};
+//!
+//! let schema = ActionSchema::builtin().add("myaction", Arity::Exactly(1));
+//! assert!(query!("set", "x", "100").validate(&schema).is_ok());
+//! assert!(query!("set", "x").validate(&schema).is_err());
+//! assert!(query!("myaction", "x").validate(&schema).is_ok());
+//! ```
+//!
+
+use core::fmt;
+use std::collections::HashMap;
+
+/// The number of arguments accepted by an action (not counting the action itself)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    /// Exactly this many arguments
+    Exactly(usize),
+    /// At least this many arguments
+    AtLeast(usize),
+    /// At least as many arguments as the first value and at most as many as the second value
+    Between(usize, usize),
+    /// At least this many key/value pairs (and hence, an even number of arguments)
+    Pairs(usize),
+}
+
+impl Arity {
+    /// Returns true if `count` arguments are accepted
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Self::Exactly(n) => count == n,
+            Self::AtLeast(n) => count >= n,
+            Self::Between(min, max) => (min..=max).contains(&count),
+            Self::Pairs(n) => count & 1 == 0 && count / 2 >= n,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Exactly(n) => write!(f, "exactly {}", n),
+            Self::AtLeast(n) => write!(f, "at least {}", n),
+            Self::Between(min, max) => write!(f, "between {} and {}", min, max),
+            Self::Pairs(n) => write!(f, "an even number (at least {}) of", n * 2),
+        }
+    }
+}
+
+/// The actions known to [`ActionSchema::builtin`]
+const BUILTIN_ACTIONS: [(&str, Arity); 30] = [
+    ("auth", Arity::AtLeast(1)),
+    ("create", Arity::AtLeast(2)),
+    ("dbsize", Arity::Between(0, 1)),
+    ("del", Arity::AtLeast(1)),
+    ("drop", Arity::AtLeast(2)),
+    ("exists", Arity::AtLeast(1)),
+    ("flushdb", Arity::Between(0, 1)),
+    ("get", Arity::Exactly(1)),
+    ("heya", Arity::Between(0, 1)),
+    ("inspect", Arity::AtLeast(1)),
+    ("keylen", Arity::Exactly(1)),
+    ("lget", Arity::AtLeast(1)),
+    ("lmod", Arity::AtLeast(2)),
+    ("lset", Arity::AtLeast(1)),
+    ("lskeys", Arity::Between(0, 2)),
+    ("mget", Arity::AtLeast(1)),
+    ("mksnap", Arity::Between(0, 1)),
+    ("mpop", Arity::AtLeast(1)),
+    ("mset", Arity::Pairs(1)),
+    ("mupdate", Arity::Pairs(1)),
+    ("pop", Arity::Exactly(1)),
+    ("sdel", Arity::AtLeast(1)),
+    ("set", Arity::Exactly(2)),
+    ("sset", Arity::Pairs(1)),
+    ("supdate", Arity::Pairs(1)),
+    ("sys", Arity::AtLeast(1)),
+    ("update", Arity::Exactly(2)),
+    ("use", Arity::Exactly(1)),
+    ("uset", Arity::Pairs(1)),
+    ("whereami", Arity::Exactly(0)),
+];
+
+/// A registry of actions and the number of arguments they accept. Actions are matched case
+/// insensitively. Queries with actions that aren't in the registry pass validation, unless
+/// unknown actions are rejected with [`ActionSchema::set_reject_unknown`]
+#[derive(Debug, Clone, Default)]
+pub struct ActionSchema {
+    actions: HashMap<String, Arity>,
+    reject_unknown: bool,
+}
+
+impl ActionSchema {
+    /// Create an empty schema
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a schema with the actions supported by the server
+    pub fn builtin() -> Self {
+        BUILTIN_ACTIONS
+            .iter()
+            .fold(Self::new(), |schema, (action, arity)| {
+                schema.add(action, *arity)
+            })
+    }
+    /// Add an action to the schema, replacing it if it already exists
+    pub fn add(mut self, action: &str, arity: Arity) -> Self {
+        self.actions.insert(action.to_ascii_lowercase(), arity);
+        self
+    }
+    /// Reject queries with actions that aren't in the schema (by default, they are accepted)
+    pub fn set_reject_unknown(mut self, reject_unknown: bool) -> Self {
+        self.reject_unknown = reject_unknown;
+        self
+    }
+    /// Returns the arity of the provided action, if it is in the schema
+    pub fn get(&self, action: &str) -> Option<Arity> {
+        self.actions.get(&action.to_ascii_lowercase()).copied()
+    }
+    pub(crate) fn rejects_unknown(&self) -> bool {
+        self.reject_unknown
+    }
+}
+
+#[test]
+fn test_arity_accepts() {
+    assert!(Arity::Exactly(2).accepts(2));
+    assert!(!Arity::Exactly(2).accepts(3));
+    assert!(Arity::AtLeast(1).accepts(5));
+    assert!(!Arity::AtLeast(1).accepts(0));
+    assert!(Arity::Between(0, 2).accepts(0));
+    assert!(!Arity::Between(0, 2).accepts(3));
+    assert!(Arity::Pairs(1).accepts(4));
+    assert!(!Arity::Pairs(1).accepts(3));
+    assert!(!Arity::Pairs(1).accepts(0));
+}
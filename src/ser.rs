@@ -0,0 +1,329 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Serde serializer
+//!
+//! Flattens the fields of a [`Serialize`] struct into query arguments, in declaration order.
+//! Only flat structs are supported: every field must be a string, a number, a boolean, a
+//! character, bytes, an `Option` of one of these or a unit enum variant. Anything nested
+//! returns [`SerializeError::Unsupported`]. See [`Query::from_serde`]
+//!
+
+use crate::error::SerializeError;
+use crate::Query;
+use core::fmt::Display;
+use serde::ser::{self, Impossible, Serialize};
+
+impl ser::Error for SerializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// Serializes a struct (or a tuple) into the arguments of a query, one argument per field
+pub(crate) struct QuerySerializer<'a> {
+    pub(crate) query: &'a mut Query,
+}
+
+macro_rules! unsupported {
+    ($what:literal: $($method:ident($($ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<$ret, SerializeError> {
+                Err(SerializeError::Unsupported($what))
+            }
+        )*
+    };
+}
+
+impl<'a> ser::Serializer for QuerySerializer<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+    type SerializeSeq = Impossible<(), SerializeError>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), SerializeError>;
+    type SerializeMap = Impossible<(), SerializeError>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), SerializeError>;
+
+    unsupported! {
+        "only structs and tuples can be turned into queries":
+        serialize_bool(bool) -> ();
+        serialize_i8(i8) -> ();
+        serialize_i16(i16) -> ();
+        serialize_i32(i32) -> ();
+        serialize_i64(i64) -> ();
+        serialize_u8(u8) -> ();
+        serialize_u16(u16) -> ();
+        serialize_u32(u32) -> ();
+        serialize_u64(u64) -> ();
+        serialize_f32(f32) -> ();
+        serialize_f64(f64) -> ();
+        serialize_char(char) -> ();
+        serialize_str(&str) -> ();
+        serialize_bytes(&[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(&'static str) -> ();
+        serialize_unit_variant(&'static str, u32, &'static str) -> ();
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeStructVariant;
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<(), SerializeError> {
+        Err(SerializeError::Unsupported(
+            "only structs and tuples can be turned into queries",
+        ))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), SerializeError> {
+        Err(SerializeError::Unsupported(
+            "only structs and tuples can be turned into queries",
+        ))
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self, SerializeError> {
+        Ok(self)
+    }
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, SerializeError> {
+        Ok(self)
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, SerializeError> {
+        Ok(self)
+    }
+}
+
+impl<'a> QuerySerializer<'a> {
+    fn push_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        let arg = value.serialize(ArgSerializer)?;
+        self.query._push_arg(arg);
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for QuerySerializer<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        self.push_field(value)
+    }
+    fn end(self) -> Result<(), SerializeError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for QuerySerializer<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), SerializeError> {
+        self.push_field(value)
+    }
+    fn end(self) -> Result<(), SerializeError> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for QuerySerializer<'a> {
+    type Ok = ();
+    type Error = SerializeError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push_field(value)
+    }
+    fn end(self) -> Result<(), SerializeError> {
+        Ok(())
+    }
+}
+
+/// Serializes a single field into the bytes of an argument. `None` (and `()`) is sent as an
+/// empty argument, so that the positions of the other fields don't change
+struct ArgSerializer;
+
+macro_rules! to_string {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method(self, value: $ty) -> Result<Vec<u8>, SerializeError> {
+                Ok(value.to_string().into_bytes())
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for ArgSerializer {
+    type Ok = Vec<u8>;
+    type Error = SerializeError;
+    type SerializeSeq = Impossible<Vec<u8>, SerializeError>;
+    type SerializeTuple = Impossible<Vec<u8>, SerializeError>;
+    type SerializeTupleStruct = Impossible<Vec<u8>, SerializeError>;
+    type SerializeTupleVariant = Impossible<Vec<u8>, SerializeError>;
+    type SerializeMap = Impossible<Vec<u8>, SerializeError>;
+    type SerializeStruct = Impossible<Vec<u8>, SerializeError>;
+    type SerializeStructVariant = Impossible<Vec<u8>, SerializeError>;
+
+    to_string!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str)
+    );
+    fn serialize_bytes(self, value: &[u8]) -> Result<Vec<u8>, SerializeError> {
+        Ok(value.to_vec())
+    }
+    fn serialize_none(self) -> Result<Vec<u8>, SerializeError> {
+        Ok(Vec::new())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, SerializeError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Vec<u8>, SerializeError> {
+        Ok(Vec::new())
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Vec<u8>, SerializeError> {
+        Ok(Vec::new())
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Vec<u8>, SerializeError> {
+        Ok(variant.as_bytes().to_vec())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Vec<u8>, SerializeError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Vec<u8>, SerializeError> {
+        Err(SerializeError::Unsupported(
+            "nested values can't be query arguments",
+        ))
+    }
+    unsupported! {
+        "nested values can't be query arguments":
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize)
+            -> Self::SerializeStructVariant;
+    }
+}
+
+#[test]
+fn test_from_serde() {
+    use serde::Serialize;
+    #[derive(Serialize)]
+    enum Role {
+        Admin,
+    }
+    #[derive(Serialize)]
+    struct User<'a> {
+        name: &'a str,
+        age: u8,
+        score: f32,
+        active: bool,
+        nickname: Option<String>,
+        role: Role,
+    }
+    let user = User {
+        name: "sayan",
+        age: 20,
+        score: 1.5,
+        active: true,
+        nickname: None,
+        role: Role::Admin,
+    };
+    let query = Query::from_serde("mset", &user).unwrap();
+    assert_eq!(
+        query,
+        Query::from("mset").arg(vec!["sayan", "20", "1.5", "true", "", "Admin"])
+    );
+    let query = Query::from_serde("set", &("x", 100)).unwrap();
+    assert_eq!(query, Query::from("set").arg("x").arg("100"));
+}
+
+#[test]
+fn test_from_serde_unsupported() {
+    use serde::Serialize;
+    #[derive(Serialize)]
+    struct Inner {
+        x: u8,
+    }
+    #[derive(Serialize)]
+    struct Outer {
+        inner: Inner,
+    }
+    assert!(matches!(
+        Query::from_serde(
+            "set",
+            &Outer {
+                inner: Inner { x: 1 }
+            }
+        ),
+        Err(SerializeError::Unsupported(_))
+    ));
+    assert!(matches!(
+        Query::from_serde("set", &(vec![1, 2],)),
+        Err(SerializeError::Unsupported(_))
+    ));
+    assert!(matches!(
+        Query::from_serde("set", "x"),
+        Err(SerializeError::Unsupported(_))
+    ));
+}
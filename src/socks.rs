@@ -0,0 +1,199 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # SOCKS5
+//!
+//! Tunneling connections through a SOCKS5 proxy, with the [`socks`] crate for sync connections
+//! and the [`tokio_socks`] crate for async connections. This needs the `socks` feature; without
+//! it, [`Socks5Proxy`] is an empty type so that no proxy can ever be set
+//!
+
+cfg_socks! {
+    /// A SOCKS5 proxy that connections are tunneled through. See
+    /// [`ConnectionBuilder::set_proxy`](crate::ConnectionBuilder::set_proxy)
+    ///
+    /// The host of the Skytable server is sent to the proxy as-is (unless it is an IP address), so
+    /// it is resolved by the proxy and not by the client
+    #[derive(Clone, PartialEq)]
+    pub struct Socks5Proxy {
+        pub(crate) host: String,
+        pub(crate) port: u16,
+        auth: Option<(String, String)>,
+    }
+
+    impl core::fmt::Debug for Socks5Proxy {
+        fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            // don't leak the password into logs
+            f.debug_struct("Socks5Proxy")
+                .field("host", &self.host)
+                .field("port", &self.port)
+                .field(
+                    "auth",
+                    &self
+                        .auth
+                        .as_ref()
+                        .map(|(username, _)| (username, "<redacted>")),
+                )
+                .finish()
+        }
+    }
+
+    impl Socks5Proxy {
+        /// Create a new proxy configuration for the SOCKS5 proxy running at `host:port`, that doesn't
+        /// need authentication
+        pub fn new(host: String, port: u16) -> Self {
+            Self {
+                host,
+                port,
+                auth: None,
+            }
+        }
+        /// Authenticate with the proxy using the provided username and password. Both can be at most
+        /// 255 bytes long
+        pub fn set_auth(mut self, username: String, password: String) -> Self {
+            self.auth = Some((username, password));
+            self
+        }
+    }
+}
+
+#[cfg(not(feature = "socks"))]
+#[derive(Debug, Clone, PartialEq)]
+/// Proxies need the `socks` feature, so this can't be constructed without it
+pub(crate) enum Socks5Proxy {}
+
+#[cfg(all(feature = "sync", feature = "socks"))]
+/// Connect to `host:port` through the proxy, which is at one of `proxy_addrs`
+pub(crate) fn connect_sync(
+    proxy_addrs: &[std::net::SocketAddr],
+    proxy: &Socks5Proxy,
+    host: &str,
+    port: u16,
+) -> crate::IoResult<std::net::TcpStream> {
+    let stream = match &proxy.auth {
+        Some((username, password)) => socks::Socks5Stream::connect_with_password(
+            proxy_addrs,
+            (host, port),
+            username,
+            password,
+        ),
+        None => socks::Socks5Stream::connect(proxy_addrs, (host, port)),
+    }?;
+    Ok(stream.into_inner())
+}
+
+cfg_async! {
+    #[cfg(feature = "socks")]
+    /// Ask the proxy on the other end of `stream` to connect to `host:port`
+    pub(crate) async fn handshake_async(
+        stream: tokio::net::TcpStream,
+        proxy: &Socks5Proxy,
+        host: &str,
+        port: u16,
+    ) -> crate::IoResult<tokio::net::TcpStream> {
+        use tokio_socks::tcp::Socks5Stream;
+        use tokio_socks::Error;
+        let ret = match &proxy.auth {
+            Some((username, password)) => {
+                Socks5Stream::connect_with_password_and_socket(
+                    stream,
+                    (host, port),
+                    username,
+                    password,
+                )
+                .await
+            }
+            None => Socks5Stream::connect_with_socket(stream, (host, port)).await,
+        };
+        match ret {
+            Ok(stream) => Ok(stream.into_inner()),
+            Err(Error::Io(e)) => Err(e),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+        }
+    }
+}
+
+#[cfg(all(feature = "sync", feature = "socks"))]
+#[test]
+fn test_connect_through_proxy() {
+    use std::io::{Read, Write};
+    let (port, proxy) = crate::mock::serve_once(move |mut stream| {
+        let mut greeting = [0u8; 4];
+        stream.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 2, 2, 0]);
+        stream.write_all(&[5, 2]).unwrap();
+        let mut auth = [0u8; 11];
+        stream.read_exact(&mut auth).unwrap();
+        assert_eq!(&auth, b"\x01\x04user\x04pass");
+        stream.write_all(&[1, 0]).unwrap();
+        let mut request = [0u8; 16];
+        stream.read_exact(&mut request).unwrap();
+        assert_eq!(&request, b"\x05\x01\x00\x03\x09db.remote\x07\xd3");
+        stream
+            .write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x07, 0xd3])
+            .unwrap();
+        // then the proxy relays the Skyhash connection (`USE default:default`)
+        let mut query = [0u8; 26];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*2\n3\nuse15\ndefault:default");
+        stream.write_all(b"*!0\n").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let proxy_config = Socks5Proxy::new("127.0.0.1".to_owned(), port)
+        .set_auth("user".to_owned(), "pass".to_owned());
+    let con = crate::ConnectionBuilder::new()
+        .set_host("db.remote".to_owned())
+        .set_port(2003)
+        .set_proxy(proxy_config)
+        .get_connection()
+        .unwrap();
+    drop(con);
+    proxy.join().unwrap();
+}
+
+#[cfg(all(feature = "aio", feature = "socks"))]
+#[tokio::test]
+async fn test_connect_through_proxy_async() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let (port, proxy) = crate::mock::serve_once_async(|mut stream| async move {
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        stream.write_all(&[5, 0]).await.unwrap();
+        let mut request = [0u8; 10];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"\x05\x01\x00\x01\x0a\x00\x00\x02\x07\xd3");
+        stream
+            .write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x07, 0xd3])
+            .await
+            .unwrap();
+        // then the proxy relays the Skyhash connection (`USE default:default`)
+        let mut query = [0u8; 26];
+        stream.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"*2\n3\nuse15\ndefault:default");
+        stream.write_all(b"*!0\n").await.unwrap();
+    })
+    .await;
+    let con = crate::ConnectionBuilder::new()
+        .set_host("10.0.0.2".to_owned())
+        .set_port(2003)
+        .set_proxy(Socks5Proxy::new("127.0.0.1".to_owned(), port))
+        .get_async_connection()
+        .await
+        .unwrap();
+    drop(con);
+    proxy.await.unwrap();
+}
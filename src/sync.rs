@@ -24,21 +24,50 @@
 //! All the [actions][crate::actions::Actions] can be used on both the connection types
 //!
 
-use crate::deserializer::{ParseError, Parser, RawResponse};
-use crate::error::SkyhashError;
-use crate::types::FromSkyhashBytes;
+use crate::actions::{Actions, SyncSocket};
+use crate::budget::Reservation;
+use crate::deserializer::{ArrayKind, ParseError, Parser, RawResponse};
+use crate::dns::DnsCache;
+use crate::socks::Socks5Proxy;
+use crate::error::{Error, PipelineError, SkyhashError};
+use crate::pool::ErrorPolicy;
+use crate::types::{FromSkyhashBytes, Health, IntoSkyhashBytes, SysMetric, Version};
+use crate::AddressFamily;
+use crate::ArgList;
+use crate::Compression;
+use crate::ConnectionBuilder;
+use crate::ConnectionState;
 use crate::Element;
+use crate::ElementType;
+use crate::MemoryBudget;
 use crate::Pipeline;
+use crate::Prefixed;
 use crate::Query;
+use crate::QueryHook;
+use crate::RespCode;
+use crate::ResponseHeader;
+use crate::ResponseVisitor;
+use crate::RetryPolicy;
 use crate::SkyQueryResult;
 use crate::SkyResult;
 use crate::WriteQuerySync;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::collections::VecDeque;
 use std::io::{Error as IoError, ErrorKind, Read, Write};
-use std::net::TcpStream;
+use std::mem;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 macro_rules! impl_sync_methods {
     ($ty:ty) => {
-        impl $ty {
+        impl_sync_methods!(@impl [] $ty);
+    };
+    ($ty:ty, $stream:ident) => {
+        impl_sync_methods!(@impl [$stream: Read + Write] $ty);
+    };
+    (@impl [$($generics:tt)*] $ty:ty) => {
+        impl<$($generics)*> $ty {
             /// Runs a query using [`Self::run_query_raw`] and attempts to return a type provided by the user
             pub fn run_query<T: FromSkyhashBytes, Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<T> {
                 self.run_query_raw(query)?.try_element_into()
@@ -48,14 +77,178 @@ macro_rules! impl_sync_methods {
             /// or invalid and return an appropriate variant of [`Error`](crate::error::Error)
             /// for any I/O errors that may occur
             ///
-            /// ## Panics
-            /// This method will panic:
-            /// - if the [`Query`] supplied is empty (i.e has no arguments)
-            /// This function is a subroutine of `run_query` used to parse the response packet
+            /// An empty [`Query`] (with no arguments) isn't sent and returns
+            /// [`Error::ConfigurationError`](crate::error::Error::ConfigurationError)
             pub fn run_query_raw<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<Element> {
-                match self._run_query(query.as_ref())? {
-                    RawResponse::SimpleQuery(sq) => Ok(sq),
-                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                let query = query.as_ref();
+                let action = query.iter_args().next();
+                if action.map_or(false, |action| action.eq_ignore_ascii_case(b"use")) {
+                    // the entity is changed behind the back of `use_entity`
+                    self.entity = None;
+                }
+                let ret = match self._run_query(query) {
+                    Ok(RawResponse::SimpleQuery(sq)) => Ok(sq),
+                    Ok(RawResponse::PipelinedQuery(_)) => Err(SkyhashError::InvalidResponse.into()),
+                    Err(e) => Err(e),
+                };
+                if let Some(hook) = &self.query_hook {
+                    hook.call(query, &ret);
+                }
+                ret
+            }
+            /// Runs a query like [`Self::run_query_raw`], but also returns the raw bytes of
+            /// the response frame exactly as they were received from the server. This is useful
+            /// to forward responses as-is (for example, in a proxy) without serializing them again
+            pub fn run_with_raw<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<(Element, Vec<u8>)> {
+                let mut raw = Vec::new();
+                let element = self.run_into(query, &mut raw)?;
+                Ok((element, raw))
+            }
+            /// Runs a query like [`Self::run_query_raw`] and returns a
+            /// [`SkyhashError::ResponseMismatch`] error (holding both responses) if the response
+            /// isn't equal to `expected`. This is mostly useful in tests
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use skytable::{query, Connection, Element, RespCode};
+            ///
+            /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+            /// con.run_expecting(query!("set", "x", "100"), &Element::RespCode(RespCode::Okay))
+            ///     .unwrap();
+            /// ```
+            pub fn run_expecting<Q: AsRef<Query>>(&mut self, query: Q, expected: &Element) -> SkyResult<()> {
+                let actual = self.run_query_raw(query)?;
+                crate::check_expected(expected, actual)
+            }
+            /// Runs a query like [`Self::run_with_raw`], but writes the raw bytes of the response
+            /// frame into the provided buffer (which is cleared first) instead of allocating a
+            /// new one. Reusing the same buffer for many queries avoids an allocation per query
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use skytable::{query, Connection};
+            ///
+            /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+            /// let mut raw = Vec::with_capacity(1024);
+            /// for key in ["x", "y", "z"] {
+            ///     let element = con.run_into(query!("get", key), &mut raw).unwrap();
+            ///     println!("{:?} ({} bytes)", element, raw.len());
+            /// }
+            /// ```
+            pub fn run_into<Q: AsRef<Query>>(&mut self, query: Q, buf: &mut Vec<u8>) -> SkyResult<Element> {
+                buf.clear();
+                let ret = self._run_into(query.as_ref(), buf);
+                self.track(ret)
+            }
+            fn _run_into(&mut self, query: &Query, buf: &mut Vec<u8>) -> SkyResult<Element> {
+                self.check_state()?;
+                self.bytes_written += query.write_sync(&mut self.stream)? as u64;
+                self.stream.flush()?;
+                loop {
+                    match self.try_response() {
+                        Ok((RawResponse::SimpleQuery(sq), forward_by)) => {
+                            buf.extend(self.buffer.drain(..forward_by));
+                            return Ok(sq);
+                        }
+                        Ok((RawResponse::PipelinedQuery(_), forward_by)) => {
+                            self.buffer.drain(..forward_by);
+                            return Err(SkyhashError::InvalidResponse.into());
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            /// Runs a query and returns the type symbol and raw payload of each top-level
+            /// element of the response: the elements of the array if the response holds an
+            /// array, or else the one element it holds. The payloads are neither validated as
+            /// UTF-8 nor parsed as numbers, which makes this the cheapest faithful representation
+            /// of a response for code that only forwards responses. Null elements of typed
+            /// arrays are returned as `(b'\0', [])`
+            pub fn run_query_flat<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<Vec<(u8, Vec<u8>)>> {
+                let ret = self._run_query_flat(query.as_ref());
+                self.track(ret)
+            }
+            fn _run_query_flat(&mut self, query: &Query) -> SkyResult<Vec<(u8, Vec<u8>)>> {
+                self.check_state()?;
+                self.bytes_written += query.write_sync(&mut self.stream)? as u64;
+                self.stream.flush()?;
+                loop {
+                    match Parser::parse_flat(&self.buffer) {
+                        Ok((elements, forward_by)) => {
+                            self.buffer.drain(..forward_by);
+                            return Ok(elements);
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            /// Runs a query and drives the provided [`ResponseVisitor`] with the response, so
+            /// that the response can be processed without allocating [`Element`]s for it. This
+            /// is the cheapest way to consume large responses
+            ///
+            /// The visitor is only called once the whole response has been received.
+            /// [`SkyhashError::InvalidResponse`] is returned for pipelined responses
+            pub fn run_visit<Q: AsRef<Query>, V: ResponseVisitor>(
+                &mut self,
+                query: Q,
+                visitor: &mut V,
+            ) -> SkyResult<()> {
+                let ret = self._run_visit(query.as_ref(), visitor);
+                self.track(ret)
+            }
+            fn _run_visit<V: ResponseVisitor>(
+                &mut self,
+                query: &Query,
+                visitor: &mut V,
+            ) -> SkyResult<()> {
+                self.check_state()?;
+                self.bytes_written += query.write_sync(&mut self.stream)? as u64;
+                self.stream.flush()?;
+                loop {
+                    match Parser::visit(&self.buffer, visitor) {
+                        Ok(forward_by) => {
+                            self.buffer.drain(..forward_by);
+                            return Ok(());
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            /// Runs a query like [`Self::run_query_raw`], but retries it (as per the provided
+            /// [`RetryPolicy`]) as long as the server responds with one of the provided response
+            /// codes. This is useful for conditions that are known to be transient for a workload
+            /// (like a [`RespCode::ServerError`] while a snapshot is being taken)
+            ///
+            /// Once the retries are used up, the last response is returned. Errors (like I/O
+            /// errors) are returned right away and are never retried
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use skytable::{query, Connection, RespCode, RetryPolicy};
+            ///
+            /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+            /// let ret = con
+            ///     .run_retry_on(query!("set", "x", "100"), &[RespCode::ServerError], RetryPolicy::default())
+            ///     .unwrap();
+            /// ```
+            pub fn run_retry_on<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+                codes: &[RespCode],
+                policy: RetryPolicy,
+            ) -> SkyResult<Element> {
+                let query = query.as_ref();
+                let mut retry = 0;
+                loop {
+                    let ret = self.run_query_raw(query);
+                    if retry == policy.max_retries() || !RetryPolicy::should_retry(&ret, codes) {
+                        return ret;
+                    }
+                    std::thread::sleep(policy.delay(retry));
+                    retry += 1;
                 }
             }
             #[deprecated(
@@ -68,109 +261,3403 @@ macro_rules! impl_sync_methods {
             }
             /// Runs a pipelined query. See the [`Pipeline`](Pipeline) documentation for a guide on
             /// usage
-            pub fn run_pipeline(&mut self, pipeline: Pipeline) -> SkyResult<Vec<Element>> {
-                assert!(pipeline.len() != 0, "A `Pipeline` cannot be empty!");
-                match self._run_query(&pipeline)? {
+            ///
+            /// The queries are written in one frame and their responses are returned in the order
+            /// the queries were added to the pipeline
+            ///
+            /// An empty pipeline isn't sent to the server and returns no responses
+            pub fn run_pipeline<P: AsRef<Pipeline>>(&mut self, pipeline: P) -> SkyResult<Vec<Element>> {
+                let pipeline = pipeline.as_ref();
+                if pipeline.is_empty() {
+                    return Ok(Vec::new());
+                }
+                match self._run_query(pipeline)? {
                     RawResponse::PipelinedQuery(pq) => Ok(pq),
+                    // the server couldn't parse the pipeline
+                    RawResponse::SimpleQuery(Element::RespCode(RespCode::PacketError)) => {
+                        Err(SkyhashError::ProtocolError.into())
+                    }
                     RawResponse::SimpleQuery(_) => Err(SkyhashError::InvalidResponse.into()),
                 }
             }
-            fn _run_query<T: WriteQuerySync>(&mut self, query: &T) -> SkyResult<RawResponse> {
-                query.write_sync(&mut self.stream)?;
+            /// Runs a pipelined query like [`Self::run_pipeline`], but returns a
+            /// [`SkyhashError::PipelineFailed`] error holding the index and the response code of
+            /// the first query that returned a response code other than [`RespCode::Okay`]
+            ///
+            /// **This isn't atomic**: the queries after the failing query are still run and the
+            /// queries before it aren't rolled back. See the [`Pipeline`] documentation for more
+            /// information
+            pub fn run_pipeline_checked<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+            ) -> SkyResult<Vec<Element>> {
+                crate::check_pipeline_responses(self.run_pipeline(pipeline)?)
+            }
+            /// Runs a pipelined query like [`Self::run_pipeline`], but reads the responses one
+            /// by one so that the responses received before an error (for example, if the
+            /// connection is closed midway) aren't lost. These are returned in the
+            /// [`PipelineError`] along with the error, so that only the remaining queries have to
+            /// be run again
+            ///
+            /// **This isn't atomic**: the queries that a response was received for have been run
+            /// by the server, while the remaining queries may or may not have been run
+            ///
+            /// An empty pipeline isn't sent to the server and returns no responses
+            pub fn run_pipeline_partial<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+            ) -> Result<Vec<Element>, PipelineError> {
+                let pipeline = pipeline.as_ref();
+                if pipeline.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let mut received = Vec::new();
+                let ret = self._run_pipeline_partial(pipeline, &mut received);
+                match self.track(ret) {
+                    Ok(()) => Ok(received),
+                    Err(source) => Err(PipelineError { received, source }),
+                }
+            }
+            fn _run_pipeline_partial(
+                &mut self,
+                pipeline: &Pipeline,
+                received: &mut Vec<Element>,
+            ) -> SkyResult<()> {
+                let count = self._run_pipeline_head(pipeline)?;
+                received.reserve(count);
+                // draining the buffer after every response would move the rest of the buffer
+                // every time, so the parsed responses are only drained before reading more
+                let mut consumed = 0;
+                while received.len() != count {
+                    match Parser::parse_pipeline_element(&self.buffer[consumed..]) {
+                        Ok((element, forward_by)) => {
+                            consumed += forward_by;
+                            received.push(element);
+                            continue;
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.buffer.drain(..consumed);
+                    consumed = 0;
+                    self.fill_buffer()?;
+                }
+                self.buffer.drain(..consumed);
+                Ok(())
+            }
+            /// Send the pipeline and read the head of its response, returning the number of
+            /// responses that follow
+            fn _run_pipeline_head(&mut self, pipeline: &Pipeline) -> SkyResult<usize> {
+                self.check_state()?;
+                self.bytes_written += pipeline.write_sync(&mut self.stream)? as u64;
+                self.stream.flush()?;
+                loop {
+                    match Parser::parse_pipeline_head(&self.buffer) {
+                        Ok(Some((count, forward_by))) => {
+                            self.buffer.drain(..forward_by);
+                            return Ok(count);
+                        }
+                        Ok(None) => break,
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+                // not a pipelined response, but it still has to be consumed
+                match self._read_response()? {
+                    // the server couldn't parse the pipeline
+                    RawResponse::SimpleQuery(Element::RespCode(RespCode::PacketError)) => {
+                        Err(SkyhashError::ProtocolError.into())
+                    }
+                    _ => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Runs a pipeline like [`Self::run_pipeline`], but sends its queries one by one and
+            /// never has more than `window` of them waiting for a response: once `window`
+            /// queries were sent, a response is read before the next query is sent. Sending a
+            /// large pipeline in one go can fill up the socket buffers on both sides (the server
+            /// can't write its responses since they aren't read yet) and deadlock; this keeps
+            /// the number of unread responses bounded. The responses are returned in the order
+            /// of the queries
+            ///
+            /// An empty pipeline isn't sent to the server and returns no responses
+            ///
+            /// ## Panics
+            /// This method will panic if `window` is zero
+            pub fn run_pipeline_windowed<P: AsRef<Pipeline>>(
+                &mut self,
+                pipeline: P,
+                window: usize,
+            ) -> SkyResult<Vec<Element>> {
+                let pipeline = pipeline.as_ref();
+                assert!(window != 0, "The window must hold at least one query");
+                if pipeline.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let ret = self
+                    .check_state()
+                    .and_then(|_| self._run_pipeline_windowed(pipeline, window));
+                if ret.is_err() {
+                    // several responses (or queries) might still be pending
+                    self.recoverable = false;
+                }
+                self.track(ret)
+            }
+            fn _run_pipeline_windowed(
+                &mut self,
+                pipeline: &Pipeline,
+                window: usize,
+            ) -> SkyResult<Vec<Element>> {
+                pipeline.check_queries()?;
+                let mut responses = Vec::with_capacity(pipeline.len());
+                let frames = pipeline.query_frames();
+                for (sent, frame) in frames.iter().enumerate() {
+                    if sent - responses.len() == window {
+                        self.stream.flush()?;
+                        responses.push(self._read_windowed_response()?);
+                    }
+                    self.stream.write_all(frame)?;
+                    self.bytes_written += frame.len() as u64;
+                }
+                self.stream.flush()?;
+                while responses.len() != frames.len() {
+                    responses.push(self._read_windowed_response()?);
+                }
+                Ok(responses)
+            }
+            fn _read_windowed_response(&mut self) -> SkyResult<Element> {
+                match self._read_response()? {
+                    RawResponse::SimpleQuery(sq) => Ok(sq),
+                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Sends a `HEYA` and returns the time it took the server to respond, which is
+            /// useful to monitor the latency of a connection. An error is returned if the
+            /// server doesn't respond with `HEY!`
+            pub fn ping_latency(&mut self) -> SkyResult<Duration> {
+                let start = Instant::now();
+                let ret = self.run_query_raw(Query::from("heya"))?;
+                crate::check_heya(ret)?;
+                Ok(start.elapsed())
+            }
+            /// Returns the metrics reported by the server with `SYS METRIC`, like its health and
+            /// the size of the data it stores. A response code returned for any of the metrics
+            /// (for example, if the user isn't allowed to query them) is returned as
+            /// [`SkyhashError::Code`]
+            pub fn sys_metric(&mut self) -> SkyResult<SysMetric> {
+                SysMetric::from_responses(self.run_pipeline(SysMetric::pipeline())?)
+            }
+            /// Returns the health of the server, as reported by `SYS METRIC health`. Only
+            /// the health is queried, so this is cheaper than [`Self::sys_metric`] for frequent
+            /// health checks. A status unknown to this client is returned as [`Health::Other`]
+            pub fn health(&mut self) -> SkyResult<Health> {
+                Health::from_response(self.run_query_raw(Health::query())?)
+            }
+            /// Runs a query built from the provided arguments, without building a [`Query`]
+            /// first. This is useful for hot paths where the arguments are short-lived
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use skytable::Connection;
+            ///
+            /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+            /// let key = String::from("x");
+            /// let ret = con.run_args(&[&"get", &key]).unwrap();
+            /// ```
+            pub fn run_args(&mut self, args: &[&dyn IntoSkyhashBytes]) -> SkyResult<Element> {
+                match self._run_query(&ArgList(args))? {
+                    RawResponse::SimpleQuery(sq) => Ok(sq),
+                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Set the value of `key` to `len` bytes read from `reader`. The value is streamed to
+            /// the server in chunks instead of being buffered in memory, which is useful for very
+            /// large values (like files). Like [`set`](crate::actions::Actions::set), this returns
+            /// `true` if the key was set and `false` if it already exists
+            ///
+            /// If `reader` returns fewer than `len` bytes, the query can't be completed and an
+            /// [`UnexpectedEof`](ErrorKind::UnexpectedEof) I/O error is returned. The connection
+            /// can't be used after that
+            ///
+            /// Like the other [actions](crate::actions), the action is uppercased if
+            /// `set_uppercase_actions` is enabled. The query hook is passed the query without the
+            /// streamed value (that is, `SET <key>`)
+            pub fn set_streaming(
+                &mut self,
+                key: impl IntoSkyhashBytes,
+                reader: impl Read,
+                len: usize,
+            ) -> SkyResult<bool> {
+                let mut query = Query::from("set").arg(key);
+                if self.uppercase_actions {
+                    query.uppercase_action();
+                }
+                let ret = self._set_streaming(&query, reader, len);
+                let ret = self.track(ret);
+                if let Some(hook) = &self.query_hook {
+                    hook.call(&query, &ret);
+                }
+                gen_match!(
+                    ret,
+                    Element::RespCode(RespCode::Okay),
+                    true,
+                    Element::RespCode(RespCode::OverwriteError),
+                    false
+                )
+            }
+            fn _set_streaming(
+                &mut self,
+                query: &Query,
+                reader: impl Read,
+                len: usize,
+            ) -> SkyResult<Element> {
+                self.check_state()?;
+                let head = query.get_frame_with_streamed_arg(len);
+                self.stream.write_all(&head)?;
+                self.bytes_written += head.len() as u64;
+                let written = std::io::copy(&mut reader.take(len as u64), &mut self.stream)?;
+                self.bytes_written += written;
+                if written != len as u64 {
+                    return Err(IoError::from(ErrorKind::UnexpectedEof).into());
+                }
+                self.stream.flush()?;
+                match self._read_response()? {
+                    RawResponse::SimpleQuery(sq) => Ok(sq),
+                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Write a [`Query`] to the stream without waiting for the response. The response
+            /// can then be read with [`Self::read_response`] or [`Self::responses`]
+            pub fn send_query<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<()> {
+                let ret = self.check_state().and_then(|_| {
+                    self.bytes_written += query.as_ref().write_sync(&mut self.stream)? as u64;
+                    Ok(())
+                });
+                self.track(ret)
+            }
+            /// Read the response to a query that was previously sent with [`Self::send_query`].
+            /// Responses are returned in the order in which the queries were sent
+            pub fn read_response(&mut self) -> SkyResult<Element> {
+                let ret = self.check_state().and_then(|_| self._read_response());
+                match self.track(ret)? {
+                    RawResponse::SimpleQuery(sq) => Ok(sq),
+                    RawResponse::PipelinedQuery(_) => Err(SkyhashError::InvalidResponse.into()),
+                }
+            }
+            /// Read and discard the next response. This can be used to recover a connection
+            /// after a query was abandoned before its response was read (for example, because
+            /// it timed out), instead of reconnecting. Once this returns `Ok(())`, the
+            /// connection can be used (and pooled) again
+            ///
+            /// ## Caveats
+            /// The response is found using its framing information and hence this method will
+            /// block until a complete response frame is received. If no response is pending, this
+            /// will block until the next one arrives. If the frame is corrupt or has a data type
+            /// unknown to this client, its length can't be determined and an error is returned;
+            /// the connection should then be dropped as it can no longer be resynchronized
+            ///
+            /// A poisoned connection can only be recovered if it was poisoned by a read timeout,
+            /// once its query was written completely. If it was poisoned by anything else (like
+            /// a write timeout, an invalid or too large response, or an I/O error), an error is
+            /// returned without reading anything and the connection stays poisoned
+            pub fn discard_pending(&mut self) -> SkyResult<()> {
+                if self.state == ConnectionState::Poisoned && !self.recoverable {
+                    return Err(IoError::new(
+                        ErrorKind::NotConnected,
+                        "connection is poisoned by an error it can't be recovered from",
+                    )
+                    .into());
+                }
+                let ret = self._read_response().map(|_| ());
+                if ret.is_ok() && self.state == ConnectionState::Poisoned {
+                    // the stream is back in sync
+                    self.state = ConnectionState::Ready;
+                    self.recoverable = false;
+                }
+                self.track(ret)
+            }
+            /// Peek at the header of the next response, without consuming it. This returns the
+            /// number of top-level elements declared by the response and can be used to decide
+            /// whether a large response should be buffered or not. The response can then be read
+            /// with [`Self::read_response`]
+            pub fn peek_response_header(&mut self) -> SkyResult<ResponseHeader> {
+                let ret = self
+                    .check_state()
+                    .and_then(|_| self._peek_response_header());
+                self.track(ret)
+            }
+            /// Peek at the type of the element held by the next response, without consuming
+            /// it (or `None` if the next response is pipelined). Only the first bytes of the
+            /// response are needed, so this can be used to decide how to handle a response that
+            /// can have different types. The response can then be read with
+            /// [`Self::read_response`]
+            pub fn peek_element_type(&mut self) -> SkyResult<Option<ElementType>> {
+                let ret = self
+                    .check_state()
+                    .and_then(|_| self._peek_element_type());
+                self.track(ret)
+            }
+            /// Returns an iterator that reads responses to queries previously sent with
+            /// [`Self::send_query`]. Every call to `next` blocks until a response is available
+            /// and the iterator ends after an I/O error, a timeout or once the server closed the
+            /// connection
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use skytable::{query, Connection};
+            ///
+            /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+            /// for _ in 0..10 {
+            ///     con.send_query(query!("heya")).unwrap();
+            /// }
+            /// for response in con.responses().take(10) {
+            ///     println!("{:?}", response.unwrap());
+            /// }
+            /// ```
+            pub fn responses(&mut self) -> Responses<'_, Self> {
+                Responses {
+                    con: self,
+                    done: false,
+                }
+            }
+            /// Run a [`Query`] and iterate over the elements of the array it returns as they are
+            /// read from the stream, instead of buffering the entire response. An element that
+            /// fails to parse (for example, a string that isn't valid UTF-8) is returned as an
+            /// `Err` without affecting the remaining elements. If the response isn't an array, it
+            /// is returned as the only item. Null elements of typed arrays are returned as
+            /// [`RespCode::NotFound`]
+            ///
+            /// If an error that makes it impossible to find the remaining elements occurs (like
+            /// an I/O error or an unknown data type), it is returned as the last item. If the
+            /// iterator is dropped before all the elements were read, the connection is poisoned
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use skytable::{query, Connection};
+            ///
+            /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+            /// for element in con.run_query_iter(query!("lskeys", "1000")).unwrap() {
+            ///     match element {
+            ///         Ok(element) => println!("{:?}", element),
+            ///         Err(e) => eprintln!("skipping bad element: {}", e),
+            ///     }
+            /// }
+            /// ```
+            pub fn run_query_iter<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+            ) -> SkyResult<ResponseElements<'_, Self>> {
+                let ret = self._run_query_iter(query.as_ref());
+                let array = self.track(ret)?;
+                let scalar = match array {
+                    Some(_) => None,
+                    None => {
+                        let ret = self._read_response();
+                        match self.track(ret)? {
+                            RawResponse::SimpleQuery(sq) => Some(sq),
+                            RawResponse::PipelinedQuery(_) => {
+                                return Err(SkyhashError::InvalidResponse.into())
+                            }
+                        }
+                    }
+                };
+                let (kind, remaining) = array.unwrap_or((ArrayKind::Flat, 0));
+                if remaining != 0 {
+                    // the stream is out of sync until all the elements are read
+                    self.state = ConnectionState::Poisoned;
+                    self.recoverable = false;
+                }
+                Ok(ResponseElements {
+                    con: self,
+                    kind,
+                    remaining,
+                    scalar,
+                })
+            }
+            fn _run_query_iter(&mut self, query: &Query) -> SkyResult<Option<(ArrayKind, usize)>> {
+                self.check_state()?;
+                self.bytes_written += query.write_sync(&mut self.stream)? as u64;
                 self.stream.flush()?;
                 loop {
-                    let mut buffer = [0u8; 1024];
-                    match self.stream.read(&mut buffer) {
-                        Ok(0) => return Err(IoError::from(ErrorKind::ConnectionReset).into()),
-                        Ok(read) => {
-                            self.buffer.extend(&buffer[..read]);
+                    match Parser::parse_array_head(&self.buffer) {
+                        Ok(Some((kind, count, head_len))) => {
+                            self.buffer.drain(..head_len);
+                            return Ok(Some((kind, count)));
+                        }
+                        Ok(None) => return Ok(None),
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            /// Runs a query whose response is an array like [`Self::run_query_iter`], but only
+            /// parses the elements at the provided positions and returns them in the order of
+            /// `indices`. The other elements are skipped using their length prefixes, without
+            /// being decoded or allocated, which is useful to read a few columns of a wide row
+            ///
+            /// Null elements of typed arrays are returned as [`RespCode::NotFound`]. If the
+            /// response isn't an array, the response code it holds is returned as
+            /// [`SkyhashError::Code`] (or [`SkyhashError::UnexpectedDataType`] for any other
+            /// element), and an index past the end of the array returns an
+            /// [`Error::ParseError`]. Repeating an index returns an [`Error::ConfigurationError`]
+            /// without running the query
+            ///
+            /// ## Example
+            /// ```no_run
+            /// use skytable::{query, Connection};
+            ///
+            /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+            /// let columns = con.run_query_project(query!("mget", "a", "b", "c", "d"), &[0, 3]).unwrap();
+            /// assert_eq!(columns.len(), 2);
+            /// ```
+            pub fn run_query_project<Q: AsRef<Query>>(
+                &mut self,
+                query: Q,
+                indices: &[usize],
+            ) -> SkyResult<Vec<Element>> {
+                let mut wanted = indices.to_vec();
+                wanted.sort_unstable();
+                wanted.dedup();
+                if wanted.len() != indices.len() {
+                    return Err(Error::ConfigurationError("indices cannot be repeated"));
+                }
+                let ret = self._run_query_project(query.as_ref(), &wanted);
+                let (mut projected, count) = self.track(ret)?;
+                indices
+                    .iter()
+                    .map(|index| match wanted.binary_search(index) {
+                        Ok(at) if at < projected.len() => Ok(projected[at].take().unwrap()),
+                        _ => Err(Error::ParseError(format!(
+                            "index {} is out of bounds for an array of {} elements",
+                            index, count
+                        ))),
+                    })
+                    .collect()
+            }
+            /// Read the elements at the `wanted` (sorted) positions of an array response,
+            /// along with the number of elements in the array
+            fn _run_query_project(
+                &mut self,
+                query: &Query,
+                wanted: &[usize],
+            ) -> SkyResult<(Vec<Option<Element>>, usize)> {
+                let (kind, count) = match self._run_query_iter(query)? {
+                    Some(array) => array,
+                    None => {
+                        return match self._read_response()? {
+                            RawResponse::SimpleQuery(Element::RespCode(rc)) => {
+                                Err(SkyhashError::Code(rc).into())
+                            }
+                            RawResponse::SimpleQuery(_) => {
+                                Err(SkyhashError::UnexpectedDataType.into())
+                            }
+                            RawResponse::PipelinedQuery(_) => {
+                                Err(SkyhashError::InvalidResponse.into())
+                            }
+                        }
+                    }
+                };
+                let mut projected = Vec::with_capacity(wanted.len());
+                let mut element_error = None;
+                for position in 0..count {
+                    if wanted.binary_search(&position).is_err() {
+                        self._skip_array_element(kind)?;
+                        continue;
+                    }
+                    match self._read_array_element(kind)? {
+                        Ok(element) => projected.push(Some(element)),
+                        // the remaining elements are still read to keep the stream in sync
+                        Err(e) => element_error = element_error.or(Some(e)),
+                    }
+                }
+                match element_error {
+                    Some(e) => Err(e),
+                    None => Ok((projected, count)),
+                }
+            }
+            /// Skip the next element of an array response, without parsing it
+            fn _skip_array_element(&mut self, kind: ArrayKind) -> SkyResult<()> {
+                loop {
+                    match Parser::skip_array_element(&self.buffer, kind) {
+                        Ok(len) => {
+                            self.buffer.drain(..len);
+                            return Ok(());
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            /// Read the next element of an array response. The outer error is returned if the
+            /// element couldn't be found in the stream
+            fn _read_array_element(&mut self, kind: ArrayKind) -> SkyResult<SkyResult<Element>> {
+                loop {
+                    match Parser::parse_array_element(&self.buffer, kind) {
+                        Ok((element, len)) => {
+                            self.buffer.drain(..len);
+                            return Ok(element.map_err(|_| SkyhashError::ParseError.into()));
+                        }
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            fn _run_query<T: WriteQuerySync>(&mut self, query: &T) -> SkyResult<RawResponse> {
+                let ret = self.__run_query(query);
+                self.track(ret)
+            }
+            fn __run_query<T: WriteQuerySync>(&mut self, query: &T) -> SkyResult<RawResponse> {
+                self.check_state()?;
+                self.bytes_written += query.write_sync(&mut self.stream)? as u64;
+                self.stream.flush()?;
+                self._read_response()
+            }
+            cfg_dbg! {
+                /// Write the raw bytes of a frame to the stream, bypassing [`Query`] serialization
+                /// entirely. This is **not intended for daily use** but is useful for testing the edge
+                /// cases of the Skyhash protocol. The response can be read with [`Self::read_frame`]
+                pub fn write_frame(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+                    self.stream.write_all(bytes)?;
+                    self.bytes_written += bytes.len() as u64;
+                    self.stream.flush()
+                }
+                /// Read the raw bytes of the next response frame from the stream. The parser is
+                /// only used to find where the frame ends; if the response is invalid (and hence
+                /// can't be delimited), all the bytes read so far are returned as-is
+                pub fn read_frame(&mut self) -> std::io::Result<Vec<u8>> {
+                    loop {
+                        match Parser::parse(&self.buffer) {
+                            Ok((_, frame_len)) => return Ok(self.buffer.drain(..frame_len).collect()),
+                            Err(ParseError::NotEnough) => {}
+                            Err(_) => return Ok(self.buffer.drain(..).collect()),
+                        }
+                        if !self.read_more()? {
+                            return Err(IoError::from(ErrorKind::ConnectionReset));
+                        }
+                    }
+                }
+                /// Run a [`Query`] and read its response without parsing it into [`Element`]s,
+                /// returning the length of the response in bytes. The response is only scanned to
+                /// find where it ends. This is **not intended for daily use** but is useful to
+                /// benchmark the network separately from the parser
+                pub fn run_discard(&mut self, query: &Query) -> SkyResult<usize> {
+                    let ret = self.check_state().and_then(|_| self._run_discard(query));
+                    self.track(ret)
+                }
+                fn _run_discard(&mut self, query: &Query) -> SkyResult<usize> {
+                    self.bytes_written += query.write_sync(&mut self.stream)? as u64;
+                    self.stream.flush()?;
+                    loop {
+                        match Parser::skip(&self.buffer) {
+                            Ok(frame_len) => {
+                                self.buffer.drain(..frame_len);
+                                return Ok(frame_len);
+                            }
+                            Err(e) => self.check_parse_error(e)?,
+                        }
+                        self.fill_buffer()?;
+                    }
+                }
+            }
+            /// Returns the number of bytes written to this connection (since it was created or
+            /// since the counters were last reset with [`Self::reset_counters`]). For TLS
+            /// connections, this doesn't include the overhead of TLS itself
+            pub fn bytes_written(&self) -> u64 {
+                self.bytes_written
+            }
+            /// Returns the number of bytes read from this connection (since it was created or
+            /// since the counters were last reset with [`Self::reset_counters`]). For TLS
+            /// connections, this doesn't include the overhead of TLS itself
+            pub fn bytes_read(&self) -> u64 {
+                self.bytes_read
+            }
+            /// Reset the counters returned by [`Self::bytes_written`] and [`Self::bytes_read`]
+            pub fn reset_counters(&mut self) {
+                self.bytes_written = 0;
+                self.bytes_read = 0;
+            }
+            /// Returns the name of this connection, if one was set
+            pub fn name(&self) -> Option<&str> {
+                self.name.as_deref()
+            }
+            pub(crate) fn set_name(&mut self, name: Option<Arc<str>>) {
+                self.name = name;
+            }
+            /// Returns a [`Prefixed`] wrapper around this connection whose
+            /// [`get`](Prefixed::get), [`set`](Prefixed::set) and [`del`](Prefixed::del)
+            /// prepend `prefix` to keys (but not to values). This is useful to keep the keys of
+            /// different tenants (or features) apart in the same table
+            pub fn with_prefix(&mut self, prefix: &str) -> Prefixed<'_, Self> {
+                Prefixed::new(self, prefix)
+            }
+            pub(crate) fn set_query_hook(&mut self, query_hook: Option<QueryHook>) {
+                self.query_hook = query_hook;
+            }
+            pub(crate) fn set_uppercase_actions(&mut self, uppercase_actions: bool) {
+                self.uppercase_actions = uppercase_actions;
+            }
+            /// See [`ConnectionBuilder::set_compression`](crate::ConnectionBuilder::set_compression)
+            pub(crate) fn set_compression(&mut self, compression: Option<Compression>) {
+                self.compression = compression;
+            }
+            /// See [`ConnectionBuilder::set_validate_utf8`](crate::ConnectionBuilder::set_validate_utf8)
+            pub(crate) fn set_validate_utf8(&mut self, validate_utf8: bool) {
+                self.validate_utf8 = validate_utf8;
+            }
+            /// Limit the size of the responses read by this connection to `max` bytes (by
+            /// default, responses can be of any size). Once more than `max` bytes of a response
+            /// were read, the query fails with [`SkyhashError::ResponseTooLarge`] and the
+            /// connection is poisoned, so that a corrupted (or malicious) length can't make this
+            /// connection buffer data endlessly. Responses to queued queries that were read
+            /// together count as one response
+            ///
+            /// To cap the memory used by many connections together, use a [`MemoryBudget`]
+            pub fn set_max_response_size(&mut self, max: Option<usize>) {
+                self.max_response_size = max;
+            }
+            pub(crate) fn set_memory_budget(&mut self, budget: Option<MemoryBudget>) {
+                self.reservation = budget.map(Reservation::new);
+            }
+            /// Charge the buffer to the memory budget (if any) and check it against the maximum
+            /// response size (if any). If either is exceeded, the buffered part of the response
+            /// is dropped and the connection is poisoned
+            fn charge_buffer(&mut self) -> SkyResult<()> {
+                let mut ret = match self.max_response_size {
+                    Some(max) if self.buffer.len() > max => {
+                        Err(SkyhashError::ResponseTooLarge.into())
+                    }
+                    _ => Ok(()),
+                };
+                if let (Ok(()), Some(reservation)) = (&ret, &mut self.reservation) {
+                    ret = reservation.resize(self.buffer.len());
+                }
+                if ret.is_err() {
+                    self.buffer.clear();
+                    if self.state == ConnectionState::Ready {
+                        self.state = ConnectionState::Poisoned;
+                    }
+                    // the rest of the response can't be told apart from the next one
+                    self.recoverable = false;
+                    self.release_buffer();
+                }
+                ret
+            }
+            /// Give back the part of the memory budget that the buffer doesn't use anymore
+            fn release_buffer(&mut self) {
+                if let Some(reservation) = &mut self.reservation {
+                    // the buffer only shrinks between two reads, so this can't fail
+                    let _ = reservation.resize(self.buffer.len());
+                }
+            }
+            /// Ask the server for the version of the Skyhash protocol it speaks (with
+            /// `SYS INFO PROTOVER`) and return it, if it's compatible with the version spoken by
+            /// this client ([`PROTOCOL_VERSION`](crate::PROTOCOL_VERSION)). Otherwise,
+            /// [`SkyhashError::UnsupportedProtocol`] is returned and no other queries should be
+            /// run on this connection
+            pub fn check_protocol(&mut self) -> SkyResult<f32> {
+                let query = Query::from("sys").arg("info").arg("protover");
+                let version = crate::check_protover(self.run_query_raw(query)?)?;
+                self.protocol_version = Some(version);
+                Ok(version)
+            }
+            /// Returns the version of the Skyhash protocol spoken by the server, if it was
+            /// checked with [`Self::check_protocol`]
+            pub fn protocol_version(&self) -> Option<f32> {
+                self.protocol_version
+            }
+            /// Returns the version of the server (with `SYS INFO VERSION`). The version is only
+            /// queried the first time, and is then remembered by this connection
+            pub fn server_version(&mut self) -> SkyResult<Version> {
+                if let Some(version) = &self.server_version {
+                    return Ok(version.clone());
+                }
+                let version = Version::from_response(self.run_query_raw(Version::query())?)?;
+                self.server_version = Some(version.clone());
+                Ok(version)
+            }
+            /// Switch to the provided entity (with `USE`), unless this connection is already
+            /// known to use it, in which case no query is sent. The Skyhash protocol can't target
+            /// an entity per query, so this saves the round trip of redundant `USE`s when
+            /// switching between a few entities (for example, one per tenant)
+            ///
+            /// The current entity is only known after switching with this method (or after
+            /// connecting with a [`ConnectionBuilder`](crate::ConnectionBuilder)). Running a `USE`
+            /// any other way (except in a [`Pipeline`]) forgets the current entity, so the next
+            /// call always switches
+            pub fn use_entity(&mut self, entity: &str) -> SkyResult<()> {
+                if self.entity.as_deref() != Some(entity) {
+                    crate::ddl::Ddl::switch(self, entity)?;
+                    self.entity = Some(entity.to_owned());
+                }
+                Ok(())
+            }
+            /// Returns the entity this connection is known to use. See [`Self::use_entity`]
+            pub fn entity(&self) -> Option<&str> {
+                self.entity.as_deref()
+            }
+            /// Record the errors that affect the reusability of this connection
+            fn track<T>(&mut self, ret: SkyResult<T>) -> SkyResult<T> {
+                self.release_buffer();
+                let ret = ret.map_err(Error::map_timeout);
+                match &ret {
+                    Ok(_) | Err(Error::ParseError(_)) | Err(Error::ConfigurationError(_)) => {}
+                    Err(Error::SkyError(e)) if !matches!(e, SkyhashError::Timeout) => {
+                        self.protocol_error = true
+                    }
+                    // I/O (or TLS) errors and timeouts leave the stream in an unknown state. Only
+                    // read timeouts (see `read_more`) can be recovered from
+                    Err(e) => {
+                        if self.state == ConnectionState::Ready {
+                            self.state = ConnectionState::Poisoned;
+                            if !matches!(e, Error::SkyError(SkyhashError::Timeout)) {
+                                self.recoverable = false;
+                            }
                         }
-                        Err(e) => return Err(e.into()),
                     }
+                }
+                match (ret, &self.name) {
+                    (Err(Error::IoError(e)), Some(name)) => Err(IoError::new(
+                        e.kind(),
+                        format!("connection '{}': {}", name, e),
+                    )
+                    .into()),
+                    (ret, _) => ret,
+                }
+            }
+            /// Returns true if this connection should no longer be used as per the provided
+            /// error policy
+            pub(crate) fn is_broken(&self, policy: ErrorPolicy) -> bool {
+                match policy {
+                    ErrorPolicy::KeepOnProtocolError => self.state != ConnectionState::Ready,
+                    ErrorPolicy::DropOnAnyError => {
+                        self.state != ConnectionState::Ready || self.protocol_error
+                    }
+                }
+            }
+            /// Returns the state of this connection. Queries can only be run on a connection
+            /// that is [`ConnectionState::Ready`]
+            pub fn state(&self) -> ConnectionState {
+                self.state
+            }
+            /// Returns an error if this connection can't be used to run queries
+            fn check_state(&self) -> SkyResult<()> {
+                match self.state {
+                    ConnectionState::Ready => Ok(()),
+                    ConnectionState::Poisoned => Err(IoError::new(
+                        ErrorKind::NotConnected,
+                        "connection is poisoned by an earlier error",
+                    )
+                    .into()),
+                    ConnectionState::Closed => Err(IoError::new(
+                        ErrorKind::NotConnected,
+                        "connection was closed by the server",
+                    )
+                    .into()),
+                }
+            }
+            fn _read_response(&mut self) -> SkyResult<RawResponse> {
+                loop {
+                    // the buffer might already hold a complete response
                     match self.try_response() {
                         Ok((query, forward_by)) => {
                             self.buffer.drain(..forward_by);
                             return Ok(query);
                         }
-                        Err(e) => match e {
-                            ParseError::NotEnough => (),
-                            ParseError::BadPacket => {
-                                self.buffer.clear();
-                                return Err(SkyhashError::InvalidResponse.into());
-                            }
-                            ParseError::DataTypeError => {
-                                return Err(SkyhashError::ParseError.into())
-                            }
-                            ParseError::UnknownDatatype => {
-                                return Err(SkyhashError::UnknownDataType.into())
-                            }
-                        },
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            fn _peek_response_header(&mut self) -> SkyResult<ResponseHeader> {
+                loop {
+                    match Parser::parse_header(&self.buffer) {
+                        Ok(header) => return Ok(header),
+                        Err(e) => self.check_parse_error(e)?,
                     }
+                    self.fill_buffer()?;
+                }
+            }
+            fn _peek_element_type(&mut self) -> SkyResult<Option<ElementType>> {
+                loop {
+                    match Parser::parse_element_type(&self.buffer) {
+                        Ok(element_type) => return Ok(element_type),
+                        Err(e) => self.check_parse_error(e)?,
+                    }
+                    self.fill_buffer()?;
+                }
+            }
+            /// Drop the response being read when its end can't be found, along with anything
+            /// else in the buffer. The rest of it may still arrive, so the connection is poisoned
+            /// to keep later queries from reading it as their response
+            fn drop_frame(&mut self) {
+                self.buffer.clear();
+                if self.state == ConnectionState::Ready {
+                    self.state = ConnectionState::Poisoned;
                 }
+                self.recoverable = false;
             }
+            /// Returns an error if the parse error can't be fixed by reading more data
+            fn check_parse_error(&mut self, e: ParseError) -> SkyResult<()> {
+                match e {
+                    ParseError::NotEnough => Ok(()),
+                    ParseError::BadPacket => {
+                        self.drop_frame();
+                        Err(SkyhashError::InvalidResponse.into())
+                    }
+                    ParseError::DataTypeError => {
+                        self.drop_frame();
+                        Err(SkyhashError::ParseError.into())
+                    }
+                    ParseError::UnknownDatatype(tsymbol) => {
+                        // the length of an unknown element can't be known
+                        self.drop_frame();
+                        Err(SkyhashError::UnknownDataType(tsymbol).into())
+                    }
+                    ParseError::BadDelimiter(byte) => {
+                        // the framing is lost, so whatever is buffered can't be parsed
+                        self.drop_frame();
+                        Err(SkyhashError::BadDelimiter(byte).into())
+                    }
+                }
+            }
+            /// Read more data from the stream into the buffer. If the server closed the
+            /// connection, it is marked as closed and [`SkyhashError::ConnectionClosed`] is
+            /// returned
+            fn fill_buffer(&mut self) -> SkyResult<()> {
+                if self.read_more()? {
+                    self.charge_buffer()
+                } else {
+                    Err(SkyhashError::ConnectionClosed(self.name.clone()).into())
+                }
+            }
+            /// Read more data from the stream into the buffer, returning `false` (and marking
+            /// the connection as closed) if the server closed the connection
+            fn read_more(&mut self) -> std::io::Result<bool> {
+                let mut buffer = [0u8; 1024];
+                let read = match self.stream.read(&mut buffer) {
+                    Ok(read) => read,
+                    Err(e) => {
+                        if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                            && self.state == ConnectionState::Ready
+                        {
+                            // the query was written completely, so only its response is pending
+                            // and the connection can be recovered with `discard_pending`
+                            self.recoverable = true;
+                        }
+                        return Err(e);
+                    }
+                };
+                if read == 0 {
+                    self.state = ConnectionState::Closed;
+                    return Ok(false);
+                }
+                self.bytes_read += read as u64;
+                self.buffer.extend(&buffer[..read]);
+                Ok(true)
+            }
+            /// This function is a subroutine of `run_query` used to parse the response packet
             fn try_response(&mut self) -> Result<(RawResponse, usize), ParseError> {
-                Parser::parse(&self.buffer)
+                if self.validate_utf8 {
+                    Parser::parse(&self.buffer)
+                } else {
+                    Parser::parse_lossy_utf8(&self.buffer)
+                }
+            }
+        }
+        impl<$($generics)*> Iterator for Responses<'_, $ty> {
+            type Item = SkyResult<Element>;
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.done {
+                    return None;
+                }
+                let ret = self.con.read_response();
+                if let Err(
+                    Error::IoError(_)
+                    | Error::SkyError(SkyhashError::ConnectionClosed(_) | SkyhashError::Timeout),
+                ) = ret
+                {
+                    // the connection is unusable now
+                    self.done = true;
+                }
+                Some(ret)
+            }
+        }
+        impl<$($generics)*> Iterator for ResponseElements<'_, $ty> {
+            type Item = SkyResult<Element>;
+            fn next(&mut self) -> Option<Self::Item> {
+                if let Some(scalar) = self.scalar.take() {
+                    return Some(Ok(scalar));
+                }
+                if self.remaining == 0 {
+                    return None;
+                }
+                self.remaining -= 1;
+                match self.con._read_array_element(self.kind) {
+                    Ok(element) => {
+                        if self.remaining == 0 {
+                            // the stream is back in sync
+                            self.con.state = ConnectionState::Ready;
+                        }
+                        Some(element)
+                    }
+                    Err(e) => {
+                        // the remaining elements can't be found
+                        self.remaining = 0;
+                        Some(self.con.track(Err(e)))
+                    }
+                }
             }
         }
-        impl crate::actions::SyncSocket for $ty {
-            fn run(&mut self, q: Query) -> SkyQueryResult {
-                self.run_query_raw(&q)
+        impl<$($generics)*> crate::actions::SyncSocket for $ty {
+            fn run(&mut self, mut q: Query) -> SkyQueryResult {
+                if self.uppercase_actions {
+                    q.uppercase_action();
+                }
+                match self.compression {
+                    Some(compression) => self
+                        .run_query_raw(&compression.compress_query(q))
+                        .map(|element| compression.decompress_element(element)),
+                    None => self.run_query_raw(&q),
+                }
+            }
+        }
+        impl<$($generics)*> Prefixed<'_, $ty> {
+            /// Get the value of the prefixed key. See [`Actions::get`]
+            pub fn get<T: FromSkyhashBytes>(
+                &mut self,
+                key: impl IntoSkyhashBytes,
+            ) -> SkyResult<T> {
+                let key = self.key(key);
+                self.con.get(key)
+            }
+            /// Set the value of the prefixed key. See [`Actions::set`]
+            pub fn set(
+                &mut self,
+                key: impl IntoSkyhashBytes,
+                value: impl IntoSkyhashBytes,
+            ) -> SkyResult<bool> {
+                let key = self.key(key);
+                self.con.set(key, value)
             }
+            /// Delete the prefixed key, returning the number of keys that were deleted. See
+            /// [`Actions::del`]
+            pub fn del(&mut self, key: impl IntoSkyhashBytes) -> SkyResult<u64> {
+                let key = self.key(key);
+                self.con.del(key)
+            }
+        }
+    };
+}
+
+/// An iterator over the responses read from a connection. See [`Connection::responses`]
+pub struct Responses<'a, C> {
+    con: &'a mut C,
+    done: bool,
+}
+
+/// An iterator over the elements of a response read from a connection. See
+/// [`Connection::run_query_iter`]
+pub struct ResponseElements<'a, C> {
+    con: &'a mut C,
+    kind: ArrayKind,
+    remaining: usize,
+    scalar: Option<Element>,
+}
+
+/// Run `query` on each of the `connections` (one after the other) and return the results in the
+/// same order as the connections. An error on one connection doesn't stop the query from being
+/// run on the others
+///
+/// ## Example
+/// ```no_run
+/// use skytable::{query, sync, Connection};
+///
+/// let mut shards = vec![
+///     Connection::new("10.0.0.1", 2003).unwrap(),
+///     Connection::new("10.0.0.2", 2003).unwrap(),
+/// ];
+/// for result in sync::fan_out(&mut shards, &query!("flushdb")) {
+///     result.unwrap();
+/// }
+/// ```
+pub fn fan_out<C: SyncSocket>(connections: &mut [C], query: &Query) -> Vec<SkyQueryResult> {
+    connections
+        .iter_mut()
+        .map(|con| con.run(query.clone()))
+        .collect()
+}
+
+/// A connection that doesn't connect to a server: it records the frames of the queries run on it
+/// (exactly as they would be written to the stream) and responds with canned responses. This
+/// can be used to test the queries built by an application (including with the
+/// [actions](crate::actions::Actions)) without a server
+///
+/// Responses queued with [`DryRunConnection::push_response`] are returned in order, one per
+/// query; once there are none left, [`RespCode::Okay`] is returned
+///
+/// ## Example
+/// ```
+/// use skytable::actions::Actions;
+/// use skytable::sync::DryRunConnection;
+/// use skytable::Element;
+///
+/// let mut con = DryRunConnection::new();
+/// con.push_response(Element::String("100".to_owned()));
+/// let x: String = con.get("x").unwrap();
+/// assert_eq!(x, "100");
+/// assert_eq!(con.sent(), [b"*2\n3\nget1\nx".to_vec()]);
+/// ```
+#[derive(Debug, Default)]
+pub struct DryRunConnection {
+    sent: Vec<Vec<u8>>,
+    responses: VecDeque<Element>,
+}
+
+impl DryRunConnection {
+    /// Create a dry run connection that has sent nothing and has no queued responses
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Queue the response to a query
+    pub fn push_response(&mut self, response: Element) {
+        self.responses.push_back(response);
+    }
+    /// Returns the frames sent so far, in the order they were sent
+    pub fn sent(&self) -> &[Vec<u8>] {
+        &self.sent
+    }
+    /// Returns the frames sent so far and forgets them
+    pub fn take_sent(&mut self) -> Vec<Vec<u8>> {
+        mem::take(&mut self.sent)
+    }
+    fn next_response(&mut self) -> Element {
+        self.responses
+            .pop_front()
+            .unwrap_or(Element::RespCode(RespCode::Okay))
+    }
+    /// Records the frame of a query and returns the next response, like
+    /// [`Connection::run_query_raw`]. An empty query isn't recorded and returns an error
+    pub fn run_query_raw<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<Element> {
+        let query = query.as_ref();
+        if query.is_empty() {
+            return Err(crate::empty_query());
+        }
+        self.sent.push(query.get_frame());
+        Ok(self.next_response())
+    }
+    /// Runs a query using [`Self::run_query_raw`] and attempts to return a type provided by
+    /// the user
+    pub fn run_query<T: FromSkyhashBytes, Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<T> {
+        self.run_query_raw(query)?.try_element_into()
+    }
+    /// Records the frame of a pipeline and returns the next response for each of its queries,
+    /// like [`Connection::run_pipeline`]. An empty pipeline isn't recorded
+    pub fn run_pipeline<P: AsRef<Pipeline>>(&mut self, pipeline: P) -> SkyResult<Vec<Element>> {
+        let pipeline = pipeline.as_ref();
+        if pipeline.is_empty() {
+            return Ok(Vec::new());
         }
-    };
+        pipeline.check_queries()?;
+        self.sent.push(pipeline.get_frame());
+        Ok((0..pipeline.len()).map(|_| self.next_response()).collect())
+    }
+}
+
+impl SyncSocket for DryRunConnection {
+    fn run(&mut self, q: Query) -> SkyQueryResult {
+        self.run_query_raw(&q)
+    }
 }
 
 cfg_sync!(
     /// 4 KB Read Buffer
     const BUF_CAP: usize = 4096;
 
+    /// Resolve `host:port` through `dns_cache` (if provided), keeping only the addresses in
+    /// `address_family`
+    fn resolve(
+        host: &str,
+        port: u16,
+        dns_cache: Option<&DnsCache>,
+        address_family: AddressFamily,
+    ) -> std::io::Result<Vec<SocketAddr>> {
+        let addrs: Vec<SocketAddr> = match dns_cache {
+            Some(dns_cache) => dns_cache.resolve(host, port)?,
+            None => (host, port).to_socket_addrs()?.collect(),
+        };
+        address_family.filter(addrs)
+    }
+
+    /// Connect to `host:port`, binding the socket to `local_addr` first (if provided). The host
+    /// is resolved through `dns_cache` (if provided). Connecting to all the addresses the host
+    /// resolves to can take up to `connect_timeout` (if provided)
+    fn connect_tcp(
+        host: &str,
+        port: u16,
+        local_addr: Option<SocketAddr>,
+        dns_cache: Option<&DnsCache>,
+        address_family: AddressFamily,
+        connect_timeout: Option<Duration>,
+    ) -> std::io::Result<TcpStream> {
+        let addrs = resolve(host, port, dns_cache, address_family)?;
+        if local_addr.is_none() && connect_timeout.is_none() {
+            return TcpStream::connect(&addrs[..]);
+        }
+        let deadline = connect_timeout.map(|timeout| Instant::now() + timeout);
+        let mut last_err = None;
+        for addr in addrs {
+            if matches!(local_addr, Some(local_addr) if addr.is_ipv4() != local_addr.is_ipv4()) {
+                // can't bind to an address from a different family
+                continue;
+            }
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+            if let Some(local_addr) = local_addr {
+                if let Err(e) = socket.bind(&local_addr.into()) {
+                    last_err = Some(e);
+                    continue;
+                }
+            }
+            let ret = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if remaining != Duration::ZERO => {
+                        socket.connect_timeout(&addr.into(), remaining)
+                    }
+                    _ => return Err(IoError::from(ErrorKind::TimedOut)),
+                },
+                None => socket.connect(&addr.into()),
+            };
+            match ret {
+                Ok(()) => return Ok(socket.into()),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            IoError::new(
+                ErrorKind::InvalidInput,
+                "no address for host in the same family as the local address",
+            )
+        }))
+    }
+
+    /// Connect to `host:port`, through `proxy` (if provided). Connecting to the host fails
+    /// with [`SkyhashError::Timeout`] if it takes longer than `connect_timeout`, which doesn't
+    /// apply to connecting to the proxy
+    fn connect(
+        host: &str,
+        port: u16,
+        local_addr: Option<SocketAddr>,
+        proxy: Option<&Socks5Proxy>,
+        dns_cache: Option<&DnsCache>,
+        address_family: AddressFamily,
+        connect_timeout: Option<Duration>,
+    ) -> SkyResult<TcpStream> {
+        let ret = match proxy {
+            #[cfg(feature = "socks")]
+            Some(_) if local_addr.is_some() => Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "can't bind to a local address when connecting through a proxy",
+            )),
+            #[cfg(feature = "socks")]
+            Some(proxy) => {
+                let proxy_addrs = resolve(&proxy.host, proxy.port, dns_cache, address_family)?;
+                crate::socks::connect_sync(&proxy_addrs, proxy, host, port)
+            }
+            #[cfg(not(feature = "socks"))]
+            Some(proxy) => match *proxy {},
+            None => connect_tcp(host, port, local_addr, dns_cache, address_family, connect_timeout),
+        };
+        ret.map_err(|e| Error::from(e).map_timeout())
+    }
+
     #[derive(Debug)]
-    /// A database connection over Skyhash/TCP
-    pub struct Connection {
-        stream: TcpStream,
+    /// A database connection over Skyhash/TCP. Connections can also be made over any other
+    /// stream with [`Connection::with_stream`]
+    pub struct Connection<S = TcpStream> {
+        stream: S,
         buffer: Vec<u8>,
+        state: ConnectionState,
+        protocol_error: bool,
+        recoverable: bool,
+        bytes_written: u64,
+        bytes_read: u64,
+        name: Option<Arc<str>>,
+        query_hook: Option<QueryHook>,
+        uppercase_actions: bool,
+        compression: Option<Compression>,
+        protocol_version: Option<f32>,
+        server_version: Option<Version>,
+        validate_utf8: bool,
+        entity: Option<String>,
+        reservation: Option<Reservation>,
+        max_response_size: Option<usize>,
     }
 
     impl Connection {
         /// Create a new connection to a Skytable instance hosted on `host` and running on `port`
         pub fn new(host: &str, port: u16) -> SkyResult<Self> {
-            let stream = TcpStream::connect((host, port))?;
-            Ok(Connection {
+            Self::_new(host, port, None, None, None, AddressFamily::Any, None)
+        }
+        /// Create a new connection using the details in the provided URL, authenticating and
+        /// selecting the entity if provided. See [`ConnectionBuilder::from_url`] for the format
+        /// of the URL
+        ///
+        /// [`ConnectionBuilder::from_url`]: crate::ConnectionBuilder::from_url
+        pub fn from_url(url: &str) -> SkyResult<Self> {
+            crate::ConnectionBuilder::from_url(url)?.get_connection()
+        }
+        /// Create a new connection to a Skytable instance hosted on `host` and running on
+        /// `port`, retrying until the server responds to a `HEYA` or `total_timeout` elapses.
+        /// This is useful when the application and the database are started at the same time
+        /// (for example, in containers). The delay between two attempts starts at 50 ms and is
+        /// doubled after every attempt, up to a second
+        ///
+        /// If the server isn't ready in time, the error of the last attempt is returned
+        ///
+        /// ## Example
+        /// ```no_run
+        /// use skytable::Connection;
+        /// use std::time::Duration;
+        ///
+        /// let con = Connection::connect_ready("127.0.0.1", 2003, Duration::from_secs(30)).unwrap();
+        /// ```
+        pub fn connect_ready(host: &str, port: u16, total_timeout: Duration) -> SkyResult<Self> {
+            let deadline = Instant::now() + total_timeout;
+            let mut delay = crate::READY_RETRY_DELAY;
+            loop {
+                let ret = Self::try_connect_ready(host, port, deadline);
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match ret {
+                    Ok(con) => return Ok(con),
+                    Err(e) if remaining == Duration::ZERO => return Err(e),
+                    Err(_) => {
+                        std::thread::sleep(delay.min(remaining));
+                        delay = (delay * 2).min(crate::READY_MAX_RETRY_DELAY);
+                    }
+                }
+            }
+        }
+        /// Create a new connection like [`Self::new`], but give up on connecting to each of
+        /// the addresses the host resolves to after `timeout` (by default, connecting is only
+        /// bounded by the OS). The error of the last address that was tried is returned, which
+        /// is [`SkyhashError::Timeout`] if connecting to it timed out
+        ///
+        /// This only bounds connecting; see [`Self::set_read_timeout`] and
+        /// [`Self::set_write_timeout`] to bound queries
+        pub fn new_with_timeout(host: &str, port: u16, timeout: Duration) -> SkyResult<Self> {
+            let mut last_err = None;
+            for addr in (host, port).to_socket_addrs()? {
+                match TcpStream::connect_timeout(&addr, timeout) {
+                    Ok(stream) => return Ok(Self::with_stream(stream)),
+                    Err(e) => last_err = Some(e),
+                }
+            }
+            let last_err = last_err
+                .unwrap_or_else(|| IoError::new(ErrorKind::InvalidInput, "no address for host"));
+            Err(Error::from(last_err).map_timeout())
+        }
+        fn try_connect_ready(host: &str, port: u16, deadline: Instant) -> SkyResult<Self> {
+            // neither connecting nor the `HEYA` should block past the deadline
+            let timeout = deadline
+                .saturating_duration_since(Instant::now())
+                .max(Duration::from_millis(1));
+            let mut last_err = None;
+            for addr in (host, port).to_socket_addrs()? {
+                match TcpStream::connect_timeout(&addr, timeout) {
+                    Ok(stream) => {
+                        stream.set_read_timeout(Some(timeout))?;
+                        stream.set_write_timeout(Some(timeout))?;
+                        let mut con = Self::with_stream(stream);
+                        let ret = con.run_query_raw(Query::from("heya"));
+                        crate::check_heya(ret?)?;
+                        con.stream.set_read_timeout(None)?;
+                        con.stream.set_write_timeout(None)?;
+                        return Ok(con);
+                    }
+                    Err(e) => last_err = Some(e),
+                }
+            }
+            let last_err = last_err
+                .unwrap_or_else(|| IoError::new(ErrorKind::InvalidInput, "no address for host"));
+            Err(Error::from(last_err).map_timeout())
+        }
+        /// Returns the address of the server this connection is connected to
+        pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.peer_addr()
+        }
+        /// Returns the local address of this connection, which is useful to match the
+        /// connection with the logs of the server
+        pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.local_addr()
+        }
+        /// Enable or disable Nagle's algorithm (`TCP_NODELAY`) on the socket of this
+        /// connection. With Nagle's algorithm disabled, small writes are sent right away instead
+        /// of being coalesced
+        pub fn set_nodelay(&self, nodelay: bool) -> std::io::Result<()> {
+            self.stream.set_nodelay(nodelay)
+        }
+        /// Set the timeout for every read from the socket of this connection (by default,
+        /// reads block until data arrives). Since the timeout applies to every read, a response
+        /// that stalls midway times out too. A query that times out returns
+        /// [`SkyhashError::Timeout`] and poisons the connection, since the rest of the response
+        /// might still arrive
+        pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+            self.stream.set_read_timeout(timeout)
+        }
+        /// Set the timeout for every write to the socket of this connection (by default,
+        /// writes block until they complete). A query that times out returns
+        /// [`SkyhashError::Timeout`] and poisons the connection, since it might have been
+        /// written partially
+        pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+            self.stream.set_write_timeout(timeout)
+        }
+        /// Set the size of the send buffer (`SO_SNDBUF`) of the socket of this connection. A
+        /// larger buffer lets large pipelines be written without waiting for the server to
+        /// read them. The OS may round (or cap) the size
+        pub fn set_send_buffer_size(&self, size: usize) -> std::io::Result<()> {
+            SockRef::from(&self.stream).set_send_buffer_size(size)
+        }
+        /// Cork (or uncork) the socket of this connection (`TCP_CORK`). While the socket is
+        /// corked, only full packets are sent, so that many small writes (like queries sent with
+        /// `send_query`) are coalesced. Uncorking sends whatever is left, so **the socket has
+        /// to be uncorked before reading the responses**; otherwise the last queries are only
+        /// sent once the OS gives up waiting (after 200ms on Linux)
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        pub fn set_cork(&self, cork: bool) -> std::io::Result<()> {
+            SockRef::from(&self.stream).set_tcp_cork(cork)
+        }
+        /// Read the response to a query that was previously sent with [`Self::send_query`],
+        /// without blocking. This returns `Ok(None)` if the complete response hasn't arrived yet
+        /// (the part that did arrive is buffered) and can be used to drive the connection from a
+        /// poll-based event loop
+        ///
+        /// ## Example
+        /// ```no_run
+        /// use skytable::{query, Connection};
+        ///
+        /// let mut con = Connection::new("127.0.0.1", 2003).unwrap();
+        /// con.send_query(query!("heya")).unwrap();
+        /// loop {
+        ///     if let Some(response) = con.poll_response().unwrap() {
+        ///         println!("{:?}", response);
+        ///         break;
+        ///     }
+        ///     // do other work
+        /// }
+        /// ```
+        pub fn poll_response(&mut self) -> SkyResult<Option<Element>> {
+            let ret = self.check_state().and_then(|_| self._poll_response());
+            match self.track(ret)? {
+                Some(RawResponse::SimpleQuery(sq)) => Ok(Some(sq)),
+                Some(RawResponse::PipelinedQuery(_)) => Err(SkyhashError::InvalidResponse.into()),
+                None => Ok(None),
+            }
+        }
+        fn _poll_response(&mut self) -> SkyResult<Option<RawResponse>> {
+            loop {
+                match self.try_response() {
+                    Ok((query, forward_by)) => {
+                        self.buffer.drain(..forward_by);
+                        return Ok(Some(query));
+                    }
+                    Err(e) => self.check_parse_error(e)?,
+                }
+                self.stream.set_nonblocking(true)?;
+                let ret = self.read_more();
+                // every other method expects the socket to block, so blocking mode is restored
+                // whatever the read returned. If that fails, the error poisons the connection
+                match (ret, self.stream.set_nonblocking(false)) {
+                    (_, Err(e)) => return Err(e.into()),
+                    (Ok(true), Ok(())) => self.charge_buffer()?,
+                    (Ok(false), Ok(())) => {
+                        return Err(SkyhashError::ConnectionClosed(self.name.clone()).into())
+                    }
+                    (Err(e), Ok(())) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                    (Err(e), Ok(())) => return Err(e.into()),
+                }
+            }
+        }
+        pub(crate) fn _new(
+            host: &str,
+            port: u16,
+            local_addr: Option<SocketAddr>,
+            proxy: Option<&Socks5Proxy>,
+            dns_cache: Option<&DnsCache>,
+            address_family: AddressFamily,
+            connect_timeout: Option<Duration>,
+        ) -> SkyResult<Self> {
+            let stream = connect(
+                host,
+                port,
+                local_addr,
+                proxy,
+                dns_cache,
+                address_family,
+                connect_timeout,
+            )?;
+            Ok(Self::with_stream(stream))
+        }
+    }
+
+    impl<S: Read + Write> Connection<S> {
+        /// Create a connection over the provided stream, which must already be connected to
+        /// the server. This makes it possible to use a transport other than TCP (for example,
+        /// a Unix socket, a tunnel or an in-memory pipe in tests)
+        ///
+        /// ## Example
+        /// ```no_run
+        /// use skytable::{query, Connection};
+        /// use std::net::TcpStream;
+        /// use std::time::Duration;
+        ///
+        /// // a stream with custom socket options
+        /// let stream = TcpStream::connect("127.0.0.1:2003").unwrap();
+        /// stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        /// let mut con = Connection::with_stream(stream);
+        /// con.run_query_raw(query!("heya")).unwrap();
+        /// ```
+        pub fn with_stream(stream: S) -> Self {
+            Connection {
                 stream,
                 buffer: Vec::with_capacity(BUF_CAP),
-            })
+                state: ConnectionState::Ready,
+                protocol_error: false,
+                recoverable: false,
+                bytes_written: 0,
+                bytes_read: 0,
+                name: None,
+                query_hook: None,
+                uppercase_actions: false,
+                compression: None,
+                protocol_version: None,
+                server_version: None,
+                validate_utf8: true,
+                entity: None,
+                reservation: None,
+                max_response_size: None,
+            }
+        }
+    }
+
+    impl_sync_methods!(Connection<S>, S);
+
+);
+
+/// A connection that reconnects to the server when the connection is lost (for example,
+/// because the server restarted), so that long-lived clients recover by themselves
+///
+/// A connection that can't be used anymore (because it was closed or poisoned) is replaced
+/// before the next query. If the connection is lost while a query runs, the query is retried on
+/// a new connection (as per the [`RetryPolicy`]) only if running it twice is harmless: by
+/// default, that's only the case for actions that only read data (like `GET`, `MGET` or
+/// `EXISTS`), since a write whose response was lost might have been applied already. See
+/// [`Self::set_retry_writes`]. Connecting is retried (as per the same policy) as long as it
+/// fails with I/O errors (or times out)
+///
+/// All the [actions](crate::actions::Actions) can be run on this connection
+///
+/// ## Example
+/// ```no_run
+/// use skytable::actions::Actions;
+/// use skytable::sync::ReconnectingConnection;
+/// use skytable::RetryPolicy;
+///
+/// let mut con = ReconnectingConnection::new("127.0.0.1", 2003, RetryPolicy::default()).unwrap();
+/// let x: String = con.get("x").unwrap();
+/// println!("reconnected {} times", con.reconnects());
+/// ```
+pub struct ReconnectingConnection {
+    builder: ConnectionBuilder,
+    policy: RetryPolicy,
+    con: Option<Connection>,
+    reconnects: u64,
+    retry_writes: bool,
+}
+
+impl ReconnectingConnection {
+    /// Connect to the server at `host:port`, reconnecting as per the provided policy
+    pub fn new(host: &str, port: u16, policy: RetryPolicy) -> SkyResult<Self> {
+        let builder = ConnectionBuilder::new()
+            .set_host(host.to_owned())
+            .set_port(port);
+        Self::with_builder(builder, policy)
+    }
+    /// Connect with the provided builder, which is used again for every reconnect (so that
+    /// the new connections use the same entity, credentials and settings)
+    pub fn with_builder(builder: ConnectionBuilder, policy: RetryPolicy) -> SkyResult<Self> {
+        let con = builder.get_connection()?;
+        Ok(Self {
+            builder,
+            policy,
+            con: Some(con),
+            reconnects: 0,
+            retry_writes: false,
+        })
+    }
+    /// Retry every query after reconnecting, not only the ones that only read data. Only
+    /// enable this if applying any of the queries twice is harmless
+    pub fn set_retry_writes(&mut self, retry_writes: bool) {
+        self.retry_writes = retry_writes;
+    }
+    /// Returns the number of times this connection reconnected
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+    /// Returns the current connection, reconnecting first if it can't be used anymore
+    pub fn connection(&mut self) -> SkyResult<&mut Connection> {
+        if !matches!(&self.con, Some(con) if con.state() == ConnectionState::Ready) {
+            self.con = None;
+            let mut retry = 0;
+            let con = loop {
+                match self.builder.get_connection() {
+                    Ok(con) => break con,
+                    Err(Error::IoError(_) | Error::SkyError(SkyhashError::Timeout))
+                        if retry < self.policy.max_retries() =>
+                    {
+                        std::thread::sleep(self.policy.delay(retry));
+                        retry += 1;
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            self.reconnects += 1;
+            self.con = Some(con);
+        }
+        Ok(self.con.as_mut().unwrap())
+    }
+    /// Runs a query on the current connection like [`Connection::run_query_raw`],
+    /// reconnecting (and retrying the query, if it can be retried) if the connection is lost
+    pub fn run_query_raw<Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<Element> {
+        self._run(query.as_ref(), false)
+    }
+    /// Runs a query using [`Self::run_query_raw`] and attempts to return a type provided by
+    /// the user
+    pub fn run_query<T: FromSkyhashBytes, Q: AsRef<Query>>(&mut self, query: Q) -> SkyResult<T> {
+        self.run_query_raw(query)?.try_element_into()
+    }
+    /// Run `query` (as an action, if `action` is set), retrying it on a new connection if the
+    /// connection is lost and the query can be retried
+    fn _run(&mut self, query: &Query, action: bool) -> SkyResult<Element> {
+        let retry_query = self.retry_writes || crate::is_read_only(query);
+        let mut retry = 0;
+        loop {
+            let con = self.connection()?;
+            let ret = if action {
+                SyncSocket::run(con, query.clone())
+            } else {
+                con.run_query_raw(query)
+            };
+            match &ret {
+                Err(e)
+                    if crate::is_connection_lost(e)
+                        && retry_query
+                        && retry < self.policy.max_retries() =>
+                {
+                    std::thread::sleep(self.policy.delay(retry));
+                    retry += 1;
+                }
+                _ => return ret,
+            }
         }
     }
+}
+
+impl SyncSocket for ReconnectingConnection {
+    fn run(&mut self, q: Query) -> SkyQueryResult {
+        self._run(&q, true)
+    }
+}
 
-    impl_sync_methods!(Connection);
+cfg_sync_unix!(
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
 
+    /// A database connection over Skyhash on a UNIX domain socket. See [`Connection::new_unix`]
+    pub type UnixConnection = Connection<UnixStream>;
+
+    impl Connection<UnixStream> {
+        /// Create a new connection to a Skytable instance listening on the UNIX domain socket at
+        /// `path`. This avoids the overhead of the TCP loopback when the client runs on the same
+        /// host as the server; otherwise, the connection behaves exactly like a TCP connection
+        pub fn new_unix(path: impl AsRef<Path>) -> SkyResult<Self> {
+            Ok(Self::with_stream(UnixStream::connect(path)?))
+        }
+    }
 );
 
 cfg_sync_ssl_any!(
-    use openssl::ssl::{Ssl, SslContext, SslMethod, SslStream};
-    use crate::error::Error;
+    use openssl::ssl::SslStream;
+    use crate::TlsConfig;
     #[derive(Debug)]
     /// A database connection over Skyhash/TLS
     pub struct TlsConnection {
         stream: SslStream<TcpStream>,
         buffer: Vec<u8>,
+        state: ConnectionState,
+        protocol_error: bool,
+        recoverable: bool,
+        bytes_written: u64,
+        bytes_read: u64,
+        name: Option<Arc<str>>,
+        query_hook: Option<QueryHook>,
+        uppercase_actions: bool,
+        compression: Option<Compression>,
+        protocol_version: Option<f32>,
+        server_version: Option<Version>,
+        validate_utf8: bool,
+        entity: Option<String>,
+        reservation: Option<Reservation>,
+        max_response_size: Option<usize>,
     }
 
     impl TlsConnection {
         /// Pass the `host` and `port` and the path to the CA certificate to use for TLS
         pub fn new(host: &str, port: u16, ssl_certificate: &str) -> Result<Self, Error> {
-            let mut ctx = SslContext::builder(SslMethod::tls_client())?;
-            ctx.set_ca_file(ssl_certificate)?;
-            let ssl = Ssl::new(&ctx.build())?;
-            let stream = TcpStream::connect((host, port))?;
+            let tls = TlsConfig::new(ssl_certificate);
+            Self::_new(host, port, &tls, None, None, None, AddressFamily::Any, None)
+        }
+        /// Pass the `host` and `port`, the path to the CA certificate and the paths to the
+        /// (PEM encoded) client certificate and private key to use for mutual TLS
+        pub fn new_with_client_cert(
+            host: &str,
+            port: u16,
+            ssl_certificate: &str,
+            client_cert: &str,
+            client_key: &str,
+        ) -> Result<Self, Error> {
+            let tls = TlsConfig {
+                client_cert: Some((client_cert, client_key)),
+                ..TlsConfig::new(ssl_certificate)
+            };
+            Self::_new(host, port, &tls, None, None, None, AddressFamily::Any, None)
+        }
+        /// Returns the address of the server this connection is connected to
+        pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.get_ref().peer_addr()
+        }
+        /// Set the timeout for every read from the socket of this connection. See
+        /// [`Connection::set_read_timeout`]
+        pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+            self.stream.get_ref().set_read_timeout(timeout)
+        }
+        /// Set the timeout for every write to the socket of this connection. See
+        /// [`Connection::set_write_timeout`]
+        pub fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+            self.stream.get_ref().set_write_timeout(timeout)
+        }
+        /// Returns the local address of this connection, which is useful to match the
+        /// connection with the logs of the server
+        pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+            self.stream.get_ref().local_addr()
+        }
+        #[allow(clippy::too_many_arguments)]
+        pub(crate) fn _new(
+            host: &str,
+            port: u16,
+            tls: &TlsConfig<'_>,
+            local_addr: Option<SocketAddr>,
+            proxy: Option<&Socks5Proxy>,
+            dns_cache: Option<&DnsCache>,
+            address_family: AddressFamily,
+            connect_timeout: Option<Duration>,
+        ) -> Result<Self, Error> {
+            let ssl = tls.new_ssl()?;
+            let stream = connect(
+                host,
+                port,
+                local_addr,
+                proxy,
+                dns_cache,
+                address_family,
+                connect_timeout,
+            )?;
             let mut stream = SslStream::new(ssl, stream)?;
-            stream.connect()?;
+            stream.connect().map_err(Error::TlsHandshakeError)?;
             Ok(Self {
                 stream,
                 buffer: Vec::with_capacity(BUF_CAP),
+                state: ConnectionState::Ready,
+                protocol_error: false,
+                recoverable: false,
+                bytes_written: 0,
+                bytes_read: 0,
+                name: None,
+                query_hook: None,
+                uppercase_actions: false,
+                compression: None,
+                protocol_version: None,
+                server_version: None,
+                validate_utf8: true,
+                entity: None,
+                reservation: None,
+                max_response_size: None,
             })
         }
     }
 
     impl_sync_methods!(TlsConnection);
 );
+
+#[test]
+fn test_responses_from_single_read() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // both responses arrive at once
+        stream.write_all(b"*+4\nHEY!*+4\nHEY!").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let responses: Vec<_> = con.responses().take(3).collect();
+    server.join().unwrap();
+    assert_eq!(responses[0], Ok(Element::String("HEY!".to_owned())));
+    assert_eq!(responses[1], Ok(Element::String("HEY!".to_owned())));
+    assert_eq!(responses[2], Err(SkyhashError::ConnectionClosed(None).into()));
+}
+
+#[test]
+fn test_named_connection_errors() {
+    let (port, server) = crate::mock::serve(move |listener| {
+        // close the connection right away
+        listener.accept().unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_name(Some("cache-pool-3".into()));
+    server.join().unwrap();
+    let e = con.read_response().unwrap_err();
+    assert_eq!(e, SkyhashError::ConnectionClosed(Some("cache-pool-3".into())).into());
+    assert!(e.to_string().starts_with("connection 'cache-pool-3': "));
+    let e = con.read_response().unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    assert!(e.to_string().starts_with("connection 'cache-pool-3': "));
+}
+
+#[test]
+fn test_run_same_query_twice() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut buf = [0u8; 64];
+        for _ in 0..2 {
+            let read = stream.read(&mut buf).unwrap();
+            assert_eq!(&buf[..read], b"*1\n4\nheya");
+            stream.write_all(b"*+4\nHEY!").unwrap();
+        }
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let query = crate::query!("heya");
+    let first: String = con.run_query(&query).unwrap();
+    let second: String = con.run_query(&query).unwrap();
+    server.join().unwrap();
+    assert_eq!(first, "HEY!");
+    assert_eq!(second, "HEY!");
+}
+
+#[test]
+fn test_peek_response_header() {
+    use crate::types::Array;
+    use crate::ResponseHeader;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.write_all(b"*^+2\n").unwrap();
+        // give the client a chance to peek before the payload arrives
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        stream.write_all(b"1\na1\nb").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(con.peek_response_header().unwrap(), ResponseHeader::Array(2));
+    // peeking doesn't consume the response
+    assert_eq!(con.peek_response_header().unwrap().element_count(), 2);
+    assert_eq!(
+        con.read_response().unwrap(),
+        Element::Array(Array::NonNullStr(vec!["a".to_owned(), "b".to_owned()]))
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_peek_element_type() {
+    use crate::ElementType;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.write_all(b"*:").unwrap();
+        // give the client a chance to peek before the payload arrives
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        stream.write_all(b"10\n$1\n!0\n").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(
+        con.peek_element_type().unwrap(),
+        Some(ElementType::UnsignedInt)
+    );
+    assert_eq!(con.read_response().unwrap(), Element::UnsignedInt(10));
+    assert_eq!(con.peek_element_type().unwrap(), None);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_discard_pending() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // the first response arrives in two parts
+        stream.write_all(b"*_2\n+1\na").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        stream.write_all(b"+1\nb*+4\nHEY!").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.discard_pending().unwrap();
+    assert_eq!(con.read_response().unwrap(), Element::String("HEY!".to_owned()));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_discard_pending_after_read_timeout() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for _ in 0..2 {
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            stream.write_all(b"*+4\nHEY!").unwrap();
+        }
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let e = con.run_query_raw(crate::query!("heya")).unwrap_err();
+    assert_eq!(e, SkyhashError::Timeout.into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    con.set_read_timeout(None).unwrap();
+    // the query was written completely, so only its response has to be skipped
+    con.discard_pending().unwrap();
+    assert_eq!(con.state(), ConnectionState::Ready);
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_discard_pending_unrecoverable() {
+    let (port, server) = crate::mock::serve(move |listener| {
+        for response in [&b"*~4\nHEY!"[..], b"*+64\n"] {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            stream.write_all(response).unwrap();
+            stream.write_all(&[b'a'; 64]).unwrap();
+            // something that looks like a response, which mustn't be read as one
+            std::thread::sleep(Duration::from_millis(50));
+            stream.write_all(b"*+4\nHEY!").unwrap();
+            stream.read_to_end(&mut Vec::new()).unwrap();
+        }
+    });
+    // an unknown data type
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let e = con.run_query_raw(crate::query!("heya")).unwrap_err();
+    assert_eq!(e, SkyhashError::UnknownDataType(b'~').into());
+    let e = con.discard_pending().unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    drop(con);
+    // a response that is too large
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_max_response_size(Some(32));
+    let e = con.run_query_raw(crate::query!("heya")).unwrap_err();
+    assert_eq!(e, SkyhashError::ResponseTooLarge.into());
+    let e = con.discard_pending().unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    drop(con);
+    server.join().unwrap();
+}
+
+cfg_dbg!(
+    #[test]
+    fn test_raw_frames() {
+        let (port, server) = crate::mock::serve_once(move |mut stream| {
+            // this is a multi-query frame
+            let mut buf = [0u8; 15];
+            stream.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"*2\n1\na*2\n1\nb1\nc");
+            stream.write_all(b"*!0\n*!1\n").unwrap();
+        });
+        let mut con = Connection::new("127.0.0.1", port).unwrap();
+        con.write_frame(b"*2\n1\na*2\n1\nb1\nc").unwrap();
+        assert_eq!(con.read_frame().unwrap(), b"*!0\n");
+        assert_eq!(con.read_frame().unwrap(), b"*!1\n");
+        server.join().unwrap();
+    }
+    #[test]
+    fn test_run_discard() {
+        let (port, server) = crate::mock::serve_once(move |mut stream| {
+            // the response arrives in two parts
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            stream.write_all(b"*@+3\n5\nsay").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            stream.write_all(b"an\x002\nis*!0\n").unwrap();
+        });
+        let mut con = Connection::new("127.0.0.1", port).unwrap();
+        assert_eq!(con.run_discard(&crate::query!("heya")).unwrap(), 17);
+        // the bytes after the response are kept
+        assert_eq!(con.read_response().unwrap(), Element::RespCode(RespCode::Okay));
+        server.join().unwrap();
+    }
+    #[test]
+    fn test_run_discard_closed() {
+        let (port, server) = crate::mock::serve_once(move |mut stream| {
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            stream.write_all(b"*+4\nHE").unwrap();
+        });
+        let mut con = Connection::new("127.0.0.1", port).unwrap();
+        let query = crate::query!("heya");
+        let e = con.run_discard(&query).unwrap_err();
+        assert_eq!(e, SkyhashError::ConnectionClosed(None).into());
+        assert_eq!(con.state(), ConnectionState::Closed);
+        let e = con.run_discard(&query).unwrap_err();
+        assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+        server.join().unwrap();
+    }
+);
+
+#[test]
+fn test_query_hook() {
+    use std::sync::Mutex;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.write_all(b"*+4\nHEY!").unwrap();
+    });
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let spy = seen.clone();
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_query_hook(Some(QueryHook(Arc::new(move |query, result| {
+        let hey = Element::String("HEY!".to_owned());
+        spy.lock().unwrap().push((query.len(), *result == Ok(hey)));
+    }))));
+    let _: String = con.run_query(crate::query!("heya")).unwrap();
+    server.join().unwrap();
+    assert_eq!(*seen.lock().unwrap(), vec![(1, true)]);
+}
+
+#[test]
+fn test_query_hook_never_sees_token() {
+    use std::sync::Mutex;
+    let auth = crate::query!("auth", "login", "root", "mytoken").as_bytes();
+    let switch = crate::query!("use", crate::DEFAULT_ENTITY).as_bytes();
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for expected in [auth, switch] {
+            let mut query = vec![0u8; expected.len()];
+            stream.read_exact(&mut query).unwrap();
+            assert_eq!(query, expected);
+            stream.write_all(b"*!0\n").unwrap();
+        }
+    });
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let spy = seen.clone();
+    let con = ConnectionBuilder::new()
+        .set_host("127.0.0.1".to_owned())
+        .set_port(port)
+        .set_auth("root".to_owned(), "mytoken".to_owned())
+        .set_query_hook(move |query, _| spy.lock().unwrap().push(query.as_bytes()))
+        .get_connection()
+        .unwrap();
+    drop(con);
+    server.join().unwrap();
+    let seen = seen.lock().unwrap();
+    assert_eq!(
+        seen[0],
+        crate::query!("auth", "login", "root", "***").as_bytes()
+    );
+    assert!(!seen
+        .iter()
+        .any(|query| query.windows(7).any(|bytes| bytes == b"mytoken")));
+}
+
+#[test]
+fn test_set_streaming() {
+    let value = vec![b'x'; 100_000];
+    let expected_value = value.clone();
+    let (port, server) = crate::mock::serve(move |listener| {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut head = [0u8; 23];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"*3\n3\nset6\nmyfile100000\n");
+        let mut value = vec![0u8; 100_000];
+        stream.read_exact(&mut value).unwrap();
+        assert_eq!(value, expected_value);
+        stream.write_all(b"*!0\n").unwrap();
+        // the next client never completes its query
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert!(con.set_streaming("myfile", &value[..], value.len()).unwrap());
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let e = con.set_streaming("myfile", &value[..10], 20).unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::UnexpectedEof).into());
+    assert!(con.is_broken(ErrorPolicy::KeepOnProtocolError));
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_connection_state() {
+    let (port, server) = crate::mock::serve(move |listener| {
+        // close the connection after a partial response
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*!").unwrap();
+        drop(stream);
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let query = crate::query!("heya");
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(con.state(), ConnectionState::Ready);
+    let e = con.run_query_raw(&query).unwrap_err();
+    assert_eq!(e, SkyhashError::ConnectionClosed(None).into());
+    assert_eq!(con.state(), ConnectionState::Closed);
+    assert!(con.is_broken(ErrorPolicy::KeepOnProtocolError));
+    let e = con.run_query_raw(&query).unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    // a short read while streaming poisons the connection
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_streaming("myfile", &b"abc"[..], 5).unwrap_err();
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    let e = con.run_query_raw(&query).unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_pipeline_protocol_error() {
+    use crate::Pipeline;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.write_all(b"*!4\n").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let pipeline = Pipeline::new().append(crate::query!("heya"));
+    let e = con.run_pipeline(&pipeline).unwrap_err();
+    assert_eq!(e, SkyhashError::ProtocolError.into());
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_parse_error_poisons_connection() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // the integer is invalid and the rest of the response arrives later
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*:1x\n").unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let query = crate::query!("heya");
+    let e = con.run_query_raw(&query).unwrap_err();
+    assert_eq!(e, SkyhashError::ParseError.into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    assert!(con.is_broken(ErrorPolicy::KeepOnProtocolError));
+    let e = con.run_query_raw(&query).unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_bad_delimiter_poisons_connection() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\r\nHEY!").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let query = crate::query!("heya");
+    let e = con.run_query_raw(&query).unwrap_err();
+    assert_eq!(e, SkyhashError::BadDelimiter(b'\r').into());
+    assert!(con.is_broken(ErrorPolicy::KeepOnProtocolError));
+    let e = con.run_query_raw(&query).unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_byte_counters() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for _ in 0..2 {
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            stream.write_all(b"*+4\nHEY!").unwrap();
+        }
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let query = crate::query!("heya");
+    con.run_query_raw(&query).unwrap();
+    assert_eq!(con.bytes_written(), 9);
+    assert_eq!(con.bytes_read(), 8);
+    con.reset_counters();
+    assert_eq!((con.bytes_written(), con.bytes_read()), (0, 0));
+    con.run_query_raw(&query).unwrap();
+    assert_eq!((con.bytes_written(), con.bytes_read()), (9, 8));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_query_iter() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // the second element isn't valid UTF-8 and the third isn't a valid integer
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*_5\n+1\na+1\n\xff:x\n:10\n").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        stream.write_all(b"!1\n").unwrap();
+        // a typed array with a null element
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*@+2\n\x001\nb").unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        // this response is never read completely
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*_2\n+1\na+1\nb").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let query = crate::query!("heya");
+    let elements: Vec<_> = con.run_query_iter(&query).unwrap().collect();
+    assert_eq!(
+        elements,
+        vec![
+            Ok(Element::String("a".to_owned())),
+            Ok(Element::Str { raw: vec![0xff] }),
+            Err(SkyhashError::ParseError.into()),
+            Ok(Element::UnsignedInt(10)),
+            Ok(Element::RespCode(RespCode::NotFound)),
+        ]
+    );
+    assert_eq!(con.state(), ConnectionState::Ready);
+    let elements: Vec<_> = con.run_query_iter(&query).unwrap().collect();
+    assert_eq!(
+        elements,
+        vec![
+            Ok(Element::RespCode(RespCode::NotFound)),
+            Ok(Element::String("b".to_owned())),
+        ]
+    );
+    let elements: Vec<_> = con.run_query_iter(&query).unwrap().collect();
+    assert_eq!(elements, vec![Ok(Element::String("HEY!".to_owned()))]);
+    let mut elements = con.run_query_iter(&query).unwrap();
+    assert_eq!(elements.next(), Some(Ok(Element::String("a".to_owned()))));
+    drop(elements);
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_pipeline_checked() {
+    use crate::Pipeline;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.write_all(b"$3\n!0\n+1\na!0\n").unwrap();
+        stream.write_all(b"$3\n!0\n!1\n!2\n").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let pipeline = Pipeline::new()
+        .append(crate::query!("set", "x", "1"))
+        .append(crate::query!("get", "x"))
+        .append(crate::query!("del", "x"));
+    assert_eq!(
+        con.run_pipeline_checked(&pipeline).unwrap(),
+        vec![
+            Element::RespCode(RespCode::Okay),
+            Element::String("a".to_owned()),
+            Element::RespCode(RespCode::Okay),
+        ]
+    );
+    let e = con.run_pipeline_checked(&pipeline).unwrap_err();
+    assert_eq!(e, SkyhashError::PipelineFailed(1, RespCode::NotFound).into());
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_pipeline_partial() {
+    use crate::error::PipelineError;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // the pipeline is `$3\n1\n4\nheya1\n4\nheya1\n4\nheya`
+        stream.read_exact(&mut [0u8; 27]).unwrap();
+        // the connection is closed before the last response
+        stream.write_all(b"$3\n+4\nHEY!!0\n").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let pipeline = Pipeline::new()
+        .append(crate::query!("heya"))
+        .append(crate::query!("heya"))
+        .append(crate::query!("heya"));
+    let e = con.run_pipeline_partial(&pipeline).unwrap_err();
+    assert_eq!(
+        e,
+        PipelineError {
+            received: vec![
+                Element::String("HEY!".to_owned()),
+                Element::RespCode(RespCode::Okay)
+            ],
+            source: SkyhashError::ConnectionClosed(None).into(),
+        }
+    );
+    assert_eq!(con.state(), ConnectionState::Closed);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_pipeline_partial_leaves_next_response() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // the pipeline is `$2\n1\n4\nheya1\n4\nheya`
+        stream.read_exact(&mut [0u8; 19]).unwrap();
+        // the response to the next query is sent along with the responses to the pipeline
+        stream.write_all(b"$2\n+4\nHEY!!0\n*!1\n").unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let pipeline = Pipeline::new()
+        .append(crate::query!("heya"))
+        .append(crate::query!("heya"));
+    assert_eq!(
+        con.run_pipeline_partial(&pipeline).unwrap(),
+        [
+            Element::String("HEY!".to_owned()),
+            Element::RespCode(RespCode::Okay)
+        ]
+    );
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).unwrap(),
+        Element::RespCode(RespCode::NotFound)
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_with_stream() {
+    /// An in-memory stream that replays a fixed response and records what is written to it
+    struct MemoryStream {
+        response: std::io::Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.response.read(buf)
+        }
+    }
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let stream = MemoryStream {
+        response: std::io::Cursor::new(b"*+4\nHEY!*!0\n".to_vec()),
+        written: Vec::new(),
+    };
+    let mut con = Connection::with_stream(stream);
+    let ret = con.run_query_raw(crate::query!("heya")).unwrap();
+    assert_eq!(ret, Element::String("HEY!".to_owned()));
+    assert!(con.set("x", "100").unwrap());
+    assert_eq!(con.stream.written, b"*1\n4\nheya*3\n3\nset1\nx3\n100");
+    // the stream has no more data, like a closed connection
+    let e = con.run_query_raw(crate::query!("heya")).unwrap_err();
+    assert_eq!(e, SkyhashError::ConnectionClosed(None).into());
+}
+
+#[test]
+fn test_unknown_data_type() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*~4\nHEY!").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let e = con.run_query_raw(crate::query!("heya")).unwrap_err();
+    assert_eq!(e, SkyhashError::UnknownDataType(b'~').into());
+    assert_eq!(
+        e.to_string(),
+        "Server sent unknown data type (type symbol: '~') for this client version"
+    );
+    // the rest of the element can't be skipped, so the connection can't be used anymore
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    let e = con.run_query_raw(crate::query!("heya")).unwrap_err();
+    assert_eq!(e, IoError::from(ErrorKind::NotConnected).into());
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_expecting() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for _ in 0..2 {
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            stream.write_all(b"*+4\nHEY!").unwrap();
+        }
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let hey = Element::String("HEY!".to_owned());
+    con.run_expecting(crate::query!("heya"), &hey).unwrap();
+    let e = con
+        .run_expecting(crate::query!("heya"), &Element::RespCode(RespCode::Okay))
+        .unwrap_err();
+    assert_eq!(
+        e,
+        SkyhashError::ResponseMismatch {
+            expected: Element::RespCode(RespCode::Okay),
+            actual: hey,
+        }
+        .into()
+    );
+    assert_eq!(
+        e.to_string(),
+        "Response mismatch\n- expected: RespCode(Okay)\n+   actual: String(\"HEY!\")"
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_use_entity() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut uses = Vec::new();
+        for _ in 0..3 {
+            let mut query = [0u8; 14];
+            stream.read_exact(&mut query).unwrap();
+            uses.push(query.to_vec());
+            stream.write_all(b"*!0\n").unwrap();
+        }
+        uses
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(con.entity(), None);
+    con.use_entity("ks:a").unwrap();
+    // already using it, so nothing is sent
+    con.use_entity("ks:a").unwrap();
+    assert_eq!(con.entity(), Some("ks:a"));
+    con.use_entity("ks:b").unwrap();
+    assert_eq!(con.entity(), Some("ks:b"));
+    // a `USE` that doesn't go through `use_entity` forgets the entity
+    con.run_query_raw(crate::query!("USE", "ks:b")).unwrap();
+    assert_eq!(con.entity(), None);
+    assert_eq!(
+        server.join().unwrap(),
+        [
+            b"*2\n3\nuse4\nks:a".to_vec(),
+            b"*2\n3\nuse4\nks:b".to_vec(),
+            b"*2\n3\nUSE4\nks:b".to_vec()
+        ]
+    );
+}
+
+#[test]
+fn test_run_once() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        // the connection is closed once the response is read
+        assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+    });
+    let ret = crate::run_once("127.0.0.1", port, crate::query!("heya")).unwrap();
+    assert_eq!(ret, Element::String("HEY!".to_owned()));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_socket_options() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let con = Connection::new("127.0.0.1", port).unwrap();
+    let socket = SockRef::from(&con.stream);
+    con.set_nodelay(true).unwrap();
+    assert!(socket.tcp_nodelay().unwrap());
+    con.set_send_buffer_size(1 << 16).unwrap();
+    assert!(socket.send_buffer_size().unwrap() >= 1 << 16);
+    #[cfg(target_os = "linux")]
+    {
+        con.set_cork(true).unwrap();
+        assert!(socket.tcp_cork().unwrap());
+        con.set_cork(false).unwrap();
+        assert!(!socket.tcp_cork().unwrap());
+    }
+}
+
+#[test]
+fn test_socket_addrs() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let con = Connection::new("127.0.0.1", listener.local_addr().unwrap().port()).unwrap();
+    let (_, client_addr) = listener.accept().unwrap();
+    assert_eq!(con.peer_addr().unwrap(), listener.local_addr().unwrap());
+    // the server sees the local address of the connection
+    assert_eq!(con.local_addr().unwrap(), client_addr);
+}
+
+#[test]
+fn test_run_query_project() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let responses: [&[u8]; 4] = [
+            b"*_4\n+1\na:10\n!0\n%2.5\n",
+            b"*@+3\n1\na\x001\nc",
+            b"*^+1\n1\nx",
+            b"*!1\n",
+        ];
+        for response in responses.iter() {
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            stream.write_all(response).unwrap();
+        }
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let heya = crate::query!("heya");
+    assert_eq!(
+        con.run_query_project(&heya, &[3, 0]).unwrap(),
+        [Element::Float(2.5), Element::String("a".to_owned())]
+    );
+    // null elements are returned as `NotFound`
+    assert_eq!(
+        con.run_query_project(&heya, &[1, 2]).unwrap(),
+        [
+            Element::RespCode(RespCode::NotFound),
+            Element::String("c".to_owned())
+        ]
+    );
+    assert!(matches!(
+        con.run_query_project(&heya, &[0, 5]),
+        Err(Error::ParseError(_))
+    ));
+    // nothing is sent for repeated indices
+    assert!(matches!(
+        con.run_query_project(&heya, &[1, 0, 1]),
+        Err(Error::ConfigurationError(_))
+    ));
+    assert_eq!(
+        con.run_query_project(&heya, &[0]).unwrap_err(),
+        SkyhashError::Code(RespCode::NotFound).into()
+    );
+    assert_eq!(con.state(), ConnectionState::Ready);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_ping_latency() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY?").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert!(con.ping_latency().unwrap() >= Duration::from_millis(20));
+    assert_eq!(
+        con.ping_latency().unwrap_err(),
+        SkyhashError::UnexpectedResponse.into()
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_connect_ready() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let (port, server) = crate::mock::serve(move |listener| {
+        // the server isn't ready for the first two attempts
+        for _ in 0..2 {
+            drop(listener.accept().unwrap());
+        }
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+        // this server never gets ready
+        while !stopped.load(Ordering::SeqCst) {
+            drop(listener.accept().unwrap());
+        }
+    });
+    let con = Connection::connect_ready("127.0.0.1", port, Duration::from_secs(10)).unwrap();
+    assert_eq!(con.state(), ConnectionState::Ready);
+    drop(con);
+    let start = Instant::now();
+    Connection::connect_ready("127.0.0.1", port, Duration::from_millis(300)).unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    // stop the server, waking it up in case it's waiting for a connection
+    stop.store(true, Ordering::SeqCst);
+    drop(TcpStream::connect(("127.0.0.1", port)).unwrap());
+    server.join().unwrap();
+}
+
+#[test]
+fn test_sys_metric() {
+    use crate::types::{Health, SysMetric};
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.write_all(b"$2\n+4\ngood:1024\n").unwrap();
+        stream.write_all(b"$2\n+6\nbroken:1024\n").unwrap();
+        stream.write_all(b"$2\n!11\n!11\n").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(
+        con.sys_metric().unwrap(),
+        SysMetric {
+            health: Health::Good,
+            storage: 1024
+        }
+    );
+    assert_eq!(
+        con.sys_metric().unwrap().health,
+        Health::Other("broken".to_owned())
+    );
+    let e = con.sys_metric().unwrap_err();
+    assert_eq!(e, SkyhashError::Code(RespCode::AuthPermissionError).into());
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_health() {
+    use crate::types::Health;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for response in [&b"*+4\ngood"[..], b"*+6\nbroken", b"*!11\n"] {
+            let mut query = [0u8; 24];
+            stream.read_exact(&mut query).unwrap();
+            assert_eq!(&query, b"*3\n3\nsys6\nmetric6\nhealth");
+            stream.write_all(response).unwrap();
+        }
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(con.health().unwrap(), Health::Good);
+    assert_eq!(con.health().unwrap(), Health::Other("broken".to_owned()));
+    let e = con.health().unwrap_err();
+    assert_eq!(e, SkyhashError::Code(RespCode::AuthPermissionError).into());
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_shared_query() {
+    const CONNECTIONS: usize = 4;
+    let (port, server) = crate::mock::serve(move |listener| {
+        let handlers: Vec<_> = (0..CONNECTIONS)
+            .map(|_| {
+                let (mut stream, _) = listener.accept().unwrap();
+                std::thread::spawn(move || {
+                    let mut query = [0u8; 9];
+                    stream.read_exact(&mut query).unwrap();
+                    assert_eq!(&query, b"*1\n4\nheya");
+                    stream.write_all(b"*+4\nHEY!").unwrap();
+                    stream.read_to_end(&mut Vec::new()).unwrap();
+                })
+            })
+            .collect();
+        for handler in handlers {
+            handler.join().unwrap();
+        }
+    });
+    let query = Arc::new(crate::query!("heya"));
+    let handles: Vec<_> = (0..CONNECTIONS)
+        .map(|_| {
+            let query = Arc::clone(&query);
+            std::thread::spawn(move || {
+                let mut con = Connection::new("127.0.0.1", port).unwrap();
+                let ret: String = con.run_query(&*query).unwrap();
+                assert_eq!(ret, "HEY!");
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    // the query wasn't changed by running it
+    assert_eq!(*query, crate::query!("heya"));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_query_flat() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*_2\n+2\n\xff\xfe:100\n").unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*!0\n").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(
+        con.run_query_flat(crate::query!("heya")).unwrap(),
+        [(b'+', vec![0xff, 0xfe]), (b':', b"100".to_vec())]
+    );
+    assert_eq!(
+        con.run_query_flat(crate::query!("heya")).unwrap(),
+        [(b'!', b"0".to_vec())]
+    );
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_empty_pipeline() {
+    let (port, server) = crate::mock::serve_once(|mut stream| {
+        // nothing is written, so the server never sees an empty frame
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        assert!(received.is_empty());
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(con.run_pipeline(Pipeline::new()).unwrap(), []);
+    assert_eq!(con.run_pipeline_checked(Pipeline::new()).unwrap(), []);
+    assert_eq!(con.run_pipeline_partial(Pipeline::new()).unwrap(), []);
+    assert_eq!(con.run_pipeline_windowed(Pipeline::new(), 1).unwrap(), []);
+    assert_eq!(con.state(), ConnectionState::Ready);
+    drop(con);
+    server.join().unwrap();
+    let mut con = DryRunConnection::new();
+    assert_eq!(con.run_pipeline(Pipeline::new()).unwrap(), []);
+    assert!(con.sent().is_empty());
+}
+
+#[test]
+fn test_empty_query() {
+    let (port, server) = crate::mock::serve_once(|mut stream| {
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        assert!(received.is_empty());
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let empty = Query::new().arg(Vec::<String>::new());
+    assert_eq!(con.run_query_raw(&empty), Err(crate::empty_query()));
+    assert_eq!(con.run_args(&[]), Err(crate::empty_query()));
+    let pipeline = Pipeline::new()
+        .append(Query::from("get").arg("x"))
+        .append(empty.clone());
+    assert_eq!(con.run_pipeline(&pipeline), Err(crate::empty_query()));
+    assert_eq!(
+        con.run_pipeline_partial(&pipeline),
+        Err(PipelineError {
+            received: Vec::new(),
+            source: crate::empty_query()
+        })
+    );
+    assert_eq!(
+        con.run_pipeline_windowed(&pipeline, 1),
+        Err(crate::empty_query())
+    );
+    assert_eq!(con.state(), ConnectionState::Ready);
+    drop(con);
+    server.join().unwrap();
+    let mut con = DryRunConnection::new();
+    assert_eq!(con.run_query_raw(&empty), Err(crate::empty_query()));
+    assert_eq!(con.run_pipeline(&pipeline), Err(crate::empty_query()));
+    assert!(con.sent().is_empty());
+}
+
+#[test]
+fn test_run_visit() {
+    use crate::ResponseVisitor;
+    #[derive(Default)]
+    struct Lengths {
+        total: usize,
+        nulls: usize,
+        arrays: usize,
+    }
+    impl ResponseVisitor for Lengths {
+        fn on_string(&mut self, string: &[u8]) {
+            self.total += string.len();
+        }
+        fn on_null(&mut self) {
+            self.nulls += 1;
+        }
+        fn on_array_end(&mut self) {
+            self.arrays += 1;
+        }
+    }
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        // the response arrives in two parts
+        stream.write_all(b"*@+3\n5\nhel").unwrap();
+        stream.flush().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        stream.write_all(b"lo\x003\nabc").unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"$1\n!0\n").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let mut lengths = Lengths::default();
+    con.run_visit(crate::query!("heya"), &mut lengths).unwrap();
+    assert_eq!((lengths.total, lengths.nulls, lengths.arrays), (8, 1, 1));
+    assert!(matches!(
+        con.run_visit(crate::query!("heya"), &mut lengths),
+        Err(Error::SkyError(SkyhashError::InvalidResponse))
+    ));
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_compression() {
+    use crate::types::RawString;
+    use crate::CompressionAlgorithm;
+    let compression = Compression::new(CompressionAlgorithm::Lz4).set_threshold(64);
+    let value = vec![b'x'; 1000];
+    let query = Query::from("set")
+        .arg("x")
+        .arg(RawString::from(value.clone()));
+    let query = compression.compress_query(query);
+    let sent = query.as_bytes();
+    let stored = query.iter_args().last().unwrap().to_vec();
+    assert!(stored.len() < 100);
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = vec![0u8; sent.len()];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(query, sent);
+        stream.write_all(b"*!0\n").unwrap();
+        // `*2\n3\nget1\nx`
+        stream.read_exact(&mut [0u8; 11]).unwrap();
+        let mut response = format!("*?{}\n", stored.len()).into_bytes();
+        response.extend(stored);
+        stream.write_all(&response).unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_compression(Some(compression));
+    assert!(con.set("x", RawString::from(value.clone())).unwrap());
+    let ret: Element = con.get("x").unwrap();
+    assert_eq!(ret, Element::Binstr(value));
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_memory_budget() {
+    use crate::{ConnectionBuilder, MemoryBudget};
+    // a server that answers the `USE` sent while connecting, and then sends a small and a
+    // large response
+    let (port, server) = crate::mock::serve(move |listener| {
+        let mut handlers = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            handlers.push(std::thread::spawn(move || {
+                stream.read_exact(&mut [0u8; 26]).unwrap();
+                stream.write_all(b"*!0\n").unwrap();
+                let mut query = [0u8; 9];
+                if stream.read_exact(&mut query).is_ok() {
+                    stream.write_all(b"*+4\nHEY!").unwrap();
+                }
+                if stream.read_exact(&mut query).is_ok() {
+                    stream.write_all(b"*+64\n").unwrap();
+                    stream.write_all(&[b'a'; 64]).unwrap();
+                }
+                stream.read_to_end(&mut Vec::new()).unwrap();
+            }));
+        }
+        for handler in handlers {
+            handler.join().unwrap();
+        }
+    });
+    let budget = MemoryBudget::new(32);
+    let builder = ConnectionBuilder::new()
+        .set_host("127.0.0.1".to_owned())
+        .set_port(port)
+        .set_memory_budget(budget.clone());
+    let mut a = builder.get_connection().unwrap();
+    let mut b = builder.get_connection().unwrap();
+    assert_eq!(
+        a.run_query_raw(crate::query!("heya")).unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    assert_eq!(budget.used(), 0);
+    // the budget is shared, so the response can't be read while the other connection holds
+    // most of it
+    let mut big = Reservation::new(budget.clone());
+    big.resize(30).unwrap();
+    assert!(matches!(
+        b.run_query_raw(crate::query!("heya")),
+        Err(Error::SkyError(SkyhashError::MemoryBudgetExceeded))
+    ));
+    assert_eq!(b.state(), ConnectionState::Poisoned);
+    assert_eq!(budget.used(), 30);
+    drop(big);
+    // a response larger than the whole budget can never be read
+    assert!(matches!(
+        a.run_query_raw(crate::query!("heya")),
+        Err(Error::SkyError(SkyhashError::MemoryBudgetExceeded))
+    ));
+    assert_eq!(budget.used(), 0);
+    drop((a, b));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_max_response_size() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = [0u8; 9];
+        stream.read_exact(&mut query).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        // a corrupted length that would make the client wait for (and buffer) endless data
+        stream.read_exact(&mut query).unwrap();
+        stream.write_all(b"*+4294967296\n").unwrap();
+        stream.write_all(&[b'a'; 64]).unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_max_response_size(Some(32));
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    assert!(matches!(
+        con.run_query_raw(crate::query!("heya")),
+        Err(Error::SkyError(SkyhashError::ResponseTooLarge))
+    ));
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_server_version() {
+    use crate::types::Version;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = [0u8; 23];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*3\n3\nsys4\ninfo7\nversion");
+        stream.write_all(b"*+5\n0.7.5").unwrap();
+        // the version is only queried once
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(con.server_version().unwrap(), Version::new(0, 7, 5));
+    assert!(con.server_version().unwrap() >= Version::new(0, 7, 0));
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_dry_run_connection() {
+    let mut con = DryRunConnection::new();
+    assert!(con.set("x", "100").unwrap());
+    con.push_response(Element::RespCode(RespCode::OverwriteError));
+    assert!(!con.set("x", "200").unwrap());
+    let pipeline = Pipeline::new()
+        .append(crate::query!("hello"))
+        .append(crate::query!("world"));
+    con.push_response(Element::UnsignedInt(1));
+    assert_eq!(
+        con.run_pipeline(&pipeline).unwrap(),
+        [Element::UnsignedInt(1), Element::RespCode(RespCode::Okay)]
+    );
+    assert_eq!(
+        con.take_sent(),
+        [
+            b"*3\n3\nset1\nx3\n100".to_vec(),
+            b"*3\n3\nset1\nx3\n200".to_vec(),
+            b"$2\n1\n5\nhello1\n5\nworld".to_vec(),
+        ]
+    );
+    assert!(con.sent().is_empty());
+}
+
+#[test]
+fn test_run_with_raw() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        // the next response is sent along with this one
+        stream.write_all(b"*_2\n+1\na:10\n*+4\nHEY!").unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let query = crate::query!("heya");
+    let (element, raw) = con.run_with_raw(&query).unwrap();
+    assert_eq!(raw, b"*_2\n+1\na:10\n");
+    assert_eq!(
+        element,
+        Element::Array(crate::types::Array::Flat(vec![
+            crate::types::FlatElement::String("a".to_owned()),
+            crate::types::FlatElement::UnsignedInt(10),
+        ]))
+    );
+    let (element, raw) = con.run_with_raw(&query).unwrap();
+    assert_eq!(raw, b"*+4\nHEY!");
+    assert_eq!(element, Element::String("HEY!".to_owned()));
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_into() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    // the buffer is cleared before the response is written into it
+    let mut raw = b"stale".to_vec();
+    let element = con.run_into(crate::query!("heya"), &mut raw).unwrap();
+    assert_eq!(raw, b"*+4\nHEY!");
+    assert_eq!(element, Element::String("HEY!".to_owned()));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_uppercase_actions() {
+    use crate::actions::Actions;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = [0u8; 11];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*2\n3\nDEL1\nx");
+        stream.write_all(b"*:1\n").unwrap();
+        // queries that aren't actions are sent as-is
+        let mut query = [0u8; 9];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*1\n4\nheya");
+        stream.write_all(b"*+4\nHEY!").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_uppercase_actions(true);
+    assert_eq!(con.del("x").unwrap(), 1);
+    con.run_query_raw(crate::query!("heya")).unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_set_streaming_hook() {
+    use std::sync::Mutex;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = [0u8; 23];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*3\n3\nSET6\nmyfile5\nhello");
+        stream.write_all(b"*!0\n").unwrap();
+    });
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let spy = seen.clone();
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_uppercase_actions(true);
+    con.set_query_hook(Some(QueryHook(Arc::new(move |query, result| {
+        let okay = Element::RespCode(RespCode::Okay);
+        spy.lock().unwrap().push((query.as_bytes(), *result == Ok(okay)));
+    }))));
+    assert!(con.set_streaming("myfile", &b"hello"[..], 5).unwrap());
+    server.join().unwrap();
+    let frame = b"*2\n3\nSET6\nmyfile".to_vec();
+    assert_eq!(*seen.lock().unwrap(), vec![(frame, true)]);
+}
+
+#[test]
+fn test_lossy_utf8() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*^+2\n4\nHEY!5\nhe\xc3\xffy").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.set_validate_utf8(false);
+    let strings: Vec<String> = con.run_query(crate::query!("heya")).unwrap();
+    assert_eq!(strings, ["HEY!", "he\u{fffd}\u{fffd}y"]);
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_run_retry_on() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for response in [&b"*!5\n"[..], b"*!5\n", b"*!0\n", b"*!2\n", b"*!5\n", b"*!5\n"] {
+            stream.read_exact(&mut [0u8; 9]).unwrap();
+            stream.write_all(response).unwrap();
+        }
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let query = crate::query!("heya");
+    let policy = RetryPolicy::new(2, Duration::from_millis(1));
+    let codes = [RespCode::ServerError];
+    // retried until it succeeds
+    let ret = con.run_retry_on(&query, &codes, policy);
+    assert_eq!(ret, Ok(Element::RespCode(RespCode::Okay)));
+    // other response codes aren't retried
+    let ret = con.run_retry_on(&query, &codes, policy);
+    assert_eq!(ret, Ok(Element::RespCode(RespCode::OverwriteError)));
+    // the last response is returned once the retries are used up
+    let policy = RetryPolicy::new(1, Duration::from_millis(1));
+    let ret = con.run_retry_on(&query, &codes, policy);
+    assert_eq!(ret, Ok(Element::RespCode(RespCode::ServerError)));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_poll_response() {
+    use std::sync::mpsc;
+    let (tx, rx) = mpsc::channel();
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHE").unwrap();
+        rx.recv().unwrap();
+        stream.write_all(b"Y!").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.send_query(crate::query!("heya")).unwrap();
+    assert_eq!(con.poll_response(), Ok(None));
+    tx.send(()).unwrap();
+    let response = loop {
+        if let Some(response) = con.poll_response().unwrap() {
+            break response;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(response, Element::String("HEY!".to_owned()));
+    assert_eq!(con.state(), ConnectionState::Ready);
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_poll_response_restores_blocking() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    con.send_query(crate::query!("heya")).unwrap();
+    assert_eq!(con.poll_response(), Ok(None));
+    // this would fail with `WouldBlock` if the socket was left nonblocking
+    let response = con.read_response().unwrap();
+    assert_eq!(response, Element::String("HEY!".to_owned()));
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_with_prefix() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = [0u8; 28];
+        stream.read_exact(&mut query).unwrap();
+        // the value isn't prefixed
+        assert_eq!(&query, b"*3\n3\nset9\ntenant1:x7\ntenant1");
+        stream.write_all(b"*!0\n").unwrap();
+        let mut query = [0u8; 19];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*2\n3\nget9\ntenant1:x");
+        stream.write_all(b"*+7\ntenant1").unwrap();
+        let mut query = [0u8; 19];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*2\n3\ndel9\ntenant1:x");
+        stream.write_all(b"*:1\n").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let mut tenant = con.with_prefix("tenant1:");
+    assert!(tenant.set("x", "tenant1").unwrap());
+    assert_eq!(tenant.get::<String>("x").unwrap(), "tenant1");
+    assert_eq!(tenant.del("x").unwrap(), 1);
+    assert_eq!(tenant.strip_prefix("tenant1:x"), Some("x"));
+    assert_eq!(tenant.strip_prefix("tenant2:x"), None);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_mksnap_named_errors() {
+    use crate::actions::Actions;
+    let responses: [&[u8]; 5] = [
+        b"*!0\n",
+        b"*!duplicate-snapshot\n",
+        b"*!err-snapshot-busy\n",
+        b"*!err-snapshot-disabled\n",
+        b"*!err-invalid-snapshot-name\n",
+    ];
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for response in responses {
+            let mut query = [0u8; 19];
+            stream.read_exact(&mut query).unwrap();
+            assert_eq!(&query, b"*2\n6\nmksnap6\nbackup");
+            stream.write_all(response).unwrap();
+        }
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert!(con.mksnap_named("backup").unwrap());
+    assert!(!con.mksnap_named("backup").unwrap());
+    for e in [
+        SkyhashError::SnapshotBusy,
+        SkyhashError::SnapshotDisabled,
+        SkyhashError::InvalidSnapshotName,
+    ] {
+        assert_eq!(con.mksnap_named("backup").unwrap_err(), e.into());
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn test_wrongtype_action() {
+    use crate::actions::Actions;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        for _ in 0..2 {
+            let mut query = [0u8; 16];
+            stream.read_exact(&mut query).unwrap();
+            assert_eq!(&query, b"*2\n3\nget6\nmylist");
+            stream.write_all(b"*!7\n").unwrap();
+        }
+        stream.read_to_end(&mut Vec::new()).unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(
+        con.get::<String>("mylist").unwrap_err(),
+        SkyhashError::WrongType.into()
+    );
+    // the response code is returned as-is by `run_query_raw`
+    assert_eq!(
+        con.run_query_raw(crate::query!("get", "mylist")).unwrap(),
+        Element::RespCode(RespCode::Wrongtype)
+    );
+    drop(con);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_auth() {
+    use crate::error::errorstring;
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = [0u8; 29];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*4\n4\nauth5\nlogin4\nroot5\ntoken");
+        stream.write_all(b"*!10\n").unwrap();
+        stream.read_exact(&mut query).unwrap();
+        stream.write_all(b"*!0\n").unwrap();
+        let mut query = [0u8; 17];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*2\n4\nauth6\nlogout");
+        stream.write_all(b"*!0\n").unwrap();
+        stream.read_exact(&mut query).unwrap();
+        stream.write_all(b"*!err-auth-disabled\n").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(
+        con.auth_login("root", "token").unwrap_err(),
+        RespCode::AuthBadCredentials.into()
+    );
+    con.auth_login("root", "token").unwrap();
+    con.auth_logout().unwrap();
+    assert_eq!(
+        con.auth_logout().unwrap_err(),
+        RespCode::ErrorString(errorstring::ERR_AUTH_DISABLED.to_owned()).into()
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_check_protocol() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut query = [0u8; 24];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*3\n3\nsys4\ninfo8\nprotover");
+        stream.write_all(b"*%2.1\n").unwrap();
+        stream.read_exact(&mut query).unwrap();
+        stream.write_all(b"*%1.1\n").unwrap();
+        // servers that predate `SYS` don't know the action
+        stream.read_exact(&mut query).unwrap();
+        stream.write_all(b"*!Unknown action\n").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    assert_eq!(con.protocol_version(), None);
+    assert_eq!(con.check_protocol().unwrap(), 2.1);
+    assert_eq!(con.protocol_version(), Some(2.1));
+    assert_eq!(
+        con.check_protocol().unwrap_err(),
+        SkyhashError::UnsupportedProtocol("1.1".to_owned()).into()
+    );
+    assert_eq!(
+        con.check_protocol().unwrap_err(),
+        SkyhashError::UnsupportedProtocol("unknown".to_owned()).into()
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_fan_out() {
+    let mut servers = Vec::new();
+    let mut connections = Vec::new();
+    for response in [&b"*!0\n"[..], b"*!5\n"] {
+        let (port, server) = crate::mock::serve_once(move |mut stream| {
+            let mut query = [0u8; 12];
+            stream.read_exact(&mut query).unwrap();
+            assert_eq!(&query, b"*1\n7\nflushdb");
+            stream.write_all(response).unwrap();
+        });
+        servers.push(server);
+        connections.push(Connection::new("127.0.0.1", port).unwrap());
+    }
+    let results = fan_out(&mut connections, &crate::query!("flushdb"));
+    assert_eq!(
+        results,
+        vec![
+            Ok(Element::RespCode(RespCode::Okay)),
+            Ok(Element::RespCode(RespCode::ServerError))
+        ]
+    );
+    servers.into_iter().for_each(|server| server.join().unwrap());
+}
+
+#[test]
+fn test_run_pipeline_windowed() {
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        let mut queries = [0u8; 18];
+        stream.read_exact(&mut queries).unwrap();
+        assert_eq!(&queries, b"*1\n4\nheya*1\n4\nheya");
+        // the third query isn't sent until a response was read
+        std::thread::sleep(Duration::from_millis(50));
+        stream.set_nonblocking(true).unwrap();
+        let ret = stream.read(&mut [0u8; 1]);
+        assert_eq!(ret.unwrap_err().kind(), ErrorKind::WouldBlock);
+        stream.set_nonblocking(false).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        stream.read_exact(&mut queries[..9]).unwrap();
+        stream.write_all(b"*+4\nHEY!*!0\n").unwrap();
+    });
+    let mut con = Connection::new("127.0.0.1", port).unwrap();
+    let pipeline = Pipeline::new()
+        .append(crate::query!("heya"))
+        .append(crate::query!("heya"))
+        .append(crate::query!("heya"));
+    let responses = con.run_pipeline_windowed(&pipeline, 2).unwrap();
+    assert_eq!(
+        responses,
+        vec![
+            Element::String("HEY!".to_owned()),
+            Element::String("HEY!".to_owned()),
+            Element::RespCode(RespCode::Okay)
+        ]
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_read_timeout() {
+    use std::sync::mpsc;
+    let (done_tx, done_rx) = mpsc::channel();
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        // the response stalls midway
+        stream.write_all(b"*+4\nHE").unwrap();
+        done_rx.recv().unwrap();
+    });
+    let mut con = Connection::new_with_timeout("127.0.0.1", port, Duration::from_secs(1)).unwrap();
+    con.set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let e = con.run_query_raw(crate::query!("heya")).unwrap_err();
+    assert_eq!(e, SkyhashError::Timeout.into());
+    assert_eq!(con.state(), ConnectionState::Poisoned);
+    done_tx.send(()).unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn test_builder_read_timeout() {
+    use std::sync::mpsc;
+    let (done_tx, done_rx) = mpsc::channel();
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // the response to the `use` never arrives
+        stream.read_exact(&mut [0u8; 16]).unwrap();
+        done_rx.recv().unwrap();
+    });
+    let e = ConnectionBuilder::new()
+        .set_port(port)
+        .set_entity("ks:tbl".to_owned())
+        .set_read_timeout(Duration::from_millis(20))
+        .get_connection()
+        .unwrap_err();
+    assert_eq!(e, SkyhashError::Timeout.into());
+    done_tx.send(()).unwrap();
+    server.join().unwrap();
+}
+
+#[cfg(feature = "unix")]
+#[test]
+fn test_unix_connection() {
+    use std::os::unix::net::UnixListener;
+    let path = std::env::temp_dir().join(format!("skytable-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+    });
+    let mut con = Connection::new_unix(&path).unwrap();
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    server.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(any(feature = "ssl", feature = "sslv"))]
+#[test]
+fn test_tls_handshake_error() {
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509NameBuilder, X509};
+    // a self-signed CA certificate, like the ones used by self-hosted deployments
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "skytable-test").unwrap();
+    let name = name.build();
+    let mut cert = X509::builder().unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+    let ca_file = std::env::temp_dir().join(format!("skytable-ca-{}.pem", std::process::id()));
+    std::fs::write(&ca_file, cert.build().to_pem().unwrap()).unwrap();
+    // a server that doesn't speak TLS
+    let (port, server) = crate::mock::serve_once(move |mut stream| {
+        // the client hello
+        assert_ne!(stream.read(&mut [0u8; 1024]).unwrap(), 0);
+        stream.write_all(b"*!0\n").unwrap();
+    });
+    let ret = TlsConnection::new("127.0.0.1", port, ca_file.to_str().unwrap());
+    std::fs::remove_file(&ca_file).unwrap();
+    assert!(matches!(ret, Err(Error::TlsHandshakeError(_))));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_reconnecting_connection() {
+    let (port, server) = crate::mock::serve(move |listener| {
+        // every new connection switches to the default entity first
+        let accept = || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut query = [0u8; 26];
+            stream.read_exact(&mut query).unwrap();
+            assert_eq!(&query, b"*2\n3\nuse15\ndefault:default");
+            stream.write_all(b"*!0\n").unwrap();
+            stream
+        };
+        // the first connection is lost while the query runs, so the query is retried
+        let mut stream = accept();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        drop(stream);
+        let mut stream = accept();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+        // writes aren't retried
+        let mut query = [0u8; 14];
+        stream.read_exact(&mut query).unwrap();
+        assert_eq!(&query, b"*3\n3\nset1\nx1\n1");
+        drop(stream);
+        let mut stream = accept();
+        stream.read_exact(&mut [0u8; 9]).unwrap();
+        stream.write_all(b"*+4\nHEY!").unwrap();
+    });
+    let mut con = ReconnectingConnection::new("127.0.0.1", port, RetryPolicy::default()).unwrap();
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    assert_eq!(con.reconnects(), 1);
+    assert!(crate::is_connection_lost(&con.set("x", "1").unwrap_err()));
+    assert_eq!(con.reconnects(), 1);
+    assert_eq!(
+        con.run_query_raw(crate::query!("heya")).unwrap(),
+        Element::String("HEY!".to_owned())
+    );
+    assert_eq!(con.reconnects(), 2);
+    server.join().unwrap();
+}
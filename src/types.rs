@@ -86,17 +86,37 @@
 //!
 
 use crate::error::Error;
+use crate::error::SkyhashError;
 use crate::Element;
 use crate::Query;
 use crate::RespCode;
 use crate::SkyResult;
-use core::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
+use core::hash::{Hash, Hasher};
+use core::mem;
 use core::ops::Deref;
 use core::ops::DerefMut;
+use std::collections::{BTreeMap, HashMap};
 
 const BAD_ELEMENT: &str = "Bad element type for parsing into custom type";
 const HAS_NULL_ELEMENTS: &str = "Array has null elements";
 
+/// Returns the error for an element that can't be converted into the expected type. If the
+/// server returned an error (a response code other than [`RespCode::Okay`], including error
+/// strings) instead of data, the error is returned as a [`SkyhashError::Code`], so that it
+/// isn't mistaken for a type mismatch
+fn bad_element(expected: &str, found: Element) -> Error {
+    match found {
+        Element::RespCode(rc) if rc != RespCode::Okay => SkyhashError::Code(rc).into(),
+        found => Error::ParseError(format!(
+            "{}: expected {}, found {}",
+            BAD_ELEMENT,
+            expected,
+            found.type_name()
+        )),
+    }
+}
+
 /// Anything that implements this trait can be turned into a [`String`]. This trait is implemented
 /// for most primitive types by default using [`std`]'s [`ToString`] trait.
 ///
@@ -115,9 +135,41 @@ const HAS_NULL_ELEMENTS: &str = "Array has null elements";
 /// }
 /// ```
 ///
+/// ### Serializing in place
+///
+/// Queries don't call [`IntoSkyhashBytes::as_bytes`] directly; they call
+/// [`IntoSkyhashBytes::write_skyhash`], which by default appends the bytes returned by `as_bytes`
+/// to the query's buffer. Performance-critical types can override it to write themselves into
+/// the buffer directly, avoiding the intermediate allocation:
+/// ```
+/// use skytable::types::IntoSkyhashBytes;
+/// use skytable::Query;
+/// use std::io::Write;
+///
+/// struct Point(u32, u32);
+///
+/// impl IntoSkyhashBytes for Point {
+///     fn as_bytes(&self) -> Vec<u8> {
+///         let mut buf = Vec::new();
+///         self.write_skyhash(&mut buf);
+///         buf
+///     }
+///     fn write_skyhash(&self, buf: &mut Vec<u8>) {
+///         write!(buf, "{},{}", self.0, self.1).unwrap();
+///     }
+/// }
+///
+/// assert_eq!(Query::from("set").arg("x").arg(Point(1, 2)), Query::from(vec!["set", "x", "1,2"]));
+/// ```
 pub trait IntoSkyhashBytes: Send + Sync {
     /// Return the byte representation of Self
     fn as_bytes(&self) -> Vec<u8>;
+    /// Append the byte representation of Self to `buf`. The default implementation extends
+    /// `buf` with the return value of [`IntoSkyhashBytes::as_bytes`]; this has to write
+    /// exactly the same bytes
+    fn write_skyhash(&self, buf: &mut Vec<u8>) {
+        buf.extend(self.as_bytes())
+    }
 }
 
 macro_rules! impl_skyhash_bytes {
@@ -127,6 +179,9 @@ macro_rules! impl_skyhash_bytes {
                 fn as_bytes(&self) -> Vec<u8> {
                     self.to_string().into_bytes()
                 }
+                fn write_skyhash(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(AsRef::<[u8]>::as_ref(self))
+                }
             }
         )*
     };
@@ -175,7 +230,7 @@ where
     T: IntoSkyhashBytes,
 {
     fn push_into_query(&self, q: &mut Query) {
-        q._push_arg(self.as_bytes());
+        q._write_arg(self);
     }
     fn incr_len_by(&self) -> usize {
         1
@@ -218,6 +273,61 @@ where
     }
 }
 
+/// `Some(value)` adds `value` to the query while `None` adds nothing at all (the argument is
+/// skipped). Skyhash queries have no null element, so if an absent value has to take up a
+/// position in the query, use [`Query::arg_or_empty`] instead
+impl<T> IntoSkyhashAction for Option<T>
+where
+    T: IntoSkyhashBytes,
+{
+    fn push_into_query(&self, data: &mut Query) {
+        if let Some(elem) = self {
+            elem.push_into_query(data)
+        }
+    }
+    fn incr_len_by(&self) -> usize {
+        self.is_some() as usize
+    }
+}
+
+macro_rules! impl_skyhash_action_map {
+    ($($ty:ident),*) => {
+        $(
+            /// The entries are added as alternating keys and values, which is the layout expected
+            /// by actions like `MSET`. Do note that iteration order may be arbitrary
+            impl<K, V> IntoSkyhashAction for $ty<K, V>
+            where
+                K: IntoSkyhashBytes,
+                V: IntoSkyhashBytes,
+            {
+                fn push_into_query(&self, data: &mut Query) {
+                    self.iter().for_each(|(k, v)| {
+                        k.push_into_query(data);
+                        v.push_into_query(data);
+                    });
+                }
+                fn incr_len_by(&self) -> usize {
+                    self.len() * 2
+                }
+            }
+            impl<K, V> IntoSkyhashAction for &$ty<K, V>
+            where
+                K: IntoSkyhashBytes,
+                V: IntoSkyhashBytes,
+            {
+                fn push_into_query(&self, data: &mut Query) {
+                    (*self).push_into_query(data)
+                }
+                fn incr_len_by(&self) -> usize {
+                    (*self).incr_len_by()
+                }
+            }
+        )*
+    };
+}
+
+impl_skyhash_action_map!(HashMap, BTreeMap);
+
 #[cfg(feature = "const-gen")]
 impl<T: IntoSkyhashBytes, const N: usize> IntoSkyhashAction for [T; N] {
     fn push_into_query(&self, data: &mut Query) {
@@ -249,11 +359,232 @@ pub enum SnapshotResult {
     Busy,
 }
 
+/// The health of the server, as reported by `SYS METRIC health`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Health {
+    /// The server is healthy (`good`)
+    Good,
+    /// The server ran into a critical error, like a failed background flush (`critical`)
+    Critical,
+    /// A health status that this client doesn't know about
+    Other(String),
+}
+
+#[cfg(any(feature = "sync", feature = "aio"))]
+impl Health {
+    /// Returns the query for the health of the server
+    pub(crate) fn query() -> Query {
+        Query::from("sys").arg("metric").arg("health")
+    }
+    /// Parse the response to [`Health::query`]. Unknown statuses are kept in [`Health::Other`]
+    pub(crate) fn from_response(response: Element) -> SkyResult<Self> {
+        match response {
+            Element::String(st) => match st.as_str() {
+                "good" => Ok(Health::Good),
+                "critical" => Ok(Health::Critical),
+                _ => Ok(Health::Other(st)),
+            },
+            Element::RespCode(rc) => Err(SkyhashError::Code(rc).into()),
+            _ => Err(SkyhashError::UnexpectedDataType.into()),
+        }
+    }
+}
+
+/// The version of the server, as reported by `SYS INFO VERSION`. This is returned by the
+/// `server_version` method on connections
+///
+/// Versions are ordered like [semantic versions](https://semver.org), so features can be
+/// enabled depending on the version of the server. Pre-releases are ordered before the release
+/// and by their labels
+///
+/// ## Example
+/// ```
+/// use skytable::types::Version;
+///
+/// let version: Version = "0.8.0-beta.2".parse().unwrap();
+/// assert!(version > Version::new(0, 7, 5));
+/// assert!(version < Version::new(0, 8, 0));
+/// assert_eq!(version.to_string(), "0.8.0-beta.2");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version {
+    /// The major version
+    pub major: u64,
+    /// The minor version
+    pub minor: u64,
+    /// The patch version
+    pub patch: u64,
+    /// The pre-release label (like `beta.2` in `0.8.0-beta.2`), if any
+    pub pre: Option<String>,
+}
+
+impl Version {
+    /// Create a release version
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre: None,
+        }
+    }
+}
+
+#[cfg(any(feature = "sync", feature = "aio"))]
+impl Version {
+    /// Returns the query for the version of the server
+    pub(crate) fn query() -> Query {
+        Query::from("sys").arg("info").arg("version")
+    }
+    /// Parse the response to [`Version::query`]
+    pub(crate) fn from_response(response: Element) -> SkyResult<Self> {
+        match response {
+            Element::String(st) => st.parse(),
+            Element::RespCode(rc) => Err(SkyhashError::Code(rc).into()),
+            _ => Err(SkyhashError::UnexpectedDataType.into()),
+        }
+    }
+}
+
+impl core::str::FromStr for Version {
+    type Err = Error;
+    /// Parse a version like `0.7.5` or `0.8.0-beta.2`. Build metadata (like `+abcdef`) is
+    /// ignored
+    fn from_str(st: &str) -> Result<Self, Error> {
+        let invalid = || Error::ParseError(format!("invalid version: {:?}", st));
+        let version = st.split('+').next().unwrap_or(st);
+        let (release, pre) = match version.split_once('-') {
+            Some((release, pre)) if !pre.is_empty() => (release, Some(pre.to_owned())),
+            Some(_) => return Err(invalid()),
+            None => (version, None),
+        };
+        let mut numbers = release.split('.').map(|n| n.parse::<u64>());
+        match (
+            numbers.next(),
+            numbers.next(),
+            numbers.next(),
+            numbers.next(),
+        ) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Ok(Self {
+                major,
+                minor,
+                patch,
+                pre,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl core::fmt::Display for Version {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        match &self.pre {
+            Some(pre) => write!(f, "-{}", pre),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        use core::cmp::Ordering;
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => cmp_pre_release(a, b),
+            })
+    }
+}
+
+/// Compare two pre-release versions like semver does: their dot separated identifiers are
+/// compared one by one, numerically if both are numeric (so `beta.2` is lower than `beta.10`)
+/// and lexically otherwise, with numeric identifiers being lower than the others. If all their
+/// identifiers are equal, the version with more identifiers is greater
+fn cmp_pre_release(a: &str, b: &str) -> core::cmp::Ordering {
+    use core::cmp::Ordering;
+    let numeric = |id: &str| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit());
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        let ordering = match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (numeric(a), numeric(b)) {
+                (true, true) => {
+                    // numbers can be longer than a `u64`, so they're compared by their digits.
+                    // Leading zeros only break ties, to stay consistent with `Eq`
+                    let (a_digits, b_digits) =
+                        (a.trim_start_matches('0'), b.trim_start_matches('0'));
+                    a_digits
+                        .len()
+                        .cmp(&b_digits.len())
+                        .then_with(|| a_digits.cmp(b_digits))
+                        .then_with(|| a.cmp(b))
+                }
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (false, false) => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Metrics of the server, as reported by `SYS METRIC`. This is returned by the `sys_metric`
+/// method on connections
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SysMetric {
+    /// The health of the server (`SYS METRIC health`)
+    pub health: Health,
+    /// The size of the data stored by the server in bytes (`SYS METRIC storage`)
+    pub storage: u64,
+}
+
+#[cfg(any(feature = "sync", feature = "aio"))]
+impl SysMetric {
+    /// Returns the pipeline that queries every metric
+    pub(crate) fn pipeline() -> crate::Pipeline {
+        crate::Pipeline::new()
+            .append(Health::query())
+            .append(Query::from("sys").arg("metric").arg("storage"))
+    }
+    /// Parse the responses to [`SysMetric::pipeline`]
+    pub(crate) fn from_responses(responses: Vec<Element>) -> SkyResult<Self> {
+        let mut responses = responses.into_iter();
+        let (health, storage) = match (responses.next(), responses.next(), responses.next()) {
+            (Some(health), Some(storage), None) => (health, storage),
+            _ => return Err(SkyhashError::InvalidResponse.into()),
+        };
+        let health = Health::from_response(health)?;
+        let storage = match storage {
+            Element::UnsignedInt(storage) => storage,
+            Element::RespCode(rc) => return Err(SkyhashError::Code(rc).into()),
+            _ => return Err(SkyhashError::UnexpectedDataType.into()),
+        };
+        Ok(Self { health, storage })
+    }
+}
+
 /// Implement this trait for methods in [`actions`](crate::actions) that need them. See the
 /// [module level documentation](crate::types) for more information
 pub trait GetIterator<T: IntoSkyhashBytes>: IntoSkyhashAction {
     /// Returns an iterator for self
-    fn get_iter(&self) -> std::slice::Iter<T>;
+    fn get_iter(&self) -> std::slice::Iter<'_, T>;
 }
 
 #[cfg(feature = "const-gen")]
@@ -270,7 +601,7 @@ impl<T: IntoSkyhashBytes, const N: usize> GetIterator<T> for &'static [T; N] {
     }
 }
 
-impl<'a, T: IntoSkyhashBytes> GetIterator<T> for &'a [T] {
+impl<T: IntoSkyhashBytes> GetIterator<T> for &[T] {
     fn get_iter(&self) -> std::slice::Iter<'_, T> {
         self.iter()
     }
@@ -283,16 +614,19 @@ impl<T: IntoSkyhashBytes> GetIterator<T> for Vec<T> {
 }
 
 /// Array types
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum Array {
     /// A binary array with nullable elements(typed array tsymbol `?`, `@` base tsymbol)
     Bin(Vec<Option<Vec<u8>>>),
     /// A non-null binary array
     NonNullBin(Vec<Vec<u8>>),
-    /// An unicode string array with nullable elements (typed array tsymbol `+`, `@` base tsymbol)
+    /// An unicode string array with nullable elements (typed array tsymbol `+`, `@` base tsymbol).
+    /// If any of the strings sent by the server isn't valid UTF-8, the array is returned as
+    /// [`Array::Bin`] instead
     Str(Vec<Option<String>>),
-    /// A non-null string array
+    /// A non-null string array. If any of the strings sent by the server isn't valid UTF-8, the
+    /// array is returned as [`Array::NonNullBin`] instead
     NonNullStr(Vec<String>),
     /// A non-recursive 'flat' array (tsymbol `_`)
     Flat(Vec<FlatElement>),
@@ -300,12 +634,66 @@ pub enum Array {
     Recursive(Vec<Element>),
 }
 
-#[derive(Debug, PartialEq)]
+impl Array {
+    /// Turns this array into a vector of [`Element`]s, returning an error if the array has
+    /// null elements
+    pub fn into_elements(self) -> SkyResult<Vec<Element>> {
+        fn nonnull<T>(v: Vec<Option<T>>, f: fn(T) -> Element) -> SkyResult<Vec<Element>> {
+            v.into_iter()
+                .map(|item| {
+                    item.map(f)
+                        .ok_or_else(|| Error::ParseError(HAS_NULL_ELEMENTS.to_owned()))
+                })
+                .collect()
+        }
+        let ret = match self {
+            Self::Bin(brr) => nonnull(brr, Element::Binstr)?,
+            Self::Str(srr) => nonnull(srr, Element::String)?,
+            Self::NonNullBin(brr) => brr.into_iter().map(Element::Binstr).collect(),
+            Self::NonNullStr(srr) => srr.into_iter().map(Element::String).collect(),
+            Self::Flat(frr) => frr.into_iter().map(Element::from).collect(),
+            Self::Recursive(rrr) => rrr,
+        };
+        Ok(ret)
+    }
+    /// Returns the number of elements in this array (including null elements)
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Bin(brr) => brr.len(),
+            Self::Str(srr) => srr.len(),
+            Self::NonNullBin(brr) => brr.len(),
+            Self::NonNullStr(srr) => srr.len(),
+            Self::Flat(frr) => frr.len(),
+            Self::Recursive(rrr) => rrr.len(),
+        }
+    }
+    /// Check if this array has no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns (a copy of) the element at `index` as an [`Element`], whatever the kind of this
+    /// array is. `None` is returned if `index` is out of bounds or the element is null
+    pub fn get(&self, index: usize) -> Option<Element> {
+        match self {
+            Self::Bin(brr) => brr.get(index)?.clone().map(Element::Binstr),
+            Self::Str(srr) => srr.get(index)?.clone().map(Element::String),
+            Self::NonNullBin(brr) => brr.get(index).cloned().map(Element::Binstr),
+            Self::NonNullStr(srr) => srr.get(index).cloned().map(Element::String),
+            Self::Flat(frr) => frr.get(index).cloned().map(Element::from),
+            Self::Recursive(rrr) => rrr.get(index).cloned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 #[non_exhaustive]
 /// A _flat_ element. This corresponds to the types that can be present
 /// in a flat array as defined by the Skyhash protocol
+///
+/// Like [`Element`]s, floats are compared (and hashed) by their bit patterns
 pub enum FlatElement {
-    /// An unicode string
+    /// An unicode string. Strings that aren't valid UTF-8 are returned as
+    /// [`FlatElement::Binstr`]s instead
     String(String),
     /// A binary string (blob)
     Binstr(Vec<u8>),
@@ -317,6 +705,46 @@ pub enum FlatElement {
     Float(f32),
 }
 
+impl PartialEq for FlatElement {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Binstr(a), Self::Binstr(b)) => a == b,
+            (Self::RespCode(a), Self::RespCode(b)) => a == b,
+            (Self::UnsignedInt(a), Self::UnsignedInt(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for FlatElement {}
+
+impl Hash for FlatElement {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Self::String(st) => st.hash(state),
+            Self::Binstr(bstr) => bstr.hash(state),
+            Self::RespCode(rc) => rc.hash(state),
+            Self::UnsignedInt(int) => int.hash(state),
+            Self::Float(float) => float.to_bits().hash(state),
+        }
+    }
+}
+
+impl From<FlatElement> for Element {
+    fn from(fe: FlatElement) -> Self {
+        match fe {
+            FlatElement::String(st) => Element::String(st),
+            FlatElement::Binstr(bstr) => Element::Binstr(bstr),
+            FlatElement::RespCode(rc) => Element::RespCode(rc),
+            FlatElement::UnsignedInt(int) => Element::UnsignedInt(int),
+            FlatElement::Float(float) => Element::Float(float),
+        }
+    }
+}
+
 /// A raw string
 ///
 /// Use this type when you need to directly send raw data (i.e a byte sequence) instead of converting
@@ -391,12 +819,18 @@ impl IntoSkyhashBytes for RawString {
     fn as_bytes(&self) -> Vec<u8> {
         self.0.to_owned()
     }
+    fn write_skyhash(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.0)
+    }
 }
 
-impl<'a> IntoSkyhashBytes for &'a RawString {
+impl IntoSkyhashBytes for &RawString {
     fn as_bytes(&self) -> Vec<u8> {
         self.0.to_owned()
     }
+    fn write_skyhash(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.0)
+    }
 }
 
 /// Implementing this trait enables Skyhash [elements](crate::Element) to be converted
@@ -454,6 +888,38 @@ pub trait FromSkyhashBytes: Sized {
     fn from_element(element: Element) -> SkyResult<Self>;
 }
 
+/// Types that can be stored as one key per field, with [`set_struct`](crate::actions::Actions::set_struct)
+/// and [`get_struct`](crate::actions::Actions::get_struct)
+///
+/// This is usually derived with the `ToSkyhashMap` derive macro (behind the `derive` feature),
+/// but it can also be implemented manually:
+/// ```
+/// use skytable::types::ToSkyhashMap;
+///
+/// struct User {
+///     name: String,
+///     email: String,
+/// }
+///
+/// impl ToSkyhashMap for User {
+///     fn field_names() -> &'static [&'static str] {
+///         &["name", "email"]
+///     }
+///     fn to_skyhash_map(&self) -> Vec<(&'static str, Vec<u8>)> {
+///         vec![
+///             ("name", self.name.clone().into_bytes()),
+///             ("email", self.email.clone().into_bytes()),
+///         ]
+///     }
+/// }
+/// ```
+pub trait ToSkyhashMap: Send + Sync {
+    /// The names of the fields, in the same order as [`ToSkyhashMap::to_skyhash_map`]
+    fn field_names() -> &'static [&'static str];
+    /// Returns every field name along with the byte representation of its value
+    fn to_skyhash_map(&self) -> Vec<(&'static str, Vec<u8>)>;
+}
+
 macro_rules! impl_from_skyhash {
     ($($ty:ty),* $(,)?) => {
         $(impl FromSkyhashBytes for $ty {
@@ -462,7 +928,7 @@ macro_rules! impl_from_skyhash {
                     Element::Binstr(bstr) => String::from_utf8_lossy(&bstr).parse::<$ty>()?,
                     Element::String(st) => st.parse::<$ty>()?,
                     Element::UnsignedInt(int) => int.try_into()?,
-                    _ => return Err(Error::ParseError(BAD_ELEMENT.to_owned())),
+                    other => return Err(bad_element("an integer", other)),
                 };
                 Ok(ret)
             }
@@ -472,13 +938,48 @@ macro_rules! impl_from_skyhash {
 
 impl_from_skyhash!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, usize, isize);
 
+macro_rules! impl_from_skyhash_float {
+    ($($ty:ty),* $(,)?) => {
+        $(impl FromSkyhashBytes for $ty {
+            fn from_element(element: Element) -> SkyResult<$ty> {
+                let ret = match element {
+                    Element::Binstr(bstr) => String::from_utf8_lossy(&bstr).parse::<$ty>()?,
+                    Element::String(st) => st.parse::<$ty>()?,
+                    Element::Float(float) => float.into(),
+                    other => return Err(bad_element("a float", other)),
+                };
+                Ok(ret)
+            }
+        })*
+    };
+}
+
+impl_from_skyhash_float!(f32, f64);
+
+impl FromSkyhashBytes for bool {
+    fn from_element(element: Element) -> SkyResult<bool> {
+        let ret = match element {
+            Element::UnsignedInt(0) => false,
+            Element::UnsignedInt(1) => true,
+            Element::String(ref st) if st == "true" => true,
+            Element::String(ref st) if st == "false" => false,
+            Element::Binstr(ref bstr) if bstr == b"true" => true,
+            Element::Binstr(ref bstr) if bstr == b"false" => false,
+            other => return Err(bad_element("a boolean", other)),
+        };
+        Ok(ret)
+    }
+}
+
 impl FromSkyhashBytes for String {
     fn from_element(element: Element) -> SkyResult<String> {
         let e = match element {
-            Element::Binstr(bstr) => std::string::String::from_utf8(bstr)?,
+            Element::Binstr(bstr) | Element::Str { raw: bstr } => {
+                std::string::String::from_utf8(bstr)?
+            }
             Element::String(st) => st,
             Element::UnsignedInt(int) => int.to_string(),
-            _ => return Err(Error::ParseError(BAD_ELEMENT.to_owned())),
+            other => return Err(bad_element("a string", other)),
         };
         Ok(e)
     }
@@ -517,7 +1018,7 @@ impl FromSkyhashBytes for Vec<String> {
                 }
                 ret
             }
-            _ => return Err(Error::ParseError(BAD_ELEMENT.to_owned())),
+            other => return Err(bad_element("a string array", other)),
         };
         Ok(e)
     }
@@ -552,7 +1053,7 @@ impl FromSkyhashBytes for Vec<Vec<u8>> {
                 strarr.into_iter().map(|v| v.into_bytes()).collect()
             }
             Element::Array(Array::NonNullBin(brr)) => brr,
-            _ => return Err(Error::ParseError(BAD_ELEMENT.to_owned())),
+            other => return Err(bad_element("a binary array", other)),
         };
         Ok(e)
     }
@@ -564,21 +1065,58 @@ impl FromSkyhashBytes for Element {
     }
 }
 
+/// Any kind of array can be turned into a vector of [`Element`]s, so that the elements of
+/// typed and flat arrays can be handled alike. See [`Array::into_elements`]
+impl FromSkyhashBytes for Vec<Element> {
+    fn from_element(e: Element) -> SkyResult<Self> {
+        match e {
+            Element::Array(array) => array.into_elements(),
+            other => Err(bad_element("an array", other)),
+        }
+    }
+}
+
+/// A missing value (a [`RespCode::NotFound`]) is returned as `None`, so that looking up a key
+/// that might not exist doesn't need the error to be matched
+impl<T: FromSkyhashBytes> FromSkyhashBytes for Option<T> {
+    fn from_element(element: Element) -> SkyResult<Self> {
+        match element {
+            Element::RespCode(RespCode::NotFound) => Ok(None),
+            element => T::from_element(element).map(Some),
+        }
+    }
+}
+
 macro_rules! impl_fsb_element {
-    ($($ty:ty => $variant:ident),*) => {
+    ($($ty:ty => $variant:ident => $expected:literal),*) => {
         $(impl FromSkyhashBytes for $ty {
             fn from_element(e: Element) -> SkyResult<$ty> {
-                if let Element::$variant(rc) = e {
-                    Ok(rc)
-                } else {
-                    Err(Error::ParseError(BAD_ELEMENT.to_owned()))
+                match e {
+                    Element::$variant(rc) => Ok(rc),
+                    other => Err(bad_element($expected, other)),
                 }
             }
         })*
     };
 }
 
-impl_fsb_element!(RespCode => RespCode, Array => Array);
+impl_fsb_element!(
+    RespCode => RespCode => "a response code",
+    Array => Array => "an array"
+);
+
+macro_rules! impl_try_from_element {
+    ($($ty:ty),* $(,)?) => {
+        $(impl TryFrom<Element> for $ty {
+            type Error = Error;
+            fn try_from(element: Element) -> SkyResult<$ty> {
+                <$ty>::from_element(element)
+            }
+        })*
+    };
+}
+
+impl_try_from_element!(String, u64, i64, f64, Vec<String>, bool);
 
 #[test]
 fn test_arr_from_str_to_vecstr() {
@@ -613,3 +1151,164 @@ fn test_arr_from_str_with_null_to_vecstr() {
         Error::ParseError(HAS_NULL_ELEMENTS.to_string())
     )
 }
+
+#[test]
+fn test_map_into_query() {
+    let mut map = BTreeMap::new();
+    map.insert("x", "100");
+    map.insert("y", "200");
+    let q = Query::from("mset").arg(&map);
+    assert_eq!(q, Query::from(vec!["mset", "x", "100", "y", "200"]));
+    let mut map = HashMap::new();
+    map.insert("x".to_owned(), "100".to_owned());
+    assert_eq!(
+        Query::from("mset").arg(map),
+        Query::from(vec!["mset", "x", "100"])
+    );
+}
+
+#[test]
+fn test_option_into_query() {
+    let q = Query::from("set").arg("x").arg(Some("100"));
+    assert_eq!(q, Query::from(vec!["set", "x", "100"]));
+    let q = Query::from("set").arg("x").arg(None::<&str>);
+    assert_eq!(q, Query::from(vec!["set", "x"]));
+    let q = Query::from("set").arg("x").arg_or_empty(None::<&str>);
+    assert_eq!(q, Query::from(vec!["set", "x", ""]));
+}
+
+#[test]
+fn test_array_into_elements() {
+    let arr = Array::Flat(vec![
+        FlatElement::UnsignedInt(1),
+        FlatElement::String("sayan".to_owned()),
+    ]);
+    assert_eq!(
+        arr.into_elements().unwrap(),
+        vec![Element::UnsignedInt(1), Element::String("sayan".to_owned())]
+    );
+    let arr = Array::Bin(vec![Some(vec![1]), None]);
+    assert_eq!(
+        arr.into_elements().unwrap_err(),
+        Error::ParseError(HAS_NULL_ELEMENTS.to_owned())
+    );
+}
+
+#[test]
+fn test_array_get() {
+    let arr = Array::Str(vec![Some("a".to_owned()), None]);
+    assert_eq!(arr.get(0), Some(Element::String("a".to_owned())));
+    assert_eq!(arr.get(1), None);
+    assert_eq!(arr.get(2), None);
+    let arr = Array::Flat(vec![FlatElement::UnsignedInt(1)]);
+    assert_eq!(arr.get(0), Some(Element::UnsignedInt(1)));
+    let element = Element::Array(Array::NonNullBin(vec![vec![1], vec![2]]));
+    assert_eq!(element.element_at(1), Some(Element::Binstr(vec![2])));
+    assert_eq!(Element::UnsignedInt(1).element_at(0), None);
+    let elements: Vec<Element> = element.try_element_into().unwrap();
+    assert_eq!(
+        elements,
+        [Element::Binstr(vec![1]), Element::Binstr(vec![2])]
+    );
+    assert!(Element::UnsignedInt(1)
+        .try_element_into::<Vec<Element>>()
+        .is_err());
+}
+
+#[test]
+fn test_element_try_from() {
+    let int: u64 = Element::UnsignedInt(10).try_into().unwrap();
+    assert_eq!(int, 10);
+    let signed: i64 = Element::String("-10".to_owned()).try_into().unwrap();
+    assert_eq!(signed, -10);
+    let float: f64 = Element::Float(1.5).try_into().unwrap();
+    assert_eq!(float, 1.5);
+    let yes: bool = Element::UnsignedInt(1).try_into().unwrap();
+    assert!(yes);
+    let strings: Vec<String> = Element::Array(Array::NonNullStr(vec!["a".to_owned()]))
+        .try_into()
+        .unwrap();
+    assert_eq!(strings, vec!["a".to_owned()]);
+    assert_eq!(
+        String::try_from(Element::RespCode(RespCode::Okay)).unwrap_err(),
+        Error::ParseError(format!(
+            "{}: expected a string, found a response code",
+            BAD_ELEMENT
+        ))
+    );
+    // errors returned by the server aren't mistaken for data
+    assert_eq!(
+        String::try_from(Element::RespCode(RespCode::ErrorString(
+            "err-snapshot-busy".to_owned()
+        )))
+        .unwrap_err(),
+        Error::SkyError(SkyhashError::Code(RespCode::ErrorString(
+            "err-snapshot-busy".to_owned()
+        )))
+    );
+    assert_eq!(
+        u64::try_from(Element::RespCode(RespCode::NotFound)).unwrap_err(),
+        Error::SkyError(SkyhashError::Code(RespCode::NotFound))
+    );
+}
+
+#[test]
+fn test_option_from_element() {
+    let found: Option<String> = Element::String("100".to_owned())
+        .try_element_into()
+        .unwrap();
+    assert_eq!(found, Some("100".to_owned()));
+    let missing: Option<u64> = Element::RespCode(RespCode::NotFound)
+        .try_element_into()
+        .unwrap();
+    assert_eq!(missing, None);
+    // other response codes are still errors
+    assert_eq!(
+        Element::RespCode(RespCode::ServerError)
+            .try_element_into::<Option<u64>>()
+            .unwrap_err(),
+        Error::SkyError(SkyhashError::Code(RespCode::ServerError))
+    );
+}
+
+#[test]
+fn test_version_parse_and_order() {
+    let parse = |st: &str| st.parse::<Version>();
+    assert_eq!(parse("0.7.5").unwrap(), Version::new(0, 7, 5));
+    assert_eq!(parse("0.8.0+abcdef").unwrap(), Version::new(0, 8, 0));
+    assert_eq!(
+        parse("0.8.0-beta.2").unwrap().pre.as_deref(),
+        Some("beta.2")
+    );
+    for invalid in ["", "0.7", "0.7.5.1", "0.7.x", "0.8.0-", "v0.7.5"] {
+        assert!(parse(invalid).is_err(), "{:?} was parsed", invalid);
+    }
+    let mut versions: Vec<Version> = [
+        "0.10.0",
+        "0.8.0",
+        "0.8.0-beta.10",
+        "0.8.0-beta.2",
+        "0.8.0-beta",
+        "0.7.5",
+        "0.8.0-alpha",
+        "0.8.0-alpha.1",
+    ]
+    .iter()
+    .map(|st| parse(st).unwrap())
+    .collect();
+    versions.sort();
+    let versions: Vec<String> = versions.iter().map(Version::to_string).collect();
+    assert_eq!(
+        versions,
+        [
+            "0.7.5",
+            "0.8.0-alpha",
+            "0.8.0-alpha.1",
+            "0.8.0-beta",
+            "0.8.0-beta.2",
+            "0.8.0-beta.10",
+            "0.8.0",
+            "0.10.0"
+        ]
+    );
+}
@@ -20,6 +20,20 @@
 macro_rules! gen_match {
     ($ret:expr, $($($mtch:pat)+ $(if $exp:expr)*, $expect:expr),*) => {
         match $ret {
+            // older servers don't know about newer actions
+            Ok($crate::Element::RespCode($crate::RespCode::ErrorString(ref estr)))
+                if estr == $crate::error::errorstring::ERR_UNKNOWN_ACTION =>
+            {
+                Err($crate::error::SkyhashError::UnknownAction.into())
+            }
+            // the server couldn't parse the query
+            Ok($crate::Element::RespCode($crate::RespCode::PacketError)) => {
+                Err($crate::error::SkyhashError::ProtocolError.into())
+            }
+            // the key holds a value of another type
+            Ok($crate::Element::RespCode($crate::RespCode::Wrongtype)) => {
+                Err($crate::error::SkyhashError::WrongType.into())
+            }
             $($(Ok($mtch))|* $(if $exp:expr)* => Ok($expect),)*
             // IMPORTANT: Translate respcodes into errors!
             Ok($crate::Element::RespCode(rc)) => Err($crate::error::SkyhashError::Code(rc).into()),
@@ -91,6 +105,46 @@ macro_rules! cfg_async {
     };
 }
 
+macro_rules! cfg_sync_unix {
+    ($($body:item)*) => {
+        $(
+            #[cfg(all(feature = "sync", feature = "unix"))]
+            #[cfg_attr(docsrs, doc(cfg(all(feature = "sync", feature = "unix"))))]
+            $body
+        )*
+    };
+}
+
+macro_rules! cfg_async_unix {
+    ($($body:item)*) => {
+        $(
+            #[cfg(all(feature = "aio", feature = "unix"))]
+            #[cfg_attr(docsrs, doc(cfg(all(feature = "aio", feature = "unix"))))]
+            $body
+        )*
+    };
+}
+
+macro_rules! cfg_socks {
+    ($($body:item)*) => {
+        $(
+            #[cfg(feature = "socks")]
+            #[cfg_attr(docsrs, doc(cfg(feature = "socks")))]
+            $body
+        )*
+    };
+}
+
+macro_rules! cfg_serde {
+    ($($body:item)*) => {
+        $(
+            #[cfg(feature = "serde")]
+            #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+            $body
+        )*
+    };
+}
+
 macro_rules! cfg_dbg {
     ($($body:item)*) => {
         $(
@@ -0,0 +1,149 @@
+/*
+ * Copyright 2022, Sayan Nandan <nandansayan@outlook.com>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+
+//! # Integration tests
+//!
+//! These tests run actions against a live server. They are skipped unless `SKYTABLE_TEST_URL`
+//! is set to the URL of the server (in the format accepted by
+//! [`ConnectionBuilder::from_url`]), for example:
+//! ```text
+//! SKYTABLE_TEST_URL=skytable://127.0.0.1:2003 cargo test --test integration
+//! ```
+//! Every test creates its own keyspace (named `clienttests_<test>`) and drops it afterwards, so
+//! the tests can run in parallel and don't touch any other data on the server
+//!
+
+#![cfg(feature = "sync")]
+
+use skytable::actions::Actions;
+use skytable::ddl::{Ddl, Keymap, KeymapType};
+use skytable::{query, Connection, ConnectionBuilder, Element, RespCode};
+use std::env;
+use std::ops::{Deref, DerefMut};
+
+/// The environment variable holding the URL of the server to test against
+const TEST_URL_VAR: &str = "SKYTABLE_TEST_URL";
+
+/// A connection to the test server, that uses a keyspace of its own which is dropped once the
+/// connection is dropped
+struct TestKeyspace {
+    con: Connection,
+    keyspace: String,
+}
+
+impl TestKeyspace {
+    /// Connect to the test server and create the keyspace for `test`, or return `None` if no
+    /// test server is configured
+    fn new(test: &str) -> Option<Self> {
+        let url = match env::var(TEST_URL_VAR) {
+            Ok(url) => url,
+            Err(_) => {
+                eprintln!("skipping {}: {} isn't set", test, TEST_URL_VAR);
+                return None;
+            }
+        };
+        let mut con = ConnectionBuilder::from_url(&url)
+            .unwrap()
+            .set_entity("default:default".to_owned())
+            .get_connection()
+            .unwrap();
+        let keyspace = format!("clienttests_{}", test);
+        // a failed earlier run might have left the keyspace behind
+        let _ = con.drop_keyspace(&keyspace, true);
+        assert!(con.create_keyspace(&keyspace).unwrap());
+        Some(Self { con, keyspace })
+    }
+    /// Create a table with the provided types in the test keyspace and switch to it
+    fn table(&mut self, name: &str, ktype: KeymapType, vtype: KeymapType) {
+        let entity = format!("{}:{}", self.keyspace, name);
+        let table = Keymap::new(&entity).set_ktype(ktype).set_vtype(vtype);
+        self.con.create_table(table).unwrap();
+        self.con.switch(&entity).unwrap();
+    }
+}
+
+impl Deref for TestKeyspace {
+    type Target = Connection;
+    fn deref(&self) -> &Connection {
+        &self.con
+    }
+}
+
+impl DerefMut for TestKeyspace {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.con
+    }
+}
+
+impl Drop for TestKeyspace {
+    fn drop(&mut self) {
+        // a keyspace can't be dropped while it's in use
+        let cleanup = self
+            .con
+            .switch("default:default")
+            .and_then(|_| self.con.drop_keyspace(&self.keyspace, true));
+        if let Err(e) = cleanup {
+            eprintln!("failed to drop keyspace {}: {}", self.keyspace, e);
+        }
+    }
+}
+
+#[test]
+fn test_kv_roundtrip() {
+    let mut con = match TestKeyspace::new("kv") {
+        Some(con) => con,
+        None => return,
+    };
+    con.table("kv", KeymapType::Str, KeymapType::Str);
+    assert!(con.set("x", "100").unwrap());
+    // set doesn't overwrite existing keys
+    assert!(!con.set("x", "200").unwrap());
+    assert_eq!(con.get::<String>("x").unwrap(), "100");
+    assert!(con.set("y", "200").unwrap());
+    let values: Vec<String> = con.mget(vec!["x", "y"]).unwrap();
+    assert_eq!(values, ["100", "200"]);
+    assert_eq!(con.exists(vec!["x", "y", "z"]).unwrap(), 2);
+    assert_eq!(con.del(vec!["x", "z"]).unwrap(), 1);
+    assert_eq!(con.exists("x").unwrap(), 0);
+    assert_eq!(con.dbsize().unwrap(), 1);
+}
+
+#[test]
+fn test_list_roundtrip() {
+    let mut con = match TestKeyspace::new("lists") {
+        Some(con) => con,
+        None => return,
+    };
+    con.table(
+        "lists",
+        KeymapType::Str,
+        KeymapType::Other("list<str>".to_owned()),
+    );
+    assert_eq!(
+        con.run_query_raw(query!("lset", "mylist", "a", "b"))
+            .unwrap(),
+        Element::RespCode(RespCode::Okay)
+    );
+    assert_eq!(
+        con.run_query_raw(query!("lmod", "mylist", "push", "c"))
+            .unwrap(),
+        Element::RespCode(RespCode::Okay)
+    );
+    let list: Vec<String> = con.run_query(query!("lget", "mylist")).unwrap();
+    assert_eq!(list, ["a", "b", "c"]);
+    assert_eq!(con.lget_range("mylist", 1, 5).unwrap(), ["b", "c"]);
+    assert_eq!(con.del("mylist").unwrap(), 1);
+}